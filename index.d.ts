@@ -8,6 +8,8 @@ export enum ProbeType {
 export interface TargetIdentity {
     uniqueId?: string;
     flashSize?: number;
+    // Clock the probe actually chose, may be below the requested speedKhz
+    speedKhz?: number;
 }
 
 export interface ProbeInfo {
@@ -16,6 +18,48 @@ export interface ProbeInfo {
     serialNum?: string;
     probeType: ProbeType
     shortId: number;
+    ready?: boolean;
+}
+
+// 'usb' inspects the CMSIS-DAP interface descriptors, 'udev' waits for the DAPLINK volume (Linux only)
+export interface ReadinessOptions {
+    readinessCheck?: 'usb' | 'udev' | 'none';
+    includeNotReady?: boolean;
+}
+
+// Fields narrow down the probe list, index picks among the matches. Probes are opened by
+// VID, PID and serial, so one without a serial can only be picked while its VID/PID is unique
+export interface ProbeSelector {
+    vid?: number;
+    pid?: number;
+    serialNum?: string;
+    shortId?: number;
+    index?: number;
+}
+
+export type ProbeProtocol = 'swd' | 'jtag';
+
+export interface ProbeFeatures {
+    swo: boolean;
+    targetPower: boolean;
+    targetVoltage: boolean;
+    // Old ST-Link V2 firmware (before J28) can only reach AP 0
+    multiAp: boolean;
+    arm: boolean;
+    riscv: boolean;
+}
+
+export interface ProbeDetails {
+    probe: ProbeInfo;
+    name: string;
+    manufacturer?: string;
+    product?: string;
+    firmwareVersion?: string;
+    hardwareVersion?: string;
+    protocols: ProbeProtocol[];
+    maxSpeedKhz?: number;
+    targetVoltage?: number;
+    features: ProbeFeatures;
 }
 
 export interface Probes {
@@ -23,12 +67,484 @@ export interface Probes {
 }
 
 export enum FirmwareType {
+    AUTO = 'auto',
     BIN = 'bin',
     HEX = 'hex',
-    ELF = 'elf'
+    ELF = 'elf',
+    SREC = 'srec',
+    UF2 = 'uf2',
+    TITXT = 'ti-txt'
+}
+
+export interface FlashRegionReport {
+    start: number;
+    end: number;
+    erasedBytes: number;
+    programmedBytes: number;
+}
+
+export interface FlashPhaseTimes {
+    attachMs: number;
+    loadMs: number;
+    fillMs: number;
+    eraseMs: number;
+    programMs: number;
+    verifyMs: number;
+    totalMs: number;
+}
+
+export interface FlashReport {
+    targetName: string;
+    format: Exclude<FirmwareType, FirmwareType.AUTO>;
+    probe: {
+        name: string;
+        vid: number;
+        pid: number;
+        serialNum?: string;
+    };
+    speedKhz: number;
+    regions: FlashRegionReport[];
+    timing: FlashPhaseTimes;
+    imageSize: number;
+    imageCrc32: number;
+    imageSha256: string;
+    verified: boolean;
+}
+
+// Shared by every operation that opens a probe, the probe default is used when protocol is omitted
+// 'connectUnderReset' holds NRST low while attaching, 'underReset' and 'softwareReset' reset
+// through the debug port after attaching and either halt or let the target run
+export type AttachMode = 'normal' | 'underReset' | 'connectUnderReset' | 'softwareReset';
+
+// JTAG scan chain configuration is not supported, the debug port has to be the only TAP on the chain
+export interface ConnectOptions {
+    protocol?: ProbeProtocol;
+    // Defaults to the mode each step of the operation has always used
+    attachMode?: AttachMode;
+    // Flashing defaults to 1800 kHz, everything else keeps the probe's default clock
+    speedKhz?: number;
+    // Retry at lower clocks when a DP or AP transfer fails while attaching, defaults to true
+    speedFallback?: boolean;
+    // Operations on the same probe run one at a time, 'queue' (default) waits for the probe
+    // to be free and 'failFast' rejects with a busy error instead
+    lockMode?: 'queue' | 'failFast';
+}
+
+export interface FlashOptions extends ConnectOptions {
+    uf2FamilyId?: number;
+    verify?: boolean;
+    // Checked between phases, and between groups of equally sized sectors or every 256 KiB
+    // or so programmed
+    cancelToken?: number;
+    // Defaults to 300000 ms. Like erase and identify, checked between steps and while waiting
+    // for the probe, a probe call that hangs is not interrupted. The probe is free again once
+    // the promise rejects.
+    timeoutMs?: number;
+}
+
+// The image is parsed once and flashed to every probe in parallel
+export interface GangFlashOptions extends FlashOptions {
+    probes: ProbeSelector[];
+    image: string;
+    targetName: string;
+    format?: FirmwareType;
+    skipErase?: boolean;
+}
+
+// bytes and totalBytes count over the whole image for the current phase, index is the position in probes
+export interface GangFlashProgress {
+    index: number;
+    phase: 'attaching' | 'erasing' | 'programming' | 'verifying' | 'done' | 'failed';
+    bytes: number;
+    totalBytes: number;
+    error?: string;
+}
+
+// A failing probe does not abort the others, check ok on every result
+export interface GangFlashResult {
+    index: number;
+    probe: ProbeSelector;
+    ok: boolean;
+    report?: FlashReport;
+    error?: string;
+}
+
+export interface EraseOptions extends ConnectOptions {
+    cancelToken?: number;
+    // Defaults to 60000 ms, checked between steps like FlashOptions.timeoutMs
+    timeoutMs?: number;
+    // 'powerCycle' switches probe supplied target power off and on after erasing (J-Link only)
+    resetStrategy?: 'default' | 'powerCycle';
+    // Defaults to 500 ms
+    powerOffMs?: number;
+}
+
+export interface EraseReport {
+    // Clock the probe actually chose, may be below the requested speedKhz
+    speedKhz?: number;
+    // Option bytes as read before erasing, only from targets with their own eraser (STM32L0)
+    optionBytes?: RegisterValue;
+}
+
+// A session keeps the probe attached and locked until closeSession is called
+export interface SessionOptions extends ConnectOptions {
+    cancelToken?: number;
+    // How long to wait for the probe to become free, defaults to 10000 ms
+    timeoutMs?: number;
+    // Firmware running on the target, lets memory, breakpoint and RTT calls take symbol names
+    elfPath?: string;
+    // CMSIS-SVD file of the target, for readPeripheral and readRegisterField
+    svdPath?: string;
+}
+
+export interface SessionInfo {
+    id: number;
+    targetName: string;
+    probeName: string;
+    speedKhz: number;
+}
+
+// The control block is taken from controlBlockAddress, then the _SEGGER_RTT symbol in elfPath
+// (or the session's elfPath), otherwise the target's RAM (or scanRanges) is searched for it
+export interface RttOptions {
+    controlBlockAddress?: number;
+    elfPath?: string;
+    scanRanges?: { start: number; end: number }[];
+}
+
+export interface RttChannelInfo {
+    index: number;
+    name?: string;
+    size: number;
+    mode: 'noBlockSkip' | 'noBlockTrim' | 'blockIfFull';
+}
+
+export interface RttInfo {
+    controlBlockAddress: number;
+    upChannels: RttChannelInfo[];
+    downChannels: RttChannelInfo[];
+}
+
+export interface RttData {
+    channel: number;
+    data: Buffer;
+}
+
+// Files can only be opened for reading, relative to sandboxDir. Without it file operations fail.
+// SYS_READ and SYS_WRITE move at most 64 KiB per call and report the rest as not transferred.
+export interface SemihostingOptions {
+    sandboxDir?: string;
+    // Reset the target first instead of resuming it
+    reset?: boolean;
+    cancelToken?: number;
+    // Defaults to 60000 ms
+    timeoutMs?: number;
+}
+
+// 'halted' means the core stopped for something other than a semihosting call
+export interface SemihostingResult {
+    reason: 'exit' | 'halted' | 'lockedUp';
+    exitCode?: number;
+    output: string;
+    durationMs: number;
+}
+
+// cpuHz is the clock feeding the TPIU, usually the core clock. Without ports every stimulus port is enabled.
+export interface SwoOptions {
+    baud: number;
+    cpuHz: number;
+    encoding?: 'uart' | 'manchester';
+    ports?: number[];
+    // Also report timestamp and DWT packets
+    rawPackets?: boolean;
+}
+
+export interface SwoInfo {
+    baud: number;
+    cpuHz: number;
+    portMask: number;
 }
 
-export const listAllProbes: () => Probes;
-export const eraseTarget: (targetName: string, vid: number, pid: number, serialNum?: String) => Promise<void>;
-export const identifyTarget: (targetName: string, vid: number, pid: number, serialNum?: String) => Promise<TargetIdentity>;
-export const flashFirmwareFile: (path: string, targetName: string, type: FirmwareType, vid: number, pid: number, skip_erase?: boolean, speed_khz?: number, serialNum?: string) => Promise<void>;
\ No newline at end of file
+// Stimulus port writes are merged per port between polls
+export type SwoEvent =
+    | { kind: 'stimulus'; port: number; text: string; data: Buffer }
+    | { kind: 'instrumentation'; port: number; value: number; size: number }
+    | { kind: 'hardware'; discriminator: number; value: number; size: number }
+    | { kind: 'timestamp'; delta: number }
+    | { kind: 'globalTimestamp'; value: number }
+    | { kind: 'overflow' };
+
+// Listens on 127.0.0.1:3333 unless told otherwise, port 0 picks a free one
+export interface GdbServerOptions {
+    port?: number;
+    host?: string;
+}
+
+export interface GdbServerInfo {
+    address: string;
+    port: number;
+}
+
+// fpscr and s0-s31 are only present on cores with an FPU
+export interface CoreRegisters {
+    r0: number; r1: number; r2: number; r3: number; r4: number; r5: number; r6: number;
+    r7: number; r8: number; r9: number; r10: number; r11: number; r12: number;
+    sp: number;
+    lr: number;
+    pc: number;
+    xpsr: number;
+    msp: number;
+    psp: number;
+    primask: number;
+    basepri: number;
+    faultmask: number;
+    control: number;
+    fpscr?: number;
+    [fpRegister: string]: number | undefined;
+}
+
+export interface FaultOptions {
+    // Firmware running on the target, used to name the functions PC and LR are in.
+    // Defaults to the session's elfPath
+    elfPath?: string;
+}
+
+export interface CodeLocation {
+    address: number;
+    symbol?: string;
+    offset?: number;
+    // Needs DWARF line info in the ELF file
+    source?: SourceLocation;
+}
+
+export interface FaultReport {
+    // IPSR, 0 in thread mode, 3 for HardFault
+    exceptionNumber: number;
+    exception?: string;
+    lockedUp: boolean;
+    // Missing on Cortex-M0/M0+, which have no fault status registers
+    status?: { cfsr: number; hfsr: number; dfsr: number; mmfar: number; bfar: number; afsr: number };
+    flags: { register: 'CFSR' | 'HFSR'; name: string; description: string }[];
+    faultAddress?: number;
+    // Missing when the handler has overwritten LR or stacking failed (STKERR, MSTKERR),
+    // pc and lr are then the live registers
+    frame?: {
+        stack: 'msp' | 'psp';
+        address: number;
+        r0: number; r1: number; r2: number; r3: number; r12: number;
+        lr: number;
+        pc: number;
+        xpsr: number;
+        fpContext: boolean;
+        // Bytes pushed on entry, 0x20 or 0x68 with an FP context, plus 4 if the core realigned the stack
+        size: number;
+    };
+    pc: CodeLocation;
+    lr: CodeLocation;
+    // e.g. "precise data bus error at 0x40021000, escalated to HardFault, PC 0x08000412 (main+0x2a) in src/main.c:123"
+    summary: string;
+}
+
+// Symbols are looked up in elfPath, or the session's elfPath, function addresses come back
+// without the Thumb bit
+export interface LocationOptions {
+    elfPath?: string;
+}
+
+export interface WatchpointOptions extends LocationOptions {
+    // Defaults to 'write'
+    access?: 'read' | 'write' | 'readWrite';
+}
+
+export interface Watchpoint {
+    address: number;
+    size: number;
+    access: 'read' | 'write' | 'readWrite';
+    comparator: number;
+}
+
+export interface HaltInfo {
+    reason: 'breakpoint' | 'watchpoint' | 'step' | 'request' | 'external' | 'exception' | 'multiple' | 'lockedUp' | 'unknown';
+    pc: number;
+    breakpoint?: number;
+    watchpoint?: Watchpoint;
+}
+
+export interface WaitForHaltOptions {
+    cancelToken?: number;
+    // Defaults to 10000 ms
+    timeoutMs?: number;
+}
+
+// J-Link probes supply a fixed 5 V, other voltages are rejected
+export interface TargetPowerOptions {
+    voltage?: number;
+}
+
+export interface IdentifyOptions extends ConnectOptions {
+    cancelToken?: number;
+    // Defaults to 3000 ms, checked between steps like FlashOptions.timeoutMs
+    timeoutMs?: number;
+}
+
+export interface ProbeEvent {
+    event: 'attached' | 'detached' | 'ready';
+    probe: ProbeInfo;
+}
+
+export interface SourceLocation {
+    file: string;
+    line: number;
+    column?: number;
+}
+
+export interface SymbolInfo {
+    name: string;
+    // Thumb bit cleared for functions
+    address: number;
+    size: number;
+    kind: 'function' | 'object' | 'other';
+    section?: string;
+    // How far into the symbol a looked up address is, 0 for names
+    offset: number;
+    location?: SourceLocation;
+}
+
+export interface CoreDumpOptions {
+    // Where to write the ELF core file, load it with: gdb firmware.elf -core <path>
+    path: string;
+    // Peripheral registers to dump next to RAM, reading some of them has side effects
+    peripheralRanges?: { start: number; end: number }[];
+    cancelToken?: number;
+}
+
+// bytes and totalBytes count over all regions, region is the index in CoreDumpInfo.regions
+export interface CoreDumpProgress {
+    region: number;
+    address: number;
+    bytes: number;
+    totalBytes: number;
+}
+
+export interface CoreDumpInfo {
+    path: string;
+    regions: { kind: 'ram' | 'peripheral'; start: number; size: number }[];
+    fileSize: number;
+    // MSP, PSP and the other system registers are only here, GDB sees r0-r15, xPSR and the FPU
+    registers: CoreRegisters;
+}
+
+export interface SvdOptions {
+    // Overrides the session's svdPath
+    svdPath?: string;
+    // readRegisterField refuses registers whose readAction clears or changes something unless
+    // this is true. readPeripheral always leaves them out.
+    allowReadSideEffects?: boolean;
+}
+
+export interface FieldValue {
+    name: string;
+    description?: string;
+    bitOffset: number;
+    bitWidth: number;
+    value: number;
+    // The enumerated value from the SVD file that matches, if the field has any
+    enumerated?: { name: string; description?: string };
+}
+
+export interface RegisterValue {
+    // Registers in clusters are named CLUSTER.REGISTER
+    name: string;
+    description?: string;
+    address: number;
+    // Missing for write-only registers and ones that change when read, e.g. with a readAction
+    value?: number;
+    fields: FieldValue[];
+}
+
+export interface PeripheralValue {
+    name: string;
+    description?: string;
+    baseAddress: number;
+    registers: RegisterValue[];
+}
+
+export interface RegisterFieldValue extends FieldValue {
+    register: string;
+    address: number;
+    registerValue: number;
+}
+
+export const listAllProbes: (options?: ReadinessOptions) => Probes;
+// getProbeDetails, setTargetPower and readTargetVoltage wait at most 5 s for another operation
+// using the probe, then reject with a timeout error
+export const getProbeDetails: (vid: number | ProbeSelector, pid?: number, serialNum?: string) => Promise<ProbeDetails>;
+// Only J-Link probes switch target power. CMSIS-DAP has no standard command for it, so DAPLink,
+// ST-Link and other probes reject with an unsupported error
+export const setTargetPower: (on: boolean, vid: number | ProbeSelector, pid?: number, serialNum?: string, options?: TargetPowerOptions) => Promise<void>;
+// Volts measured on the probe's reference pin, supported on ST-Link and J-Link probes
+export const readTargetVoltage: (vid: number | ProbeSelector, pid?: number, serialNum?: string) => Promise<number | null>;
+export interface ProbeWatcher {
+    id: number;
+    // Stops the watcher, false if it was already stopped
+    unwatch(): boolean;
+}
+// Already connected probes are reported on start, probes in use by another operation stay pending until it finishes
+export const watchProbes: (callback: (err: Error | null, event: ProbeEvent) => void, options?: ReadinessOptions) => ProbeWatcher;
+// Same as watcher.unwatch(), also accepts the watcher id
+export const unwatchProbes: (watcher: ProbeWatcher | number) => boolean;
+export const eraseTarget: (targetName: string, vid: number | ProbeSelector, pid?: number, serialNum?: String, options?: EraseOptions) => Promise<EraseReport>;
+export const identifyTarget: (targetName: string, vid: number | ProbeSelector, pid?: number, serialNum?: String, options?: IdentifyOptions) => Promise<TargetIdentity>;
+export const flashFirmwareFile: (path: string, targetName: string, type: FirmwareType | undefined, vid: number | ProbeSelector, pid?: number, skip_erase?: boolean, speed_khz?: number, serialNum?: string, options?: FlashOptions) => Promise<FlashReport>;
+export const flashMany: (options: GangFlashOptions, onProgress?: (err: Error | null, event: GangFlashProgress) => void) => Promise<GangFlashResult[]>;
+export const openSession: (targetName: string, vid: number | ProbeSelector, pid?: number, serialNum?: string, options?: SessionOptions) => Promise<SessionInfo>;
+export const closeSession: (sessionId: number) => Promise<boolean>;
+// Up channel data is polled in the background and handed to the callback until stopRtt or closeSession
+export const startRtt: (sessionId: number, options: RttOptions | undefined, callback: (err: Error | null, data: RttData) => void) => Promise<RttInfo>;
+// Resolves with the number of bytes that fitted in the down channel
+export const rttWrite: (sessionId: number, channel: number, data: string | Buffer) => Promise<number>;
+export const stopRtt: (sessionId: number) => Promise<boolean>;
+// Resumes the core and services BKPT 0xAB calls until the target exits or halts otherwise
+export const runSemihosting: (sessionId: number, options?: SemihostingOptions, onOutput?: (err: Error | null, text: string) => void) => Promise<SemihostingResult>;
+// Configures TPIU, ITM and DWT on the target and streams decoded ITM packets until stopSwo or closeSession
+export const startSwo: (sessionId: number, options: SwoOptions, callback: (err: Error | null, event: SwoEvent) => void) => Promise<SwoInfo>;
+export const stopSwo: (sessionId: number) => Promise<boolean>;
+// Serves the GDB remote protocol on top of the session. While a client is connected,
+// other operations on the probe reject with 'Probe ... has a debugger attached' instead of queueing.
+export const startGdbServer: (sessionId: number, options?: GdbServerOptions) => Promise<GdbServerInfo>;
+export const stopGdbServer: (sessionId: number) => Promise<boolean>;
+// The core has to be halted first, e.g. by a breakpoint or the GDB server
+export const readRegisters: (sessionId: number) => Promise<CoreRegisters>;
+// Names as in CoreRegisters, r13-r15 are accepted for sp, lr and pc
+export const writeRegister: (sessionId: number, name: string, value: number) => Promise<void>;
+// Halts the core and decodes why it is sitting in a fault handler
+export const analyzeFault: (sessionId: number, options?: FaultOptions) => Promise<FaultReport>;
+// Hardware breakpoints through the FPB, resolves with the address used
+export const setBreakpoint: (sessionId: number, location: number | string, options?: LocationOptions) => Promise<number>;
+// Without a location every breakpoint set by setBreakpoint is cleared, resolves with how many were
+export const clearBreakpoint: (sessionId: number, location?: number | string, options?: LocationOptions) => Promise<number>;
+// DWT comparators, size must be a power of two the address is aligned to (at most 4 on ARMv8-M)
+export const setWatchpoint: (sessionId: number, location: number | string, size: number, options?: WatchpointOptions) => Promise<Watchpoint>;
+export const clearWatchpoint: (sessionId: number, location?: number | string, options?: LocationOptions) => Promise<number>;
+export const haltCore: (sessionId: number) => Promise<HaltInfo>;
+// Steps off a breakpoint the core is sitting on before letting it run
+export const resumeCore: (sessionId: number) => Promise<void>;
+// Rejects with a 'Timeout' error if the core is still running when timeoutMs runs out. A plain
+// number is taken as timeoutMs.
+export const waitForHalt: (sessionId: number, options?: WaitForHaltOptions | number) => Promise<HaltInfo>;
+export const readMemory: (sessionId: number, location: number | string, length: number, options?: LocationOptions) => Promise<Buffer>;
+// Resolves with the number of bytes written, flash is not erased or programmed
+export const writeMemory: (sessionId: number, location: number | string, data: Buffer, options?: LocationOptions) => Promise<number>;
+// Looks up a symbol name, or the function or object an address falls in. Resolves with null if
+// there is none
+export const lookupSymbol: (elfPath: string, nameOrAddress: number | string) => Promise<SymbolInfo | null>;
+// Halts the core and leaves it halted once the dump is written
+export const captureCoreDump: (sessionId: number, options: CoreDumpOptions, onProgress?: (err: Error | null, event: CoreDumpProgress) => void) => Promise<CoreDumpInfo>;
+// Peripheral, register and field names are matched case-insensitively
+export const readPeripheral: (sessionId: number, name: string, options?: SvdOptions) => Promise<PeripheralValue>;
+// path is PERIPHERAL.REGISTER.FIELD, e.g. "FLASH.OPTR.RDPROT"
+export const readRegisterField: (sessionId: number, path: string, options?: SvdOptions) => Promise<RegisterFieldValue>;
+// Operations given a cancelled token reject with an error whose code is 'CANCELLED'
+export const createCancelToken: () => number;
+export const cancelOperation: (token: number) => boolean;
+export const releaseCancelToken: (token: number) => boolean;
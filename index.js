@@ -8,4 +8,31 @@ const { loadBinding } = require('@node-rs/helper')
  * `loadBinding` helper will load `plunger-binding.[PLATFORM].node` from `__dirname` first
  * If failed to load addon, it will fallback to load from `plunger-binding-[PLATFORM]`
  */
-module.exports = loadBinding(__dirname, 'plunger-binding', 'plunger-binding')
+const binding = loadBinding(__dirname, 'plunger-binding', 'plunger-binding')
+
+/**
+ * napi-rs names error codes after its status values, so a cancelled operation
+ * rejects with code 'Cancelled'. Report it as 'CANCELLED' instead.
+ */
+const renameCancelled = (err) => {
+  if (err && err.code === 'Cancelled') {
+    err.code = 'CANCELLED'
+  }
+  throw err
+}
+
+const wrap = (func) =>
+  function (...args) {
+    let result
+    try {
+      result = func.apply(this, args)
+    } catch (err) {
+      renameCancelled(err)
+    }
+    return result && typeof result.then === 'function' ? result.catch(renameCancelled) : result
+  }
+
+for (const name of Object.keys(binding)) {
+  const value = binding[name]
+  module.exports[name] = typeof value === 'function' ? wrap(value) : value
+}
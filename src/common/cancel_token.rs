@@ -0,0 +1,182 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use lazy_static::lazy_static;
+use napi::{CallContext, JsBoolean, JsNumber};
+
+use super::plunger_error::PlungerError;
+
+lazy_static! {
+    static ref CANCEL_TOKENS: Mutex<HashMap<u32, CancelToken>> = Mutex::new(HashMap::new());
+}
+
+static NEXT_TOKEN_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Shared flag checked by long running tasks between phases and in busy-waits,
+/// optionally carrying the deadline of the operation it was handed to
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<(Instant, Duration)>,
+}
+
+impl CancelToken {
+    /// Looks up a token created by `createCancelToken`, or returns one that never cancels
+    pub fn from_id(id: Option<u32>) -> Result<CancelToken, PlungerError> {
+        let id = match id {
+            Some(id) => id,
+            None => return Ok(CancelToken::default()),
+        };
+
+        let tokens = CANCEL_TOKENS.lock().map_err(|err| {
+            PlungerError::StateError(format!("Cannot acquire token lock: {}", err))
+        })?;
+
+        tokens
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| PlungerError::StateError(format!("Unknown cancel token {}", id)))
+    }
+
+    /// Starts the operation clock, the deadline only applies to this copy of the token
+    pub fn with_timeout(mut self, timeout: Duration) -> CancelToken {
+        self.deadline = Some((Instant::now() + timeout, timeout));
+        self
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub fn check(&self) -> Result<(), PlungerError> {
+        if self.is_cancelled() {
+            return Err(PlungerError::Cancelled);
+        }
+
+        match self.deadline {
+            Some((deadline, timeout)) if Instant::now() >= deadline => {
+                Err(PlungerError::Timeout(timeout.as_millis() as u64))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn lock_error<T>(err: T) -> napi::Error
+where
+    T: std::fmt::Display,
+{
+    napi::Error {
+        status: napi::Status::Unknown,
+        reason: format!("Cannot acquire token lock: {}", err),
+    }
+}
+
+#[js_function]
+pub fn create_cancel_token(ctx: CallContext) -> napi::Result<JsNumber> {
+    let id = NEXT_TOKEN_ID.fetch_add(1, Ordering::SeqCst);
+    CANCEL_TOKENS
+        .lock()
+        .map_err(lock_error)?
+        .insert(id, CancelToken::default());
+
+    ctx.env.create_uint32(id)
+}
+
+#[js_function(1)]
+pub fn cancel_operation(ctx: CallContext) -> napi::Result<JsBoolean> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let found = match CANCEL_TOKENS.lock().map_err(lock_error)?.get(&id) {
+        Some(token) => {
+            token.cancel();
+            true
+        }
+        None => false,
+    };
+
+    ctx.env.get_boolean(found)
+}
+
+#[js_function(1)]
+pub fn release_cancel_token(ctx: CallContext) -> napi::Result<JsBoolean> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let removed = CANCEL_TOKENS.lock().map_err(lock_error)?.remove(&id);
+
+    ctx.env.get_boolean(removed.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use probe_rs::DebugProbeSelector;
+
+    use super::*;
+    use crate::common::probe_lock::{LockMode, ProbeLock};
+
+    fn selector(serial: &str) -> DebugProbeSelector {
+        DebugProbeSelector {
+            vendor_id: 0xfff0,
+            product_id: 0x0030,
+            serial_number: Some(serial.to_string()),
+        }
+    }
+
+    // What every task does: take the probe, then give up at the next checkpoint past the deadline
+    fn run_until_timeout(selector: &DebugProbeSelector, cancel: &CancelToken) -> PlungerError {
+        let _lock = ProbeLock::acquire(selector, LockMode::Queue, cancel).unwrap();
+        loop {
+            if let Err(err) = cancel.check() {
+                return err;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn times_out_at_the_next_check() {
+        let cancel = CancelToken::default().with_timeout(Duration::from_millis(20));
+        assert!(cancel.check().is_ok());
+        thread::sleep(Duration::from_millis(30));
+        assert!(matches!(cancel.check(), Err(PlungerError::Timeout(20))));
+
+        let cancel = CancelToken::default().with_timeout(Duration::from_secs(60));
+        cancel.cancel();
+        assert!(matches!(cancel.check(), Err(PlungerError::Cancelled)));
+    }
+
+    #[test]
+    fn releases_the_probe_after_a_timeout() {
+        let selector = selector("timeout-release");
+        let cancel = CancelToken::default().with_timeout(Duration::from_millis(20));
+
+        assert!(matches!(
+            run_until_timeout(&selector, &cancel),
+            PlungerError::Timeout(_)
+        ));
+        assert!(ProbeLock::acquire(&selector, LockMode::FailFast, &CancelToken::default()).is_ok());
+    }
+
+    #[test]
+    fn times_out_while_queued_for_a_busy_probe() {
+        let selector = selector("timeout-queued");
+        let _held =
+            ProbeLock::acquire(&selector, LockMode::FailFast, &CancelToken::default()).unwrap();
+
+        let cancel = CancelToken::default().with_timeout(Duration::from_millis(20));
+        assert!(matches!(
+            ProbeLock::acquire(&selector, LockMode::Queue, &cancel),
+            Err(PlungerError::Timeout(_))
+        ));
+    }
+}
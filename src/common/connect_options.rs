@@ -0,0 +1,283 @@
+use std::time::Duration;
+
+use napi::{CallContext, JsObject};
+use probe_rs::{
+    architecture::arm::{ap::AccessPortError, dp::DebugPortError, DapError},
+    config::TargetSelector,
+    DebugProbeError, DebugProbeSelector, Probe, Session,
+};
+use serde::{de::IgnoredAny, Deserialize};
+
+use super::{
+    cancel_token::CancelToken,
+    plunger_error::PlungerError,
+    probe_info::ProbeProtocol,
+    probe_lock::{LockMode, ProbeLock},
+};
+
+const RESET_HALT_TIMEOUT: Duration = Duration::from_millis(500);
+// Steps tried after a failed attach, only the ones below the last speed the probe chose
+const FALLBACK_SPEEDS_KHZ: [u32; 7] = [4000, 1800, 1000, 480, 200, 100, 50];
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum AttachMode {
+    /// Attach to the running target
+    Normal,
+    /// Attach, then reset through the debug port and halt at the reset vector
+    UnderReset,
+    /// Hold NRST low while attaching, needs the reset pin wired to the probe
+    ConnectUnderReset,
+    /// Attach, then reset through the debug port and let the target run
+    SoftwareReset,
+}
+
+/// Probe and wire settings shared by every operation that opens a probe,
+/// read from the same options object as the operation specific settings
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectOptions {
+    pub protocol: Option<ProbeProtocol>,
+    /// Only read to reject it, probe-rs drives the debug port as the only TAP on the chain
+    scan_chain: Option<IgnoredAny>,
+    pub attach_mode: Option<AttachMode>,
+    pub speed_khz: Option<u32>,
+    pub speed_fallback: Option<bool>,
+    pub lock_mode: Option<LockMode>,
+}
+
+/// An attached session and the settings the probe ended up with
+pub struct Connection {
+    pub session: Session,
+    pub speed_khz: u32,
+    pub probe_name: String,
+}
+
+// Errors that a slower clock may get rid of, e.g. long cables or weak pull-ups.
+// USB and probe level failures are not, retrying them only hides a broken probe.
+fn is_comms_error(err: &PlungerError) -> bool {
+    match err {
+        PlungerError::ProbeRsSessionError(probe_rs::Error::ArchitectureSpecific(err))
+        | PlungerError::ProbeRsSessionError(probe_rs::Error::Probe(
+            DebugProbeError::ArchitectureSpecific(err),
+        ))
+        | PlungerError::ProbeRsCommError(DebugProbeError::ArchitectureSpecific(err)) => {
+            is_transfer_error(err.as_ref())
+        }
+        _ => false,
+    }
+}
+
+// Architecture errors also carry unsupported features and bad arguments, only a failed
+// DP or AP transfer is worth retrying
+fn is_transfer_error(err: &(dyn std::error::Error + 'static)) -> bool {
+    if err.downcast_ref::<DapError>().is_some() {
+        return true;
+    }
+    if let Some(DebugProbeError::ArchitectureSpecific(err)) = err.downcast_ref::<DebugProbeError>()
+    {
+        return is_transfer_error(err.as_ref());
+    }
+    if let Some(DebugPortError::DebugProbe(err)) = err.downcast_ref::<DebugPortError>() {
+        return is_failed_access(err);
+    }
+
+    match err.downcast_ref::<AccessPortError>() {
+        Some(AccessPortError::RegisterReadError { source, .. })
+        | Some(AccessPortError::RegisterWriteError { source, .. }) => {
+            is_transfer_error(source.as_ref())
+        }
+        Some(AccessPortError::DebugPort(DebugPortError::DebugProbe(err)))
+        | Some(AccessPortError::FlushError(err)) => is_failed_access(err),
+        _ => false,
+    }
+}
+
+// A probe error raised by a DP or AP access, a timeout there is the target not answering
+fn is_failed_access(err: &DebugProbeError) -> bool {
+    match err {
+        DebugProbeError::Timeout => true,
+        DebugProbeError::ArchitectureSpecific(err) => is_transfer_error(err.as_ref()),
+        _ => false,
+    }
+}
+
+impl ConnectOptions {
+    pub fn from_arg(ctx: &CallContext, idx: usize) -> napi::Result<ConnectOptions> {
+        let options: ConnectOptions = match ctx.try_get::<JsObject>(idx)? {
+            napi::Either::A(obj) => ctx.env.from_js_value(obj)?,
+            napi::Either::B(_) => ConnectOptions::default(),
+        };
+
+        options.validate()?;
+        Ok(options)
+    }
+
+    pub fn validate(&self) -> Result<(), PlungerError> {
+        if self.scan_chain.is_some() {
+            return Err(PlungerError::Unsupported(
+                "JTAG scan chain configuration".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Takes the probe for the whole operation, which may open it several times
+    pub fn lock(
+        &self,
+        selector: &DebugProbeSelector,
+        cancel: &CancelToken,
+    ) -> Result<ProbeLock, PlungerError> {
+        ProbeLock::acquire(selector, self.lock_mode.unwrap_or_default(), cancel)
+    }
+
+    /// Opens the probe and selects the wire protocol before anything attaches
+    fn open_probe(
+        &self,
+        selector: impl Into<DebugProbeSelector> + Clone,
+    ) -> Result<Probe, PlungerError> {
+        let mut probe = Probe::open(selector)?;
+
+        if let Some(protocol) = self.protocol {
+            probe.select_protocol(protocol.into())?;
+        }
+
+        Ok(probe)
+    }
+
+    /// Opens the probe, sets the clock and attaches, stepping the clock down on comms errors
+    /// unless `speedFallback` is turned off
+    pub fn connect(
+        &self,
+        selector: impl Into<DebugProbeSelector> + Clone,
+        target: impl Into<TargetSelector> + Clone,
+        default_mode: AttachMode,
+    ) -> Result<Connection, PlungerError> {
+        let mut speed_khz = self.speed_khz;
+
+        loop {
+            let mut probe = self.open_probe(selector.clone())?;
+            probe.detach()?;
+
+            let actual_khz = match speed_khz {
+                Some(speed_khz) => probe.set_speed(speed_khz)?,
+                None => probe.speed_khz(),
+            };
+            let probe_name = probe.get_name();
+
+            let err = match self.attach(probe, target.clone(), default_mode) {
+                Ok(session) => {
+                    return Ok(Connection {
+                        session,
+                        speed_khz: actual_khz,
+                        probe_name,
+                    })
+                }
+                Err(err) => err,
+            };
+
+            let next = FALLBACK_SPEEDS_KHZ
+                .iter()
+                .copied()
+                .find(|speed| *speed < actual_khz);
+
+            match next {
+                Some(next) if self.speed_fallback.unwrap_or(true) && is_comms_error(&err) => {
+                    speed_khz = Some(next)
+                }
+                _ => return Err(err),
+            }
+        }
+    }
+
+    /// Attaches with the caller's `attachMode`, or the mode the operation used to hard-code
+    fn attach(
+        &self,
+        probe: Probe,
+        target: impl Into<TargetSelector>,
+        default_mode: AttachMode,
+    ) -> Result<Session, PlungerError> {
+        let mode = self.attach_mode.unwrap_or(default_mode);
+        if mode == AttachMode::ConnectUnderReset {
+            return Ok(probe.attach_under_reset(target)?);
+        }
+
+        let mut session = probe.attach(target)?;
+        {
+            let mut core = session.core(0)?;
+            match mode {
+                AttachMode::UnderReset => {
+                    core.reset_and_halt(RESET_HALT_TIMEOUT)?;
+                }
+                AttachMode::SoftwareReset => core.reset()?,
+                AttachMode::Normal | AttachMode::ConnectUnderReset => (),
+            }
+        }
+
+        Ok(session)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arch_error(err: impl std::error::Error + Send + Sync + 'static) -> PlungerError {
+        PlungerError::ProbeRsSessionError(probe_rs::Error::ArchitectureSpecific(Box::new(err)))
+    }
+
+    #[test]
+    fn retries_failed_transfers() {
+        assert!(is_comms_error(&arch_error(DapError::WaitResponse)));
+        assert!(is_comms_error(&arch_error(DebugPortError::DebugProbe(
+            DebugProbeError::Timeout
+        ))));
+        assert!(is_comms_error(&arch_error(
+            AccessPortError::RegisterReadError {
+                address: 0,
+                name: "CSW",
+                source: Box::new(DapError::NoAcknowledge),
+            }
+        )));
+        assert!(is_comms_error(&PlungerError::ProbeRsCommError(
+            DapError::FaultResponse.into()
+        )));
+    }
+
+    #[test]
+    fn does_not_retry_other_architecture_errors() {
+        assert!(!is_comms_error(&arch_error(
+            DebugProbeError::CommandNotSupportedByProbe
+        )));
+        assert!(!is_comms_error(&arch_error(
+            AccessPortError::OutOfBoundsError
+        )));
+        assert!(!is_comms_error(&PlungerError::ProbeRsCommError(
+            DebugProbeError::ArchitectureSpecific(Box::new(AccessPortError::MemoryNotAligned {
+                address: 0x2000_0001,
+                alignment: 4,
+            }))
+        )));
+    }
+
+    #[test]
+    fn does_not_retry_probe_errors() {
+        for err in [
+            DebugProbeError::Usb(None),
+            DebugProbeError::ProbeSpecific(Box::new(DapError::WaitResponse)),
+            DebugProbeError::Timeout,
+            DebugProbeError::TargetNotFound,
+        ] {
+            assert!(!is_comms_error(&PlungerError::ProbeRsSessionError(
+                probe_rs::Error::Probe(err)
+            )));
+        }
+        assert!(!is_comms_error(&PlungerError::ProbeRsCommError(
+            DebugProbeError::Usb(None)
+        )));
+        assert!(!is_comms_error(&arch_error(DebugPortError::DebugProbe(
+            DebugProbeError::Usb(None)
+        ))));
+    }
+}
@@ -1,2 +1,7 @@
+pub mod cancel_token;
+pub mod connect_options;
 pub mod plunger_error;
 pub mod probe_info;
+pub mod probe_lock;
+pub mod probe_selector;
+pub mod sha256;
@@ -12,8 +12,40 @@ pub enum PlungerError {
     ProbeFlashingError(#[from] probe_rs::flashing::FlashError),
     #[error(transparent)]
     ProbeRsCommError(#[from] probe_rs::DebugProbeError),
+    #[error(transparent)]
+    ProbeFileDownloadError(#[from] probe_rs::flashing::FileDownloadError),
+    #[error(transparent)]
+    UsbError(#[from] rusb::Error),
+    #[error(transparent)]
+    HidError(#[from] hidapi::HidError),
+    #[error(transparent)]
+    JLinkError(#[from] jaylink::Error),
+    #[error("Invalid option: {0}")]
+    InvalidOption(String),
+    #[error("Not supported: {0}")]
+    Unsupported(String),
     #[error("Invalid state: {0}")]
     StateError(String),
+    #[error("Invalid firmware: {0}")]
+    InvalidFirmware(String),
+    #[error("Operation cancelled")]
+    Cancelled,
+    #[error("Operation timed out after {0} ms")]
+    Timeout(u64),
+    #[error("No probe matches the selector: {0}")]
+    ProbeNotFound(String),
+    #[error("Selector matches {0} probes, narrow it down with serialNum, shortId or index")]
+    AmbiguousProbe(usize),
+    #[error("Probe {0} is in use by another operation")]
+    ProbeBusy(String),
+    #[error("No debug session with id {0}")]
+    SessionNotFound(u32),
+    #[error("RTT: {0}")]
+    RttError(String),
+    #[error("Probe {0} has a debugger attached")]
+    DebuggerAttached(String),
+    #[error("Invalid SVD file: {0}")]
+    InvalidSvd(String),
 }
 
 impl From<PlungerError> for napi::Error {
@@ -24,8 +56,24 @@ impl From<PlungerError> for napi::Error {
                 PlungerError::InvalidProtectionLevel => napi::Status::GenericFailure,
                 PlungerError::ProbeRsSessionError(_) => napi::Status::GenericFailure,
                 PlungerError::ProbeRsCommError(_) => napi::Status::GenericFailure,
+                PlungerError::UsbError(_) => napi::Status::GenericFailure,
+                PlungerError::HidError(_) => napi::Status::GenericFailure,
+                PlungerError::JLinkError(_) => napi::Status::GenericFailure,
+                PlungerError::InvalidOption(_) => napi::Status::InvalidArg,
+                PlungerError::Unsupported(_) => napi::Status::GenericFailure,
                 PlungerError::StateError(_) => napi::Status::Unknown,
                 PlungerError::ProbeFlashingError(_) => napi::Status::GenericFailure,
+                PlungerError::ProbeFileDownloadError(_) => napi::Status::GenericFailure,
+                PlungerError::InvalidFirmware(_) => napi::Status::InvalidArg,
+                PlungerError::Cancelled => napi::Status::Cancelled,
+                PlungerError::Timeout(_) => napi::Status::GenericFailure,
+                PlungerError::ProbeNotFound(_) => napi::Status::InvalidArg,
+                PlungerError::AmbiguousProbe(_) => napi::Status::InvalidArg,
+                PlungerError::ProbeBusy(_) => napi::Status::GenericFailure,
+                PlungerError::SessionNotFound(_) => napi::Status::InvalidArg,
+                PlungerError::RttError(_) => napi::Status::GenericFailure,
+                PlungerError::DebuggerAttached(_) => napi::Status::GenericFailure,
+                PlungerError::InvalidSvd(_) => napi::Status::InvalidArg,
             },
             reason: err.to_string(),
         }
@@ -1,4 +1,4 @@
-use probe_rs::DebugProbeSelector;
+use probe_rs::{DebugProbeSelector, WireProtocol};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Debug, Deserialize, Clone)]
@@ -9,6 +9,7 @@ pub struct ProbeInfo {
     pub serial_num: Option<String>,
     pub probe_type: Option<ProbeType>,
     pub short_id: Option<u32>,
+    pub ready: Option<bool>,
 }
 
 impl From<ProbeInfo> for DebugProbeSelector {
@@ -42,3 +43,20 @@ pub enum ProbeType {
     #[serde(rename = "JLink")]
     JLink,
 }
+
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum ProbeProtocol {
+    #[serde(rename = "swd")]
+    Swd,
+    #[serde(rename = "jtag")]
+    Jtag,
+}
+
+impl From<ProbeProtocol> for WireProtocol {
+    fn from(protocol: ProbeProtocol) -> Self {
+        match protocol {
+            ProbeProtocol::Swd => WireProtocol::Swd,
+            ProbeProtocol::Jtag => WireProtocol::Jtag,
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use std::{
+    sync::{Condvar, Mutex},
+    time::Duration,
+};
+
+use lazy_static::lazy_static;
+use probe_rs::DebugProbeSelector;
+use serde::Deserialize;
+
+use super::{cancel_token::CancelToken, plunger_error::PlungerError};
+
+// How often a queued operation wakes up to check its cancel token
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+lazy_static! {
+    static ref LOCKED_PROBES: Mutex<Vec<LockedProbe>> = Mutex::new(Vec::new());
+    static ref PROBE_RELEASED: Condvar = Condvar::new();
+}
+
+/// What an operation does when another one is already using its probe
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum LockMode {
+    /// Wait for the other operation to finish, still honouring cancel tokens and timeouts
+    #[default]
+    Queue,
+    /// Reject straight away with a busy error
+    FailFast,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ProbeKey {
+    vid: u16,
+    pid: u16,
+    serial_num: Option<String>,
+}
+
+impl ProbeKey {
+    // Without a serial number probe-rs opens the first probe with that VID/PID,
+    // which could be the same one another operation asked for by serial
+    fn conflicts(&self, other: &ProbeKey) -> bool {
+        self.vid == other.vid
+            && self.pid == other.pid
+            && match (&self.serial_num, &other.serial_num) {
+                (Some(a), Some(b)) => a == b,
+                _ => true,
+            }
+    }
+}
+
+impl std::fmt::Display for ProbeKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04x}:{:04x}", self.vid, self.pid)?;
+        match &self.serial_num {
+            Some(sn) => write!(f, ":{}", sn),
+            None => Ok(()),
+        }
+    }
+}
+
+impl From<&DebugProbeSelector> for ProbeKey {
+    fn from(selector: &DebugProbeSelector) -> Self {
+        ProbeKey {
+            vid: selector.vendor_id,
+            pid: selector.product_id,
+            serial_num: selector.serial_number.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct LockedProbe {
+    key: ProbeKey,
+    // Set while a GDB client is connected, nothing should queue behind an interactive debugger
+    debugger_attached: bool,
+}
+
+fn lock_error<T>(err: T) -> PlungerError
+where
+    T: std::fmt::Display,
+{
+    PlungerError::StateError(format!("Cannot acquire probe registry lock: {}", err))
+}
+
+/// Exclusive use of one probe for the lifetime of an operation, released on drop.
+/// Operations on different probes never wait for each other.
+#[derive(Debug)]
+pub struct ProbeLock {
+    key: ProbeKey,
+}
+
+impl ProbeLock {
+    pub fn acquire(
+        selector: &DebugProbeSelector,
+        mode: LockMode,
+        cancel: &CancelToken,
+    ) -> Result<ProbeLock, PlungerError> {
+        let key = ProbeKey::from(selector);
+        let mut locked = LOCKED_PROBES.lock().map_err(lock_error)?;
+
+        while let Some(other) = locked.iter().find(|other| other.key.conflicts(&key)) {
+            if other.debugger_attached {
+                return Err(PlungerError::DebuggerAttached(other.key.to_string()));
+            }
+
+            if mode == LockMode::FailFast {
+                return Err(PlungerError::ProbeBusy(key.to_string()));
+            }
+
+            cancel.check()?;
+            locked = PROBE_RELEASED
+                .wait_timeout(locked, LOCK_POLL_INTERVAL)
+                .map_err(lock_error)?
+                .0;
+        }
+
+        locked.push(LockedProbe {
+            key: key.clone(),
+            debugger_attached: false,
+        });
+        Ok(ProbeLock { key })
+    }
+
+    /// While set, operations wanting this probe are refused instead of queued
+    pub fn set_debugger_attached(&self, attached: bool) -> Result<(), PlungerError> {
+        let mut locked = LOCKED_PROBES.lock().map_err(lock_error)?;
+        if let Some(entry) = locked.iter_mut().find(|entry| entry.key == self.key) {
+            entry.debugger_attached = attached;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for ProbeLock {
+    fn drop(&mut self) {
+        let mut locked = match LOCKED_PROBES.lock() {
+            Ok(locked) => locked,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        if let Some(idx) = locked.iter().position(|entry| entry.key == self.key) {
+            locked.remove(idx);
+        }
+        PROBE_RELEASED.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Instant};
+
+    use super::*;
+
+    // Every test uses its own fake PID, the lock registry is shared between test threads
+    fn selector(pid: u16, serial: Option<&str>) -> DebugProbeSelector {
+        DebugProbeSelector {
+            vendor_id: 0xfff0,
+            product_id: pid,
+            serial_number: serial.map(String::from),
+        }
+    }
+
+    fn key(pid: u16, serial: Option<&str>) -> ProbeKey {
+        ProbeKey::from(&selector(pid, serial))
+    }
+
+    #[test]
+    fn conflicts_on_the_same_probe_only() {
+        assert!(key(1, Some("a")).conflicts(&key(1, Some("a"))));
+        assert!(!key(1, Some("a")).conflicts(&key(1, Some("b"))));
+        assert!(!key(1, Some("a")).conflicts(&key(2, Some("a"))));
+
+        // Without a serial number either side may end up opening the other's probe
+        assert!(key(1, None).conflicts(&key(1, Some("a"))));
+        assert!(key(1, Some("a")).conflicts(&key(1, None)));
+        assert!(key(1, None).conflicts(&key(1, None)));
+        assert!(!key(1, None).conflicts(&key(2, None)));
+    }
+
+    #[test]
+    fn fail_fast_rejects_a_busy_probe() {
+        let cancel = CancelToken::default();
+        let lock = ProbeLock::acquire(&selector(0x40, Some("a")), LockMode::FailFast, &cancel);
+        assert!(lock.is_ok());
+
+        assert!(matches!(
+            ProbeLock::acquire(&selector(0x40, Some("a")), LockMode::FailFast, &cancel),
+            Err(PlungerError::ProbeBusy(_))
+        ));
+        assert!(matches!(
+            ProbeLock::acquire(&selector(0x40, None), LockMode::FailFast, &cancel),
+            Err(PlungerError::ProbeBusy(_))
+        ));
+        assert!(
+            ProbeLock::acquire(&selector(0x40, Some("b")), LockMode::FailFast, &cancel).is_ok()
+        );
+
+        drop(lock);
+        assert!(
+            ProbeLock::acquire(&selector(0x40, Some("a")), LockMode::FailFast, &cancel).is_ok()
+        );
+    }
+
+    #[test]
+    fn queue_waits_for_the_probe() {
+        let lock = ProbeLock::acquire(
+            &selector(0x41, Some("a")),
+            LockMode::Queue,
+            &CancelToken::default(),
+        )
+        .unwrap();
+
+        let start = Instant::now();
+        let release = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            drop(lock);
+        });
+
+        let queued = ProbeLock::acquire(
+            &selector(0x41, Some("a")),
+            LockMode::Queue,
+            &CancelToken::default().with_timeout(Duration::from_secs(5)),
+        );
+        assert!(queued.is_ok());
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        release.join().unwrap();
+    }
+
+    #[test]
+    fn queue_gives_up_when_cancelled() {
+        let _lock = ProbeLock::acquire(
+            &selector(0x42, Some("a")),
+            LockMode::Queue,
+            &CancelToken::default(),
+        )
+        .unwrap();
+
+        let cancel = CancelToken::default();
+        cancel.cancel();
+        assert!(matches!(
+            ProbeLock::acquire(&selector(0x42, Some("a")), LockMode::Queue, &cancel),
+            Err(PlungerError::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn refuses_to_queue_behind_a_debugger() {
+        let cancel = CancelToken::default().with_timeout(Duration::from_secs(5));
+        let lock =
+            ProbeLock::acquire(&selector(0x43, Some("a")), LockMode::Queue, &cancel).unwrap();
+        lock.set_debugger_attached(true).unwrap();
+
+        for mode in [LockMode::Queue, LockMode::FailFast] {
+            assert!(matches!(
+                ProbeLock::acquire(&selector(0x43, Some("a")), mode, &cancel),
+                Err(PlungerError::DebuggerAttached(_))
+            ));
+        }
+
+        // Back to an ordinary busy probe once the client disconnects
+        lock.set_debugger_attached(false).unwrap();
+        assert!(matches!(
+            ProbeLock::acquire(&selector(0x43, Some("a")), LockMode::FailFast, &cancel),
+            Err(PlungerError::ProbeBusy(_))
+        ));
+    }
+}
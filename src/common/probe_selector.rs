@@ -0,0 +1,245 @@
+use napi::{CallContext, JsNumber, JsString, JsUnknown, ValueType};
+use probe_rs::{DebugProbeSelector, Probe};
+use serde::{Deserialize, Serialize};
+
+use crate::probe::probe_binding::convert_probe;
+
+use super::{plunger_error::PlungerError, probe_info::ProbeInfo};
+
+/// Any combination of fields narrows down the probe list, `index` picks among what is left
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeSelector {
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub serial_num: Option<String>,
+    pub short_id: Option<u32>,
+    pub index: Option<usize>,
+}
+
+impl ProbeSelector {
+    fn matches(&self, probe: &ProbeInfo) -> bool {
+        self.vid.is_none_or(|vid| vid == probe.vid)
+            && self.pid.is_none_or(|pid| pid == probe.pid)
+            && self
+                .serial_num
+                .as_ref()
+                .is_none_or(|sn| Some(sn) == probe.serial_num.as_ref())
+            && self
+                .short_id
+                .is_none_or(|short_id| Some(short_id) == probe.short_id)
+    }
+
+    pub fn resolve(&self) -> Result<DebugProbeSelector, PlungerError> {
+        // A full VID/PID pair does not need the probe list, same as before selectors existed
+        if let (Some(vid), Some(pid), None, None) = (self.vid, self.pid, self.short_id, self.index)
+        {
+            return Ok(DebugProbeSelector {
+                vendor_id: vid,
+                product_id: pid,
+                serial_number: self.serial_num.clone(),
+            });
+        }
+
+        let probes: Vec<ProbeInfo> = Probe::list_all().into_iter().map(convert_probe).collect();
+        self.resolve_in(&probes)
+    }
+
+    fn resolve_in(&self, probes: &[ProbeInfo]) -> Result<DebugProbeSelector, PlungerError> {
+        let candidates: Vec<&ProbeInfo> =
+            probes.iter().filter(|probe| self.matches(probe)).collect();
+
+        let probe = match (self.index, candidates.len()) {
+            (Some(index), _) => candidates.get(index).copied().ok_or_else(|| {
+                PlungerError::ProbeNotFound(format!(
+                    "index {} is out of range, {} probe(s) matched",
+                    index,
+                    candidates.len()
+                ))
+            })?,
+            (None, 1) => candidates[0],
+            (None, 0) => return Err(PlungerError::ProbeNotFound(format!("{:?}", self))),
+            (None, count) => return Err(PlungerError::AmbiguousProbe(count)),
+        };
+
+        // Probes are opened by VID, PID and serial, which cannot tell identical probes without
+        // a serial number apart, so the first of them would be opened rather than this one
+        let twins = probes
+            .iter()
+            .filter(|other| other.vid == probe.vid && other.pid == probe.pid)
+            .count();
+        if probe.serial_num.is_none() && twins > 1 {
+            return Err(PlungerError::AmbiguousProbe(twins));
+        }
+
+        Ok(probe.into())
+    }
+}
+
+/// Reads either a selector object at `vid_idx`, or the legacy VID, PID and serial arguments.
+/// Resolving it may list the probes, which belongs in the task rather than on the JS thread.
+pub fn probe_selector_from_args(
+    ctx: &CallContext,
+    vid_idx: usize,
+    pid_idx: usize,
+    sn_idx: usize,
+) -> napi::Result<ProbeSelector> {
+    let first = ctx.get::<JsUnknown>(vid_idx)?;
+    if first.get_type()? == ValueType::Object {
+        return ctx.env.from_js_value(first);
+    }
+
+    let vid = ctx.get::<JsNumber>(vid_idx)?.get_int32()?;
+    let pid = ctx.get::<JsNumber>(pid_idx)?.get_int32()?;
+    let serial_num = match ctx.try_get::<JsString>(sn_idx)? {
+        napi::Either::A(sn) => Some(sn.into_utf8()?.as_str()?.to_string()),
+        napi::Either::B(_) => None,
+    };
+
+    if vid < 0 || pid < 0 || vid > u16::MAX as i32 || pid > u16::MAX as i32 {
+        return Err(napi::Error {
+            status: napi::Status::InvalidArg,
+            reason: "Invalid VID/PID provided".to_string(),
+        });
+    }
+
+    Ok(ProbeSelector {
+        vid: Some(vid as u16),
+        pid: Some(pid as u16),
+        serial_num,
+        ..ProbeSelector::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe(vid: u16, pid: u16, serial_num: Option<&str>, short_id: Option<u32>) -> ProbeInfo {
+        ProbeInfo {
+            vid,
+            pid,
+            serial_num: serial_num.map(String::from),
+            probe_type: None,
+            short_id,
+            ready: None,
+        }
+    }
+
+    fn probes() -> Vec<ProbeInfo> {
+        vec![
+            probe(0x0d28, 0x0204, Some("0240000032044e45"), Some(0x3204)),
+            probe(0x0d28, 0x0204, Some("0240000034544e45"), Some(0x3454)),
+            probe(0x0483, 0x374b, Some("066DFF555071"), None),
+        ]
+    }
+
+    #[test]
+    fn matches_every_given_field() {
+        let probe = &probes()[0];
+        assert!(ProbeSelector::default().matches(probe));
+        assert!(ProbeSelector {
+            vid: Some(0x0d28),
+            short_id: Some(0x3204),
+            ..ProbeSelector::default()
+        }
+        .matches(probe));
+        assert!(!ProbeSelector {
+            vid: Some(0x0d28),
+            short_id: Some(0x3454),
+            ..ProbeSelector::default()
+        }
+        .matches(probe));
+        assert!(!ProbeSelector {
+            serial_num: Some("0240000032044e45".into()),
+            pid: Some(0x374b),
+            ..ProbeSelector::default()
+        }
+        .matches(probe));
+    }
+
+    #[test]
+    fn resolves_by_short_id() {
+        let selector = ProbeSelector {
+            short_id: Some(0x3454),
+            ..ProbeSelector::default()
+        };
+        let resolved = selector.resolve_in(&probes()).unwrap();
+        assert_eq!(resolved.serial_number.as_deref(), Some("0240000034544e45"));
+    }
+
+    #[test]
+    fn resolves_by_serial_only() {
+        let selector = ProbeSelector {
+            serial_num: Some("066DFF555071".into()),
+            ..ProbeSelector::default()
+        };
+        let resolved = selector.resolve_in(&probes()).unwrap();
+        assert_eq!((resolved.vendor_id, resolved.product_id), (0x0483, 0x374b));
+    }
+
+    #[test]
+    fn resolves_by_index_among_matches() {
+        let selector = ProbeSelector {
+            vid: Some(0x0d28),
+            index: Some(1),
+            ..ProbeSelector::default()
+        };
+        let resolved = selector.resolve_in(&probes()).unwrap();
+        assert_eq!(resolved.serial_number.as_deref(), Some("0240000034544e45"));
+
+        let selector = ProbeSelector {
+            vid: Some(0x0d28),
+            index: Some(2),
+            ..ProbeSelector::default()
+        };
+        assert!(matches!(
+            selector.resolve_in(&probes()),
+            Err(PlungerError::ProbeNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_ambiguous_and_missing_probes() {
+        let selector = ProbeSelector {
+            vid: Some(0x0d28),
+            ..ProbeSelector::default()
+        };
+        assert!(matches!(
+            selector.resolve_in(&probes()),
+            Err(PlungerError::AmbiguousProbe(2))
+        ));
+
+        let selector = ProbeSelector {
+            short_id: Some(0xffff),
+            ..ProbeSelector::default()
+        };
+        assert!(matches!(
+            selector.resolve_in(&probes()),
+            Err(PlungerError::ProbeNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_serial_less_twins() {
+        let twins = vec![
+            probe(0x1366, 0x0101, None, Some(1)),
+            probe(0x1366, 0x0101, None, Some(2)),
+        ];
+        let selector = ProbeSelector {
+            short_id: Some(2),
+            ..ProbeSelector::default()
+        };
+        assert!(matches!(
+            selector.resolve_in(&twins),
+            Err(PlungerError::AmbiguousProbe(2))
+        ));
+
+        // A lone probe without a serial number is fine
+        let selector = ProbeSelector {
+            short_id: Some(1),
+            ..ProbeSelector::default()
+        };
+        assert!(selector.resolve_in(&twins[..1]).is_ok());
+    }
+}
@@ -1,6 +1,17 @@
-use crate::common::plunger_error::PlungerError;
+use serde::Serialize;
+
+use crate::{common::plunger_error::PlungerError, svd::device::RegisterValue};
 
 pub trait BaseEraser {
     fn mass_erase(&mut self) -> Result<(), PlungerError>;
     fn unlock_flash(&mut self) -> Result<(), PlungerError>;
 }
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct EraseReport {
+    /// Clock the probe ended up with on the last attach
+    pub speed_khz: Option<u32>,
+    /// Option bytes as read before erasing, only from targets with their own eraser
+    pub option_bytes: Option<RegisterValue>,
+}
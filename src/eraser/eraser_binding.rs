@@ -1,12 +1,29 @@
-use std::{collections::HashMap, sync::Mutex};
+use std::{collections::HashMap, sync::Mutex, time::Duration};
 
-use napi::{CallContext, JsNumber, JsObject, JsString, JsUndefined, Task};
+use napi::{CallContext, JsObject, JsString, JsUnknown, Task};
 
 use lazy_static::lazy_static;
+use probe_rs::DebugProbeSelector;
+use serde::Deserialize;
 
-use crate::eraser::{generic_eraser::erase_generic, stm32l0_eraser::erase_stm32l0};
+use crate::{
+    common::{
+        cancel_token::CancelToken,
+        connect_options::ConnectOptions,
+        plunger_error::PlungerError,
+        probe_selector::{probe_selector_from_args, ProbeSelector},
+    },
+    eraser::{
+        base_eraser::EraseReport, generic_eraser::erase_generic, stm32l0_eraser::erase_stm32l0,
+    },
+    probe::{
+        probe_details::find_probe,
+        probe_power::{power_cycle, supports_target_power, ResetStrategy, DEFAULT_POWER_OFF_MS},
+    },
+};
 
-type EraserFn = fn(String, u16, u16, Option<String>) -> napi::Result<()>;
+type EraserFn =
+    fn(String, u16, u16, Option<String>, ConnectOptions, CancelToken) -> napi::Result<EraseReport>;
 type EraserMap = HashMap<String, EraserFn>;
 
 lazy_static! {
@@ -17,18 +34,33 @@ lazy_static! {
     };
 }
 
+const DEFAULT_ERASE_TIMEOUT_MS: u64 = 60_000;
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct EraseOptions {
+    cancel_token: Option<u32>,
+    timeout_ms: Option<u64>,
+    reset_strategy: Option<ResetStrategy>,
+    power_off_ms: Option<u64>,
+}
+
 pub struct EraserTask {
-    probe_sn: Option<String>,
-    probe_vid: u16,
-    probe_pid: u16,
+    probe: ProbeSelector,
     target_name: String,
+    connect: ConnectOptions,
+    cancel: CancelToken,
+    timeout: Duration,
+    reset_strategy: ResetStrategy,
+    power_off: Duration,
 }
 
-impl Task for EraserTask {
-    type Output = ();
-    type JsValue = JsUndefined;
-
-    fn compute(&mut self) -> napi::Result<Self::Output> {
+impl EraserTask {
+    fn erase(
+        &self,
+        selector: &DebugProbeSelector,
+        cancel: CancelToken,
+    ) -> napi::Result<EraseReport> {
         let result = match ERASER_MAP.lock() {
             Ok(ret) => ret,
             Err(err) => {
@@ -44,24 +76,63 @@ impl Task for EraserTask {
             if self.target_name.contains(key) {
                 return Ok(val(
                     self.target_name.clone(),
-                    self.probe_vid,
-                    self.probe_pid,
-                    self.probe_sn.clone(),
+                    selector.vendor_id,
+                    selector.product_id,
+                    selector.serial_number.clone(),
+                    self.connect.clone(),
+                    cancel,
                 )?);
             }
         }
 
         // If no optimised target algorithm found, then use probe-rs's generic method
         Ok(erase_generic(
-            self.probe_vid,
-            self.probe_pid,
-            self.probe_sn.clone(),
+            selector.vendor_id,
+            selector.product_id,
+            selector.serial_number.clone(),
+            self.connect.clone(),
+            cancel,
         )?)
     }
+}
+
+impl Task for EraserTask {
+    type Output = EraseReport;
+    type JsValue = JsUnknown;
+
+    fn compute(&mut self) -> napi::Result<Self::Output> {
+        let cancel = self.cancel.clone().with_timeout(self.timeout);
+        let selector = self.probe.resolve()?;
+        let _lock = self.connect.lock(&selector, &cancel)?;
 
-    fn resolve(self, env: napi::Env, _output: Self::Output) -> napi::Result<Self::JsValue> {
-        // Does nothing?
-        env.get_undefined()
+        // Check before erasing, rather than failing with the target half way through
+        let power_probe = match self.reset_strategy {
+            ResetStrategy::PowerCycle => {
+                let probe = find_probe(&selector)?;
+                if !supports_target_power(&probe) {
+                    return Err(PlungerError::Unsupported(format!(
+                        "powerCycle reset with {:?} probes",
+                        probe.probe_type
+                    ))
+                    .into());
+                }
+                Some(probe)
+            }
+            ResetStrategy::Default => None,
+        };
+
+        let report = self.erase(&selector, cancel)?;
+
+        // Option bytes changed by an RDP regression only load on a power-on reset
+        if let Some(probe) = power_probe {
+            power_cycle(&probe, self.power_off)?;
+        }
+
+        Ok(report)
+    }
+
+    fn resolve(self, env: napi::Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+        env.to_js_value(&output)
     }
 
     fn reject(self, _env: napi::Env, err: napi::Error) -> napi::Result<Self::JsValue> {
@@ -69,28 +140,27 @@ impl Task for EraserTask {
     }
 }
 
-#[js_function(4)]
+#[js_function(5)]
 pub fn erase_target(ctx: CallContext) -> napi::Result<JsObject> {
     let target_name = ctx.get::<JsString>(0)?.into_utf8()?.as_str()?.to_string();
-    let vid = ctx.get::<JsNumber>(1)?.get_int32()?;
-    let pid = ctx.get::<JsNumber>(2)?.get_int32()?;
-    let serial_num = match ctx.try_get::<JsString>(3)? {
-        napi::Either::A(sn) => Some(sn.into_utf8()?.as_str()?.to_string()),
-        napi::Either::B(_) => None,
+    let probe = probe_selector_from_args(&ctx, 1, 2, 3)?;
+    let options: EraseOptions = match ctx.try_get::<JsObject>(4)? {
+        napi::Either::A(obj) => ctx.env.from_js_value(obj)?,
+        napi::Either::B(_) => EraseOptions::default(),
     };
-
-    if vid > u16::MAX as i32 || pid > u16::MAX as i32 {
-        return Err(napi::Error {
-            status: napi::Status::InvalidArg,
-            reason: "Invalid probe VID/PID provided".to_string(),
-        });
-    }
+    let connect = ConnectOptions::from_arg(&ctx, 4)?;
+    let cancel = CancelToken::from_id(options.cancel_token)?;
+    let timeout = Duration::from_millis(options.timeout_ms.unwrap_or(DEFAULT_ERASE_TIMEOUT_MS));
+    let power_off = Duration::from_millis(options.power_off_ms.unwrap_or(DEFAULT_POWER_OFF_MS));
 
     let task = EraserTask {
-        probe_sn: serial_num.clone(),
-        probe_vid: vid as u16,
-        probe_pid: pid as u16,
+        probe,
         target_name: target_name.clone(),
+        connect,
+        cancel,
+        timeout,
+        reset_strategy: options.reset_strategy.unwrap_or_default(),
+        power_off,
     };
     ctx.env.spawn(task).map(|t| t.promise_object())
 }
@@ -1,17 +1,31 @@
-use probe_rs::{config::TargetSelector, flashing::erase_all, DebugProbeSelector, Probe};
+use probe_rs::{config::TargetSelector, flashing::erase_all, DebugProbeSelector};
 
-use crate::common::plunger_error::PlungerError;
+use crate::common::{
+    cancel_token::CancelToken,
+    connect_options::{AttachMode, ConnectOptions},
+    plunger_error::PlungerError,
+};
 
-use super::base_eraser::BaseEraser;
+use super::base_eraser::{BaseEraser, EraseReport};
 
 pub struct GenericEraser {
     probe: DebugProbeSelector,
+    connect: ConnectOptions,
+    cancel: CancelToken,
+    speed_khz: Option<u32>,
 }
 
 impl GenericEraser {
-    pub fn new(probe: DebugProbeSelector) -> Result<GenericEraser, PlungerError> {
+    pub fn new(
+        probe: DebugProbeSelector,
+        connect: ConnectOptions,
+        cancel: CancelToken,
+    ) -> Result<GenericEraser, PlungerError> {
         Ok(GenericEraser {
             probe: probe.clone(),
+            connect,
+            cancel,
+            speed_khz: None,
         })
     }
 }
@@ -19,13 +33,13 @@ impl GenericEraser {
 impl BaseEraser for GenericEraser {
     fn mass_erase(&mut self) -> Result<(), PlungerError> {
         // Prepare the probe
-        let mut probe = Probe::open(self.probe.clone())?;
+        let mut connection =
+            self.connect
+                .connect(self.probe.clone(), TargetSelector::Auto, AttachMode::Normal)?;
+        self.speed_khz = Some(connection.speed_khz);
+        self.cancel.check()?;
 
-        probe.detach()?;
-
-        let mut session = probe.attach(TargetSelector::Auto)?;
-
-        Ok(erase_all(&mut session)?)
+        Ok(erase_all(&mut connection.session)?)
     }
 
     fn unlock_flash(&mut self) -> Result<(), PlungerError> {
@@ -33,11 +47,26 @@ impl BaseEraser for GenericEraser {
     }
 }
 
-pub fn erase_generic(vid: u16, pid: u16, sn: Option<String>) -> Result<(), napi::Error> {
-    let mut eraser = GenericEraser::new(DebugProbeSelector {
-        serial_number: sn.clone(),
-        vendor_id: vid,
-        product_id: pid,
-    })?;
-    Ok(eraser.mass_erase()?)
+pub fn erase_generic(
+    vid: u16,
+    pid: u16,
+    sn: Option<String>,
+    connect: ConnectOptions,
+    cancel: CancelToken,
+) -> Result<EraseReport, napi::Error> {
+    let mut eraser = GenericEraser::new(
+        DebugProbeSelector {
+            serial_number: sn.clone(),
+            vendor_id: vid,
+            product_id: pid,
+        },
+        connect,
+        cancel,
+    )?;
+    eraser.mass_erase()?;
+
+    Ok(EraseReport {
+        speed_khz: eraser.speed_khz,
+        ..EraseReport::default()
+    })
 }
@@ -1,10 +1,17 @@
-use std::{thread, time::Duration};
+use std::{cell::Cell, thread, time::Duration};
 
-use probe_rs::{Core, DebugProbeSelector, MemoryInterface, Probe};
+use probe_rs::{Core, DebugProbeSelector, MemoryInterface, Session};
 
-use crate::common::plunger_error::PlungerError;
+use crate::{
+    common::{
+        cancel_token::CancelToken,
+        connect_options::{AttachMode, ConnectOptions},
+        plunger_error::PlungerError,
+    },
+    svd::device::{Device, RegisterValue},
+};
 
-use super::base_eraser::BaseEraser;
+use super::base_eraser::{BaseEraser, EraseReport};
 
 const FLASH_PECR: u32 = 0x40022004;
 const FLASH_PKEYR: u32 = 0x4002200C;
@@ -14,15 +21,81 @@ const FLASH_SR: u32 = 0x40022018;
 const FLASH_OPTR: u32 = 0x4002201C;
 const FLASH_OPT_BASE: u32 = 0x1ff80000;
 
+// FLASH_OPTR as described in the STM32L0x3 SVD, used to report the option bytes readably
+const FLASH_OPTR_SVD: &str = r#"
+<device>
+  <name>STM32L0</name>
+  <peripherals>
+    <peripheral>
+      <name>FLASH</name>
+      <baseAddress>0x40022000</baseAddress>
+      <registers>
+        <register>
+          <name>OPTR</name>
+          <addressOffset>0x1C</addressOffset>
+          <fields>
+            <field>
+              <name>RDPROT</name><bitOffset>0</bitOffset><bitWidth>8</bitWidth>
+              <enumeratedValues>
+                <enumeratedValue><name>Level0</name><value>0xAA</value></enumeratedValue>
+                <enumeratedValue><name>Level2</name><value>0xCC</value></enumeratedValue>
+                <enumeratedValue><name>Level1</name><isDefault>true</isDefault></enumeratedValue>
+              </enumeratedValues>
+            </field>
+            <field>
+              <name>WPRMOD</name><bitOffset>8</bitOffset><bitWidth>1</bitWidth>
+              <enumeratedValues>
+                <enumeratedValue><name>WriteProtection</name><value>0</value></enumeratedValue>
+                <enumeratedValue><name>ReadProtection</name><value>1</value></enumeratedValue>
+              </enumeratedValues>
+            </field>
+            <field>
+              <name>BOR_LEV</name><bitOffset>16</bitOffset><bitWidth>4</bitWidth>
+              <enumeratedValues>
+                <enumeratedValue><name>Level1</name><value>0x8</value></enumeratedValue>
+                <enumeratedValue><name>Level2</name><value>0x9</value></enumeratedValue>
+                <enumeratedValue><name>Level3</name><value>0xA</value></enumeratedValue>
+                <enumeratedValue><name>Level4</name><value>0xB</value></enumeratedValue>
+                <enumeratedValue><name>Level5</name><value>0xC</value></enumeratedValue>
+                <enumeratedValue><name>Off</name><value>#0xxx</value></enumeratedValue>
+              </enumeratedValues>
+            </field>
+            <field><name>WDG_SW</name><bitOffset>20</bitOffset><bitWidth>1</bitWidth></field>
+            <field><name>nRST_STOP</name><bitOffset>21</bitOffset><bitWidth>1</bitWidth></field>
+            <field><name>nRST_STDBY</name><bitOffset>22</bitOffset><bitWidth>1</bitWidth></field>
+            <field><name>BFB2</name><bitOffset>23</bitOffset><bitWidth>1</bitWidth></field>
+            <field><name>nBOOT1</name><bitOffset>31</bitOffset><bitWidth>1</bitWidth></field>
+          </fields>
+        </register>
+      </registers>
+    </peripheral>
+  </peripherals>
+</device>
+"#;
+
 pub struct STM32L0Eraser {
     probe: DebugProbeSelector,
     target_name: String,
+    connect: ConnectOptions,
+    cancel: CancelToken,
+    speed_khz: Cell<Option<u32>>,
+    option_bytes: Option<RegisterValue>,
+}
+
+fn decode_option_bytes(optr: u32) -> Result<RegisterValue, PlungerError> {
+    let device = Device::parse(FLASH_OPTR_SVD)?;
+    let flash = device.peripheral("FLASH")?;
+    flash
+        .register("OPTR")?
+        .decode(flash.base_address, Some(optr))
 }
 
 impl STM32L0Eraser {
     pub fn new(
         target_name: String,
         probe: DebugProbeSelector,
+        connect: ConnectOptions,
+        cancel: CancelToken,
     ) -> Result<STM32L0Eraser, PlungerError> {
         if !target_name.contains("STM32L0") && !target_name.contains("stm32l0") {
             return Err(PlungerError::InvalidTarget(format!(
@@ -34,9 +107,26 @@ impl STM32L0Eraser {
         Ok(STM32L0Eraser {
             target_name,
             probe: probe.clone(),
+            connect,
+            cancel,
+            speed_khz: Cell::new(None),
+            option_bytes: None,
         })
     }
 
+    pub fn speed_khz(&self) -> Option<u32> {
+        self.speed_khz.get()
+    }
+
+    fn open_session(&self, mode: AttachMode) -> Result<Session, PlungerError> {
+        let connection =
+            self.connect
+                .connect(self.probe.clone(), self.target_name.clone(), mode)?;
+        self.speed_khz.set(Some(connection.speed_khz));
+
+        Ok(connection.session)
+    }
+
     fn wait_for_flash(core: &mut Core) -> Result<(), PlungerError> {
         let mut result: u32 = 1;
         while result != 0 {
@@ -46,13 +136,19 @@ impl STM32L0Eraser {
         Ok(())
     }
 
-    fn set_rdp_0_to_1(&self) -> Result<(), PlungerError> {
-        // Prepare the probe
-        let mut probe = Probe::open(self.probe.clone())?;
+    // Only used before the RDP 0 to 1 change, so bailing out leaves the flash locked but intact
+    fn wait_for_flash_cancellable(&self, core: &mut Core) -> Result<(), PlungerError> {
+        let mut result: u32 = 1;
+        while result != 0 {
+            self.cancel.check()?;
+            result = core.read_word_32(FLASH_SR)? & 0b1;
+        }
 
-        probe.detach()?;
+        Ok(())
+    }
 
-        let mut session = probe.attach(self.target_name.clone())?;
+    fn set_rdp_0_to_1(&self) -> Result<(), PlungerError> {
+        let mut session = self.open_session(AttachMode::Normal)?;
         let mut core = session.core(0)?;
 
         core.halt(Duration::from_secs(1))?;
@@ -75,16 +171,41 @@ impl STM32L0Eraser {
     }
 
     fn get_option_byte(&self) -> Result<u32, PlungerError> {
-        // Prepare the probe
-        let mut probe = Probe::open(self.probe.clone())?;
-        probe.detach()?;
-
-        let mut session = probe.attach(self.target_name.clone())?;
+        let mut session = self.open_session(AttachMode::Normal)?;
         let mut core = session.core(0)?;
 
         // Read OPTR for RDP level
         Ok(core.read_word_32(FLASH_OPTR)?)
     }
+
+    fn unlock(&self, cancellable: bool) -> Result<(), PlungerError> {
+        let wait = |core: &mut Core| match cancellable {
+            true => self.wait_for_flash_cancellable(core),
+            false => STM32L0Eraser::wait_for_flash(core),
+        };
+
+        let mut session = self.open_session(AttachMode::ConnectUnderReset)?;
+        let mut core = session.core(0)?;
+
+        core.halt(Duration::from_secs(1))?;
+
+        // Unlock flash PKEY
+        core.write_word_32(FLASH_PKEYR, 0x89abcdef)?;
+        core.write_word_32(FLASH_PKEYR, 0x02030405)?;
+        wait(&mut core)?;
+
+        // Unlock PRGKEY - programming
+        core.write_word_32(FLASH_PRGKEYR, 0x8c9daebf)?;
+        core.write_word_32(FLASH_PRGKEYR, 0x13141516)?;
+        wait(&mut core)?;
+
+        // Unlock OPTKEY - option bytes
+        core.write_word_32(FLASH_OPTKEYR, 0xfbead9c8)?;
+        core.write_word_32(FLASH_OPTKEYR, 0x24252627)?;
+        wait(&mut core)?;
+
+        Ok(())
+    }
 }
 
 impl BaseEraser for STM32L0Eraser {
@@ -93,6 +214,10 @@ impl BaseEraser for STM32L0Eraser {
         self.unlock_flash()?;
 
         let opt_val = self.get_option_byte()?;
+        self.option_bytes = Some(decode_option_bytes(opt_val)?);
+
+        // Last chance to bail out, once RDP is raised the regression back to 0 must run to the end
+        self.cancel.check()?;
 
         // RDP = 0xCC => RDP level 2, fully protected
         if opt_val & 0xff == 0xCC {
@@ -105,15 +230,12 @@ impl BaseEraser for STM32L0Eraser {
             self.set_rdp_0_to_1()?;
 
             // Re-unlock the flash for the next step
-            self.unlock_flash()?;
+            self.unlock(false)?;
         }
 
         // RDP with other values (or previously been set as 1) => deal it as 1
         // Prepare the probe
-        let mut probe = Probe::open(self.probe.clone())?;
-        probe.detach()?;
-
-        let mut session = probe.attach(self.target_name.clone())?;
+        let mut session = self.open_session(AttachMode::Normal)?;
         let mut core = session.core(0)?;
 
         println!("Setting RDP 1 to 0");
@@ -135,29 +257,7 @@ impl BaseEraser for STM32L0Eraser {
     }
 
     fn unlock_flash(&mut self) -> Result<(), PlungerError> {
-        let probe = Probe::open(self.probe.clone())?;
-
-        let mut session = probe.attach_under_reset(self.target_name.clone())?;
-        let mut core = session.core(0)?;
-
-        core.halt(Duration::from_secs(1))?;
-
-        // Unlock flash PKEY
-        core.write_word_32(FLASH_PKEYR, 0x89abcdef)?;
-        core.write_word_32(FLASH_PKEYR, 0x02030405)?;
-        STM32L0Eraser::wait_for_flash(&mut core)?;
-
-        // Unlock PRGKEY - programming
-        core.write_word_32(FLASH_PRGKEYR, 0x8c9daebf)?;
-        core.write_word_32(FLASH_PRGKEYR, 0x13141516)?;
-        STM32L0Eraser::wait_for_flash(&mut core)?;
-
-        // Unlock OPTKEY - option bytes
-        core.write_word_32(FLASH_OPTKEYR, 0xfbead9c8)?;
-        core.write_word_32(FLASH_OPTKEYR, 0x24252627)?;
-        STM32L0Eraser::wait_for_flash(&mut core)?;
-
-        Ok(())
+        self.unlock(true)
     }
 }
 
@@ -166,7 +266,9 @@ pub fn erase_stm32l0(
     vid: u16,
     pid: u16,
     sn: Option<String>,
-) -> Result<(), napi::Error> {
+    connect: ConnectOptions,
+    cancel: CancelToken,
+) -> Result<EraseReport, napi::Error> {
     let mut eraser = STM32L0Eraser::new(
         target_name.clone(),
         DebugProbeSelector {
@@ -174,6 +276,13 @@ pub fn erase_stm32l0(
             vendor_id: vid,
             product_id: pid,
         },
+        connect,
+        cancel,
     )?;
-    Ok(eraser.mass_erase()?)
+    eraser.mass_erase()?;
+
+    Ok(EraseReport {
+        speed_khz: eraser.speed_khz(),
+        option_bytes: eraser.option_bytes.take(),
+    })
 }
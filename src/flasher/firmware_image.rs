@@ -0,0 +1,64 @@
+use crate::common::plunger_error::PlungerError;
+
+use super::{
+    firmware_parser::{
+        parse_elf, parse_ihex, parse_srec, parse_ti_txt, parse_uf2, FirmwareChunk, FirmwareFormat,
+    },
+    flash_report::image_digest,
+};
+
+// TODO: change to a parameter instead of hard-coded here
+pub const BIN_BASE_ADDRESS: u32 = 0x08000000;
+
+/// Firmware parsed into address/data chunks that do not depend on the target's memory map,
+/// so the same image can be handed to any number of flash loaders
+#[derive(Debug, Clone)]
+pub struct FirmwareImage {
+    pub format: FirmwareFormat,
+    pub chunks: Vec<FirmwareChunk>,
+    pub size: usize,
+    pub crc32: u32,
+    pub sha256: String,
+}
+
+impl FirmwareImage {
+    pub fn parse(
+        content: &[u8],
+        format: Option<FirmwareFormat>,
+        uf2_family_id: Option<u32>,
+    ) -> Result<FirmwareImage, PlungerError> {
+        let format = format.unwrap_or_else(|| FirmwareFormat::detect(content));
+        let chunks = match format {
+            FirmwareFormat::Bin => vec![FirmwareChunk {
+                address: BIN_BASE_ADDRESS,
+                data: content.to_vec(),
+            }],
+            FirmwareFormat::Hex => parse_ihex(content)?,
+            FirmwareFormat::Elf => parse_elf(content)?,
+            FirmwareFormat::SRecord => parse_srec(content)?,
+            FirmwareFormat::Uf2 => parse_uf2(content, uf2_family_id)?,
+            FirmwareFormat::TiTxt => parse_ti_txt(content)?,
+        };
+        let (crc32, sha256) = image_digest(content);
+
+        Ok(FirmwareImage {
+            format,
+            chunks,
+            size: content.len(),
+            crc32,
+            sha256,
+        })
+    }
+
+    pub fn load(
+        path: &str,
+        format: Option<FirmwareFormat>,
+        uf2_family_id: Option<u32>,
+    ) -> Result<FirmwareImage, PlungerError> {
+        let content = std::fs::read(path).map_err(|err| {
+            PlungerError::InvalidFirmware(format!("Failed to open file {}: {}", path, err))
+        })?;
+
+        FirmwareImage::parse(&content, format, uf2_family_id)
+    }
+}
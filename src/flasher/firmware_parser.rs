@@ -0,0 +1,701 @@
+use std::{convert::TryFrom, ops::Range};
+
+use ihex::Record;
+use object::{
+    elf::{FileHeader32, FileHeader64, PT_LOAD},
+    read::elf::{ElfFile, FileHeader, ProgramHeader},
+    Endianness, FileKind, Object, ObjectSection,
+};
+use serde::Serialize;
+
+use crate::common::plunger_error::PlungerError;
+
+const UF2_MAGIC_START0: u32 = 0x0A324655;
+const UF2_MAGIC_START1: u32 = 0x9E5D5157;
+const UF2_MAGIC_END: u32 = 0x0AB16F30;
+const UF2_BLOCK_SIZE: usize = 512;
+const UF2_MAX_PAYLOAD: usize = 476;
+const UF2_FLAG_NOT_MAIN_FLASH: u32 = 0x00000001;
+const UF2_FLAG_FAMILY_ID_PRESENT: u32 = 0x00002000;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum FirmwareFormat {
+    #[serde(rename = "bin")]
+    Bin,
+    #[serde(rename = "hex")]
+    Hex,
+    #[serde(rename = "elf")]
+    Elf,
+    #[serde(rename = "srec")]
+    SRecord,
+    #[serde(rename = "uf2")]
+    Uf2,
+    #[serde(rename = "ti-txt")]
+    TiTxt,
+}
+
+impl FirmwareFormat {
+    /// Returns `None` if the format should be detected from the file content
+    pub fn from_type_name(name: &str) -> Result<Option<FirmwareFormat>, PlungerError> {
+        match name.to_lowercase().as_str() {
+            "" | "auto" => Ok(None),
+            "bin" => Ok(Some(FirmwareFormat::Bin)),
+            "hex" | "ihex" => Ok(Some(FirmwareFormat::Hex)),
+            "elf" => Ok(Some(FirmwareFormat::Elf)),
+            "srec" | "s19" | "s28" | "s37" | "mot" => Ok(Some(FirmwareFormat::SRecord)),
+            "uf2" => Ok(Some(FirmwareFormat::Uf2)),
+            "txt" | "titxt" | "ti-txt" => Ok(Some(FirmwareFormat::TiTxt)),
+            _ => Err(invalid(format!(
+                "Unknown firmware type {}, expected auto/bin/hex/elf/srec/uf2/ti-txt",
+                name
+            ))),
+        }
+    }
+
+    pub fn detect(content: &[u8]) -> FirmwareFormat {
+        if content.starts_with(b"\x7fELF") {
+            return FirmwareFormat::Elf;
+        }
+
+        if content.len() >= UF2_BLOCK_SIZE
+            && read_le_u32(content, 0) == UF2_MAGIC_START0
+            && read_le_u32(content, 4) == UF2_MAGIC_START1
+        {
+            return FirmwareFormat::Uf2;
+        }
+
+        // Text formats may come with a BOM or leading blank lines
+        let text = content.strip_prefix(b"\xef\xbb\xbf").unwrap_or(content);
+        let text_start = text
+            .iter()
+            .position(|byte| !byte.is_ascii_whitespace())
+            .map(|idx| &text[idx..]);
+
+        match text_start {
+            Some([b':', next, ..]) if next.is_ascii_hexdigit() => FirmwareFormat::Hex,
+            Some([b'S', b'0'..=b'9', ..]) => FirmwareFormat::SRecord,
+            Some([b'@', next, ..]) if next.is_ascii_hexdigit() => FirmwareFormat::TiTxt,
+            _ => FirmwareFormat::Bin,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FirmwareChunk {
+    pub address: u32,
+    pub data: Vec<u8>,
+}
+
+fn invalid(reason: String) -> PlungerError {
+    PlungerError::InvalidFirmware(reason)
+}
+
+fn decode_hex_bytes(text: &str, line_num: usize) -> Result<Vec<u8>, PlungerError> {
+    if !text.is_ascii() {
+        return Err(invalid(format!("Invalid hex digits on line {}", line_num)));
+    }
+
+    if !text.len().is_multiple_of(2) {
+        return Err(invalid(format!(
+            "Odd number of hex digits on line {}",
+            line_num
+        )));
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|idx| {
+            u8::from_str_radix(&text[idx..idx + 2], 16)
+                .map_err(|_| invalid(format!("Invalid hex digits on line {}", line_num)))
+        })
+        .collect()
+}
+
+fn read_le_u32(block: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        block[offset],
+        block[offset + 1],
+        block[offset + 2],
+        block[offset + 3],
+    ])
+}
+
+// Append data to the last chunk if it is contiguous, otherwise start a new one
+pub(super) fn push_chunk(chunks: &mut Vec<FirmwareChunk>, address: u32, data: &[u8]) {
+    if let Some(last) = chunks.last_mut() {
+        if last.address.wrapping_add(last.data.len() as u32) == address {
+            last.data.extend_from_slice(data);
+            return;
+        }
+    }
+
+    chunks.push(FirmwareChunk {
+        address,
+        data: data.to_vec(),
+    });
+}
+
+/// Motorola S-record, accepts S1/S2/S3 data records
+pub fn parse_srec(content: &[u8]) -> Result<Vec<FirmwareChunk>, PlungerError> {
+    let text = std::str::from_utf8(content)
+        .map_err(|_| invalid("S-record file is not valid ASCII".to_string()))?;
+    let mut chunks: Vec<FirmwareChunk> = Vec::new();
+
+    for (idx, line) in text.lines().enumerate() {
+        let line_num = idx + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.len() < 4 || !line.is_ascii() || !line.starts_with('S') {
+            return Err(invalid(format!("Invalid S-record on line {}", line_num)));
+        }
+
+        let record_type = line.as_bytes()[1];
+        let bytes = decode_hex_bytes(&line[2..], line_num)?;
+        let count = bytes[0] as usize;
+        if count + 1 != bytes.len() || count < 3 {
+            return Err(invalid(format!("Bad S-record length on line {}", line_num)));
+        }
+
+        // Checksum is the one's complement of the sum of count, address and data
+        let sum = bytes[..count]
+            .iter()
+            .fold(0u8, |acc, byte| acc.wrapping_add(*byte));
+        if !sum != bytes[count] {
+            return Err(invalid(format!(
+                "S-record checksum mismatch on line {}",
+                line_num
+            )));
+        }
+
+        let addr_len = match record_type {
+            b'1' => 2,
+            b'2' => 3,
+            b'3' => 4,
+            b'0' | b'5' | b'6' | b'7' | b'8' | b'9' => continue,
+            _ => {
+                return Err(invalid(format!(
+                    "Unsupported S-record type on line {}",
+                    line_num
+                )))
+            }
+        };
+
+        if count < addr_len + 1 {
+            return Err(invalid(format!("Truncated S-record on line {}", line_num)));
+        }
+
+        let address = bytes[1..1 + addr_len]
+            .iter()
+            .fold(0u32, |acc, byte| (acc << 8) | *byte as u32);
+        push_chunk(&mut chunks, address, &bytes[1 + addr_len..count]);
+    }
+
+    Ok(chunks)
+}
+
+/// UF2 blocks, optionally filtered by family ID
+pub fn parse_uf2(
+    content: &[u8],
+    family_id: Option<u32>,
+) -> Result<Vec<FirmwareChunk>, PlungerError> {
+    if content.is_empty() || !content.len().is_multiple_of(UF2_BLOCK_SIZE) {
+        return Err(invalid(
+            "UF2 file size is not a multiple of 512 bytes".to_string(),
+        ));
+    }
+
+    let mut chunks: Vec<FirmwareChunk> = Vec::new();
+    let mut seen_family: Option<u32> = None;
+
+    for (idx, block) in content.chunks(UF2_BLOCK_SIZE).enumerate() {
+        if read_le_u32(block, 0) != UF2_MAGIC_START0
+            || read_le_u32(block, 4) != UF2_MAGIC_START1
+            || read_le_u32(block, UF2_BLOCK_SIZE - 4) != UF2_MAGIC_END
+        {
+            return Err(invalid(format!("Bad UF2 magic in block {}", idx)));
+        }
+
+        let flags = read_le_u32(block, 8);
+        let target_addr = read_le_u32(block, 12);
+        let payload_size = read_le_u32(block, 16) as usize;
+
+        if flags & UF2_FLAG_NOT_MAIN_FLASH != 0 {
+            continue;
+        }
+
+        if payload_size > UF2_MAX_PAYLOAD {
+            return Err(invalid(format!("Bad UF2 payload size in block {}", idx)));
+        }
+
+        if flags & UF2_FLAG_FAMILY_ID_PRESENT != 0 {
+            let block_family = read_le_u32(block, 28);
+            match family_id {
+                Some(wanted) if wanted != block_family => continue,
+                Some(_) => (),
+                None => match seen_family {
+                    Some(seen) if seen != block_family => {
+                        return Err(invalid(format!(
+                            "UF2 contains multiple families (0x{:08x}, 0x{:08x}), specify one",
+                            seen, block_family
+                        )))
+                    }
+                    _ => seen_family = Some(block_family),
+                },
+            }
+        } else if family_id.is_some() {
+            continue;
+        }
+
+        push_chunk(&mut chunks, target_addr, &block[32..32 + payload_size]);
+    }
+
+    if chunks.is_empty() {
+        return Err(invalid("No matching UF2 blocks found".to_string()));
+    }
+
+    Ok(chunks)
+}
+
+/// TI-TXT, "@ADDR" sections followed by hex bytes and terminated with "q"
+pub fn parse_ti_txt(content: &[u8]) -> Result<Vec<FirmwareChunk>, PlungerError> {
+    let text = std::str::from_utf8(content)
+        .map_err(|_| invalid("TI-TXT file is not valid ASCII".to_string()))?;
+    let mut chunks: Vec<FirmwareChunk> = Vec::new();
+    let mut address: Option<u32> = None;
+
+    for (idx, line) in text.lines().enumerate() {
+        let line_num = idx + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.starts_with('q') || line.starts_with('Q') {
+            return Ok(chunks);
+        }
+
+        if let Some(addr_str) = line.strip_prefix('@') {
+            address =
+                Some(u32::from_str_radix(addr_str.trim(), 16).map_err(|_| {
+                    invalid(format!("Invalid TI-TXT address on line {}", line_num))
+                })?);
+            continue;
+        }
+
+        let current = address
+            .ok_or_else(|| invalid(format!("TI-TXT data before address on line {}", line_num)))?;
+        let data = line
+            .split_whitespace()
+            .map(|byte| decode_hex_bytes(byte, line_num))
+            .collect::<Result<Vec<Vec<u8>>, PlungerError>>()?
+            .concat();
+
+        push_chunk(&mut chunks, current, &data);
+        address = Some(current.wrapping_add(data.len() as u32));
+    }
+
+    Err(invalid(
+        "TI-TXT file is missing the \"q\" terminator".to_string(),
+    ))
+}
+
+/// Intel HEX, accepts data, EOF, extended segment and extended linear address records
+pub fn parse_ihex(content: &[u8]) -> Result<Vec<FirmwareChunk>, PlungerError> {
+    let text = std::str::from_utf8(content)
+        .map_err(|_| invalid("Intel HEX file is not valid ASCII".to_string()))?;
+    let mut chunks: Vec<FirmwareChunk> = Vec::new();
+    let mut base: u32 = 0;
+
+    for (idx, line) in text.lines().enumerate() {
+        let line_num = idx + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let record = line.parse::<Record>().map_err(|err| {
+            invalid(format!(
+                "Invalid Intel HEX record on line {}: {}",
+                line_num, err
+            ))
+        })?;
+        match record {
+            Record::Data { offset, value } => {
+                push_chunk(&mut chunks, base.wrapping_add(offset as u32), &value)
+            }
+            Record::EndOfFile => return Ok(chunks),
+            Record::ExtendedSegmentAddress(segment) => base = (segment as u32) << 4,
+            Record::ExtendedLinearAddress(upper) => base = (upper as u32) << 16,
+            Record::StartSegmentAddress { .. } | Record::StartLinearAddress(_) => continue,
+        }
+    }
+
+    Err(invalid(
+        "Intel HEX file is missing the EOF record".to_string(),
+    ))
+}
+
+fn elf_error(err: object::read::Error) -> PlungerError {
+    invalid(format!("Invalid ELF file: {}", err))
+}
+
+// PT_LOAD segments of either ELF class that hold at least one section with file contents,
+// NOBITS sections like .bss take no room in the file
+fn elf_segments<Elf>(content: &[u8]) -> Result<Vec<(u32, &[u8])>, PlungerError>
+where
+    Elf: FileHeader<Endian = Endianness>,
+{
+    let file = ElfFile::<Elf>::parse(content).map_err(elf_error)?;
+    let endian = file.endian();
+
+    let sections: Vec<Range<u64>> = file
+        .sections()
+        .filter_map(|section| section.file_range())
+        .filter(|(_, size)| *size > 0)
+        .map(|(offset, size)| {
+            offset
+                .checked_add(size)
+                .map(|end| offset..end)
+                .ok_or_else(|| invalid("ELF section is out of bounds".to_string()))
+        })
+        .collect::<Result<_, _>>()?;
+
+    let headers = file
+        .raw_header()
+        .program_headers(endian, content)
+        .map_err(elf_error)?;
+
+    let mut segments: Vec<(u32, &[u8])> = Vec::new();
+    for (idx, segment) in headers.iter().enumerate() {
+        if segment.p_type(endian) != PT_LOAD {
+            continue;
+        }
+
+        let (offset, size) = segment.file_range(endian);
+        if size == 0 {
+            continue;
+        }
+
+        let data = segment
+            .data(endian, content)
+            .map_err(|_| invalid(format!("ELF segment {} is out of bounds", idx)))?;
+        let end = offset
+            .checked_add(size)
+            .ok_or_else(|| invalid(format!("ELF segment {} is out of bounds", idx)))?;
+        if !sections
+            .iter()
+            .any(|section| section.start >= offset && section.end <= end)
+        {
+            continue;
+        }
+
+        let paddr: u64 = segment.p_paddr(endian).into();
+        let address = u32::try_from(paddr).map_err(|_| {
+            invalid(format!(
+                "ELF segment {} is outside the 32-bit address space",
+                idx
+            ))
+        })?;
+        segments.push((address, data));
+    }
+
+    Ok(segments)
+}
+
+/// ELF, loads the file contents of every PT_LOAD segment holding a section at its physical
+/// address. Segments without sections, e.g. a stack reservation, are skipped like probe-rs does.
+pub fn parse_elf(content: &[u8]) -> Result<Vec<FirmwareChunk>, PlungerError> {
+    let mut segments = match FileKind::parse(content) {
+        Ok(FileKind::Elf32) => elf_segments::<FileHeader32<Endianness>>(content)?,
+        Ok(FileKind::Elf64) => elf_segments::<FileHeader64<Endianness>>(content)?,
+        _ => return Err(invalid("Not an ELF file".to_string())),
+    };
+
+    segments.sort_by_key(|(address, _)| *address);
+
+    let mut chunks: Vec<FirmwareChunk> = Vec::new();
+    for (address, data) in segments {
+        push_chunk(&mut chunks, address, data);
+    }
+
+    if chunks.is_empty() {
+        return Err(invalid("ELF file has no loadable segments".to_string()));
+    }
+
+    Ok(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uf2_block(flags: u32, address: u32, payload: &[u8], family: u32) -> Vec<u8> {
+        let mut block = vec![0u8; UF2_BLOCK_SIZE];
+        let header = [
+            UF2_MAGIC_START0,
+            UF2_MAGIC_START1,
+            flags,
+            address,
+            payload.len() as u32,
+            0,
+            1,
+            family,
+        ];
+        for (idx, word) in header.iter().enumerate() {
+            block[idx * 4..idx * 4 + 4].copy_from_slice(&word.to_le_bytes());
+        }
+        block[32..32 + payload.len()].copy_from_slice(payload);
+        block[UF2_BLOCK_SIZE - 4..].copy_from_slice(&UF2_MAGIC_END.to_le_bytes());
+        block
+    }
+
+    // The example file from the S-record article on Wikipedia
+    const SREC: &str = "S00F000068656C6C6F202020202000003C
+S11F00007C0802A6900100049421FFF07C6C1B787C8C23783C6000003863000026
+S11F001C4BFFFFE5398000007D83637880010014382100107C0803A64E800020E9
+S111003848656C6C6F20776F726C642E0A0042
+S5030003F9
+S9030000FC
+";
+
+    // ELF32 with a segment per entry of `segments`, given as (file offset, paddr, data), and
+    // section headers for the (type, file offset, size) entries of `sections` followed by an
+    // empty section name table
+    fn elf32(segments: &[(u32, u32, &[u8])], sections: &[(u32, u32, u32)]) -> Vec<u8> {
+        use object::elf::SHT_STRTAB;
+
+        let ph_offset = 0x34;
+        let sh_offset = 0x200;
+        let sections = [sections, &[(SHT_STRTAB, 0x3ff, 1)]].concat();
+        let mut elf = vec![0u8; 0x400];
+        elf[..7].copy_from_slice(b"\x7fELF\x01\x01\x01");
+        elf[0x1c..0x20].copy_from_slice(&(ph_offset as u32).to_le_bytes());
+        elf[0x20..0x24].copy_from_slice(&(sh_offset as u32).to_le_bytes());
+        elf[0x2a..0x2c].copy_from_slice(&32u16.to_le_bytes());
+        elf[0x2c..0x2e].copy_from_slice(&(segments.len() as u16).to_le_bytes());
+        elf[0x2e..0x30].copy_from_slice(&40u16.to_le_bytes());
+        elf[0x30..0x32].copy_from_slice(&(sections.len() as u16).to_le_bytes());
+        elf[0x32..0x34].copy_from_slice(&(sections.len() as u16 - 1).to_le_bytes());
+
+        for (idx, (offset, address, data)) in segments.iter().enumerate() {
+            let header = ph_offset + idx * 32;
+            elf[header..header + 4].copy_from_slice(&PT_LOAD.to_le_bytes());
+            elf[header + 0x04..header + 0x08].copy_from_slice(&offset.to_le_bytes());
+            elf[header + 0x0c..header + 0x10].copy_from_slice(&address.to_le_bytes());
+            elf[header + 0x10..header + 0x14].copy_from_slice(&(data.len() as u32).to_le_bytes());
+            let offset = *offset as usize;
+            elf[offset..offset + data.len()].copy_from_slice(data);
+        }
+
+        for (idx, (kind, offset, size)) in sections.iter().enumerate() {
+            let header = sh_offset + idx * 40;
+            elf[header + 0x04..header + 0x08].copy_from_slice(&kind.to_le_bytes());
+            elf[header + 0x10..header + 0x14].copy_from_slice(&offset.to_le_bytes());
+            elf[header + 0x14..header + 0x18].copy_from_slice(&size.to_le_bytes());
+        }
+
+        elf
+    }
+
+    #[test]
+    fn elf_skips_segments_without_sections() {
+        use object::elf::{SHT_NOBITS, SHT_PROGBITS};
+
+        let elf = elf32(
+            &[
+                (0x100, 0x0800_0000, &[1, 2, 3, 4]),
+                (0x120, 0x2000_0000, &[5, 6, 7, 8]),
+            ],
+            &[(0, 0, 0), (SHT_PROGBITS, 0x100, 4), (SHT_NOBITS, 0x120, 4)],
+        );
+
+        let chunks = parse_elf(&elf).unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].address, 0x0800_0000);
+        assert_eq!(chunks[0].data, vec![1, 2, 3, 4]);
+
+        let elf = elf32(&[(0x120, 0x2000_0000, &[5, 6, 7, 8])], &[(0, 0, 0)]);
+        assert!(parse_elf(&elf).is_err());
+    }
+
+    #[test]
+    fn elf_rejects_out_of_bounds_tables() {
+        let mut elf = elf32(&[(0x100, 0x0800_0000, &[1, 2, 3, 4])], &[(1, 0x100, 4)]);
+        elf[0x1c..0x20].copy_from_slice(&0xffff_fff0u32.to_le_bytes());
+        assert!(parse_elf(&elf).is_err());
+
+        let mut elf = elf32(&[(0x100, 0x0800_0000, &[1, 2, 3, 4])], &[(1, 0x100, 4)]);
+        elf[0x34 + 0x10..0x34 + 0x14].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(parse_elf(&elf).is_err());
+
+        assert!(parse_elf(b"\x7fELF\x01\x01\x01").is_err());
+    }
+
+    #[test]
+    fn ihex_follows_extended_addresses() {
+        let content = b":020000040800F2\n:0400000001020304F2\n:020000021000EC\n:02000200AABB97\n:00000001FF\n";
+        let chunks = parse_ihex(content).unwrap();
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].address, 0x0800_0000);
+        assert_eq!(chunks[0].data, vec![1, 2, 3, 4]);
+        assert_eq!(chunks[1].address, 0x0001_0002);
+        assert_eq!(chunks[1].data, vec![0xaa, 0xbb]);
+    }
+
+    #[test]
+    fn ihex_rejects_checksum_mismatch_and_missing_eof() {
+        let err = parse_ihex(b":0400000001020304F3\n:00000001FF\n").unwrap_err();
+        assert!(err.to_string().contains("line 1"));
+
+        assert!(parse_ihex(b":0400000001020304F2\n").is_err());
+    }
+
+    #[test]
+    fn srec_merges_contiguous_records() {
+        let chunks = parse_srec(SREC.as_bytes()).unwrap();
+
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].address, 0);
+        assert_eq!(chunks[0].data.len(), 28 + 28 + 14);
+        assert!(chunks[0].data.ends_with(b"Hello world.\n\0"));
+    }
+
+    #[test]
+    fn srec_reads_24_and_32_bit_addresses() {
+        let chunks = parse_srec(b"S2060800001122BE\nS30708000010AABB7B\n").unwrap();
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].address, 0x0008_0000);
+        assert_eq!(chunks[0].data, vec![0x11, 0x22]);
+        assert_eq!(chunks[1].address, 0x0800_0010);
+        assert_eq!(chunks[1].data, vec![0xaa, 0xbb]);
+    }
+
+    #[test]
+    fn srec_rejects_checksum_mismatch() {
+        let corrupted = SREC.replace("0A0042", "0A0043");
+        let err = parse_srec(corrupted.as_bytes()).unwrap_err();
+
+        assert!(err.to_string().contains("checksum mismatch on line 4"));
+    }
+
+    #[test]
+    fn srec_rejects_bad_length() {
+        assert!(parse_srec(b"S1050000AA\n").is_err());
+    }
+
+    #[test]
+    fn uf2_filters_by_family() {
+        let mut content = uf2_block(UF2_FLAG_FAMILY_ID_PRESENT, 0x1000, &[1, 2], 0xe48bff56);
+        content.extend(uf2_block(
+            UF2_FLAG_FAMILY_ID_PRESENT,
+            0x2000,
+            &[3, 4],
+            0x68ed2b88,
+        ));
+
+        let chunks = parse_uf2(&content, Some(0x68ed2b88)).unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].address, 0x2000);
+        assert_eq!(chunks[0].data, vec![3, 4]);
+
+        let err = parse_uf2(&content, None).unwrap_err();
+        assert!(err.to_string().contains("multiple families"));
+
+        assert!(parse_uf2(&content, Some(0x12345678)).is_err());
+    }
+
+    #[test]
+    fn uf2_merges_blocks_and_skips_non_flash() {
+        let mut content = uf2_block(0, 0x1000, &[1, 2], 0);
+        content.extend(uf2_block(UF2_FLAG_NOT_MAIN_FLASH, 0x9000, &[9], 0));
+        content.extend(uf2_block(0, 0x1002, &[3], 0));
+
+        let chunks = parse_uf2(&content, None).unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].data, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn uf2_rejects_bad_magic_and_size() {
+        let mut content = uf2_block(0, 0x1000, &[1], 0);
+        content[UF2_BLOCK_SIZE - 1] ^= 0xff;
+        assert!(parse_uf2(&content, None).is_err());
+
+        assert!(parse_uf2(&[0u8; 100], None).is_err());
+    }
+
+    #[test]
+    fn ti_txt_follows_address_records() {
+        let content = b"@F000\n31 40 00 03\nB2 40 80 5A\n@FFFE\n00 F0\nq\n";
+        let chunks = parse_ti_txt(content).unwrap();
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].address, 0xf000);
+        assert_eq!(
+            chunks[0].data,
+            vec![0x31, 0x40, 0x00, 0x03, 0xb2, 0x40, 0x80, 0x5a]
+        );
+        assert_eq!(chunks[1].address, 0xfffe);
+        assert_eq!(chunks[1].data, vec![0x00, 0xf0]);
+    }
+
+    #[test]
+    fn ti_txt_rejects_missing_terminator_and_address() {
+        assert!(parse_ti_txt(b"@F000\n31 40\n").is_err());
+        assert!(parse_ti_txt(b"31 40\nq\n").is_err());
+        assert!(parse_ti_txt(b"@ZZZZ\n31 40\nq\n").is_err());
+    }
+
+    #[test]
+    fn detects_format_from_content() {
+        let uf2 = uf2_block(0, 0x1000, &[1], 0);
+
+        assert_eq!(
+            FirmwareFormat::detect(b"\x7fELF\x01\x01"),
+            FirmwareFormat::Elf
+        );
+        assert_eq!(FirmwareFormat::detect(&uf2), FirmwareFormat::Uf2);
+        assert_eq!(
+            FirmwareFormat::detect(b":020000040800F2\n"),
+            FirmwareFormat::Hex
+        );
+        assert_eq!(
+            FirmwareFormat::detect(SREC.as_bytes()),
+            FirmwareFormat::SRecord
+        );
+        assert_eq!(
+            FirmwareFormat::detect(b"\xef\xbb\xbf\r\n  @F000\n31 40\nq\n"),
+            FirmwareFormat::TiTxt
+        );
+    }
+
+    #[test]
+    fn detect_falls_back_to_bin() {
+        // A UF2 magic in a short file, and bytes that only look like the start of a text format
+        let uf2 = uf2_block(0, 0x1000, &[1], 0);
+
+        assert_eq!(FirmwareFormat::detect(&uf2[..64]), FirmwareFormat::Bin);
+        assert_eq!(FirmwareFormat::detect(b":zz"), FirmwareFormat::Bin);
+        assert_eq!(FirmwareFormat::detect(b"Sx"), FirmwareFormat::Bin);
+        assert_eq!(
+            FirmwareFormat::detect(b"\x00\x20\x00\x20"),
+            FirmwareFormat::Bin
+        );
+        assert_eq!(FirmwareFormat::detect(b""), FirmwareFormat::Bin);
+    }
+
+    #[test]
+    fn type_names_map_to_formats() {
+        assert_eq!(FirmwareFormat::from_type_name("auto").unwrap(), None);
+        assert_eq!(
+            FirmwareFormat::from_type_name("S19").unwrap(),
+            Some(FirmwareFormat::SRecord)
+        );
+        assert!(FirmwareFormat::from_type_name("zip").is_err());
+    }
+}
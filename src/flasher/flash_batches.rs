@@ -0,0 +1,265 @@
+use probe_rs::config::RawFlashAlgorithm;
+
+use super::firmware_parser::{push_chunk, FirmwareChunk};
+
+// probe-rs cannot be interrupted inside a commit, so a cancel request waits for at most this
+// much. Every batch loads the flash algorithm again, so they are kept large.
+const BATCH_SIZE: usize = 256 * 1024;
+
+/// Image data split into groups of equally sized sectors, each batch is committed by its own
+/// loader and cancellation is checked in between. Groups bigger than `BATCH_SIZE` are split
+/// too, but a sector never spans two batches, committing the second one would erase what the
+/// first one programmed.
+#[derive(Debug, Default)]
+pub struct FlashBatches {
+    pub batches: Vec<Vec<FirmwareChunk>>,
+    /// Size of the sectors holding data
+    pub erase_bytes: u32,
+    /// Size of the pages programmed
+    pub program_bytes: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Sector {
+    /// Flash algorithm and sector description the sector belongs to
+    group: (usize, usize),
+    start: u32,
+    size: u32,
+    page_size: u32,
+}
+
+// Sector containing `address`, None outside the flash algorithms, e.g. in RAM
+fn sector_at(algorithms: &[RawFlashAlgorithm], address: u32) -> Option<Sector> {
+    algorithms
+        .iter()
+        .enumerate()
+        .find_map(|(algorithm_idx, algorithm)| {
+            let properties = &algorithm.flash_properties;
+            if !properties.address_range.contains(&address) {
+                return None;
+            }
+
+            let offset = address - properties.address_range.start;
+            let (group, sector) = properties
+                .sectors
+                .iter()
+                .enumerate()
+                .rfind(|(_, sector)| sector.address <= offset && sector.size > 0)?;
+            let index = (offset - sector.address) / sector.size;
+
+            Some(Sector {
+                group: (algorithm_idx, group),
+                start: properties.address_range.start + sector.address + index * sector.size,
+                size: sector.size,
+                page_size: properties.page_size.max(1),
+            })
+        })
+}
+
+impl FlashBatches {
+    /// With `whole_sectors` every page of a sector holding data is programmed, as probe-rs
+    /// does when it keeps unwritten bytes
+    pub fn split(
+        algorithms: &[RawFlashAlgorithm],
+        chunks: &[FirmwareChunk],
+        whole_sectors: bool,
+    ) -> FlashBatches {
+        let mut chunks = chunks.to_vec();
+        chunks.sort_by_key(|chunk| chunk.address);
+
+        let mut plan = FlashBatches::default();
+        let mut batch: Vec<FirmwareChunk> = Vec::new();
+        let mut batch_bytes = 0;
+        let mut last_sector: Option<Sector> = None;
+        let mut last_page: Option<u32> = None;
+
+        for chunk in chunks.iter() {
+            let mut address = chunk.address;
+            let mut data = &chunk.data[..];
+
+            while !data.is_empty() {
+                let sector = sector_at(algorithms, address);
+                let len = match sector {
+                    Some(sector) => {
+                        let end = sector.start as u64 + sector.size as u64;
+                        ((end - address as u64) as usize).min(data.len())
+                    }
+                    None => data.len(),
+                };
+
+                // Only start a new batch where the previous piece's sector ends
+                let group_changed =
+                    sector.map(|sector| sector.group) != last_sector.map(|sector| sector.group);
+                if !batch.is_empty()
+                    && (group_changed || batch_bytes >= BATCH_SIZE)
+                    && (sector.is_none() || sector != last_sector)
+                {
+                    plan.batches.push(std::mem::take(&mut batch));
+                    batch_bytes = 0;
+                }
+
+                if let Some(sector) = sector {
+                    if Some(sector) != last_sector {
+                        plan.erase_bytes += sector.size;
+                    }
+
+                    let page_of =
+                        |address: u32| address - (address - sector.start) % sector.page_size;
+                    let first = page_of(address);
+                    let last = page_of(address + (len as u32 - 1));
+                    let mut pages = (last - first) / sector.page_size + 1;
+                    if last_page == Some(first) {
+                        pages -= 1;
+                    }
+                    plan.program_bytes += pages * sector.page_size;
+                    last_page = Some(last);
+                }
+                last_sector = sector;
+
+                push_chunk(&mut batch, address, &data[..len]);
+                batch_bytes += len;
+                address = address.wrapping_add(len as u32);
+                data = &data[len..];
+            }
+        }
+
+        if !batch.is_empty() {
+            plan.batches.push(batch);
+        }
+        if whole_sectors {
+            plan.program_bytes = plan.erase_bytes;
+        }
+
+        plan
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use probe_rs::config::{FlashProperties, SectorDescription};
+
+    use super::*;
+
+    // 64 KiB of 1 KiB sectors with 256 byte pages, then 4 KiB sectors up to 1 MiB
+    fn algorithms() -> Vec<RawFlashAlgorithm> {
+        vec![RawFlashAlgorithm {
+            flash_properties: FlashProperties {
+                address_range: 0x0800_0000..0x0810_0000,
+                page_size: 256,
+                sectors: vec![
+                    SectorDescription {
+                        address: 0,
+                        size: 1024,
+                    },
+                    SectorDescription {
+                        address: 0x1_0000,
+                        size: 4096,
+                    },
+                ],
+                ..FlashProperties::default()
+            },
+            ..RawFlashAlgorithm::default()
+        }]
+    }
+
+    fn chunk(address: u32, len: usize) -> FirmwareChunk {
+        FirmwareChunk {
+            address,
+            data: vec![0xa5; len],
+        }
+    }
+
+    fn batch_ranges(plan: &FlashBatches) -> Vec<Vec<(u32, usize)>> {
+        plan.batches
+            .iter()
+            .map(|batch| {
+                batch
+                    .iter()
+                    .map(|chunk| (chunk.address, chunk.data.len()))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splits_images_per_sector_group() {
+        let plan = FlashBatches::split(&algorithms(), &[chunk(0x0800_0000, 96 * 1024)], false);
+
+        assert_eq!(
+            batch_ranges(&plan),
+            vec![
+                vec![(0x0800_0000, 64 * 1024)],
+                vec![(0x0801_0000, 32 * 1024)],
+            ]
+        );
+        assert_eq!(plan.erase_bytes, 96 * 1024);
+        assert_eq!(plan.program_bytes, 96 * 1024);
+    }
+
+    #[test]
+    fn splits_large_groups_at_sector_boundaries() {
+        let plan = FlashBatches::split(&algorithms(), &[chunk(0x0801_0000, 600 * 1024)], false);
+
+        assert_eq!(
+            batch_ranges(&plan),
+            vec![
+                vec![(0x0801_0000, 256 * 1024)],
+                vec![(0x0805_0000, 256 * 1024)],
+                vec![(0x0809_0000, 88 * 1024)],
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_a_sector_in_one_batch() {
+        // The batch is full after the first chunk, which ends inside the sector the second starts in
+        let plan = FlashBatches::split(
+            &algorithms(),
+            &[
+                chunk(0x0805_1000, 16),
+                chunk(0x0805_0200, 16),
+                chunk(0x0801_0100, 256 * 1024),
+            ],
+            false,
+        );
+
+        assert_eq!(
+            batch_ranges(&plan),
+            vec![
+                vec![(0x0801_0100, 256 * 1024), (0x0805_0200, 16)],
+                vec![(0x0805_1000, 16)],
+            ]
+        );
+        assert_eq!(plan.erase_bytes, 66 * 4096);
+    }
+
+    #[test]
+    fn counts_sectors_and_pages_once() {
+        // Two chunks in one 256 byte page, one more in the 4 KiB sectors
+        let plan = FlashBatches::split(
+            &algorithms(),
+            &[
+                chunk(0x0800_0010, 16),
+                chunk(0x0800_0080, 16),
+                chunk(0x0801_0100, 300),
+            ],
+            false,
+        );
+
+        assert_eq!(plan.batches.len(), 2);
+        assert_eq!(plan.erase_bytes, 1024 + 4096);
+        assert_eq!(plan.program_bytes, 256 + 2 * 256);
+
+        let plan = FlashBatches::split(&algorithms(), &[chunk(0x0801_0100, 300)], true);
+        assert_eq!(plan.program_bytes, 4096);
+    }
+
+    #[test]
+    fn passes_data_outside_flash_through() {
+        let plan = FlashBatches::split(&algorithms(), &[chunk(0x2000_0000, 64 * 1024)], false);
+
+        assert_eq!(batch_ranges(&plan), vec![vec![(0x2000_0000, 64 * 1024)]]);
+        assert_eq!(plan.erase_bytes, 0);
+        assert_eq!(plan.program_bytes, 0);
+    }
+}
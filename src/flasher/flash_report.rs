@@ -0,0 +1,222 @@
+use std::{
+    cell::RefCell,
+    ops::Range,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+use probe_rs::{
+    config::MemoryRegion,
+    flashing::{FlashProgress, ProgressEvent},
+};
+use serde::Serialize;
+
+use crate::common::sha256::sha256;
+
+use super::firmware_parser::FirmwareFormat;
+
+pub const IMAGE_CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FlashProbeReport {
+    pub name: String,
+    pub vid: u16,
+    pub pid: u16,
+    pub serial_num: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FlashRegionReport {
+    pub start: u32,
+    pub end: u32,
+    pub erased_bytes: u32,
+    pub programmed_bytes: u32,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct FlashPhaseTimes {
+    pub attach_ms: f64,
+    pub load_ms: f64,
+    pub fill_ms: f64,
+    pub erase_ms: f64,
+    pub program_ms: f64,
+    pub verify_ms: f64,
+    pub total_ms: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FlashReport {
+    pub target_name: String,
+    pub format: FirmwareFormat,
+    pub probe: FlashProbeReport,
+    pub speed_khz: u32,
+    pub regions: Vec<FlashRegionReport>,
+    pub timing: FlashPhaseTimes,
+    pub image_size: usize,
+    pub image_crc32: u32,
+    pub image_sha256: String,
+    pub verified: bool,
+}
+
+pub fn to_millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+pub fn image_digest(content: &[u8]) -> (u32, String) {
+    let sha = sha256(content)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+
+    (IMAGE_CRC.checksum(content), sha)
+}
+
+#[derive(Default)]
+struct RecorderState {
+    // NVM regions of the target, a report region never spans two of them
+    nvm: Vec<Range<u32>>,
+    regions: Vec<FlashRegionReport>,
+    phase_start: Option<Instant>,
+    // probe-rs verifies right after programming, without an event of its own
+    programmed_at: Option<Instant>,
+    fill: Duration,
+    erase: Duration,
+    program: Duration,
+    verify: Duration,
+}
+
+impl RecorderState {
+    fn finish_phase(&mut self) -> Duration {
+        self.phase_start
+            .take()
+            .map(|start| start.elapsed())
+            .unwrap_or_default()
+    }
+
+    fn finish_verify(&mut self) {
+        if let Some(at) = self.programmed_at.take() {
+            self.verify += at.elapsed();
+        }
+    }
+}
+
+/// Collects programmed regions and phase timing from probe-rs progress events
+#[derive(Clone)]
+pub struct FlashRecorder {
+    state: Rc<RefCell<RecorderState>>,
+}
+
+impl FlashRecorder {
+    pub fn new(memory_map: &[MemoryRegion]) -> FlashRecorder {
+        let nvm = memory_map
+            .iter()
+            .filter_map(|region| match region {
+                MemoryRegion::Nvm(region) => Some(region.range.clone()),
+                _ => None,
+            })
+            .collect();
+
+        FlashRecorder {
+            state: Rc::new(RefCell::new(RecorderState {
+                nvm,
+                ..RecorderState::default()
+            })),
+        }
+    }
+
+    pub fn progress(&self) -> FlashProgress {
+        self.progress_with(|_| ())
+    }
+
+    /// Same as `progress`, also handing every event to `observer` once it is recorded
+    pub fn progress_with(&self, observer: impl Fn(&ProgressEvent) + 'static) -> FlashProgress {
+        let state = self.state.clone();
+        FlashProgress::new(move |event| {
+            let mut state = state.borrow_mut();
+            match &event {
+                ProgressEvent::Initialized { flash_layout } => {
+                    // The previous batch of the same image is verified by now
+                    state.finish_verify();
+
+                    let sectors = flash_layout.sectors();
+                    let start = sectors.first().map(|sector| sector.address());
+                    let end = sectors
+                        .last()
+                        .map(|sector| sector.address() + sector.size());
+                    let programmed_bytes = flash_layout
+                        .data_blocks()
+                        .iter()
+                        .map(|block| block.size())
+                        .sum();
+
+                    // Every batch of the image brings its own layout, merge those of one NVM region
+                    let nvm = start.and_then(|start| {
+                        state
+                            .nvm
+                            .iter()
+                            .find(|range| range.contains(&start))
+                            .cloned()
+                    });
+                    match (state.regions.last_mut(), nvm, end) {
+                        (Some(region), Some(nvm), Some(end)) if nvm.contains(&region.start) => {
+                            region.end = end;
+                            region.programmed_bytes += programmed_bytes;
+                        }
+                        _ => state.regions.push(FlashRegionReport {
+                            start: start.unwrap_or_default(),
+                            end: end.unwrap_or_default(),
+                            erased_bytes: 0,
+                            programmed_bytes,
+                        }),
+                    }
+                }
+                ProgressEvent::StartedFilling
+                | ProgressEvent::StartedErasing
+                | ProgressEvent::StartedProgramming => state.phase_start = Some(Instant::now()),
+                ProgressEvent::SectorErased { size, .. } => {
+                    if let Some(region) = state.regions.last_mut() {
+                        region.erased_bytes += size;
+                    }
+                }
+                ProgressEvent::FinishedFilling | ProgressEvent::FailedFilling => {
+                    let elapsed = state.finish_phase();
+                    state.fill += elapsed;
+                }
+                ProgressEvent::FinishedErasing | ProgressEvent::FailedErasing => {
+                    let elapsed = state.finish_phase();
+                    state.erase += elapsed;
+                }
+                ProgressEvent::FinishedProgramming | ProgressEvent::FailedProgramming => {
+                    let elapsed = state.finish_phase();
+                    state.program += elapsed;
+                    state.programmed_at = Some(Instant::now());
+                }
+                _ => (),
+            }
+            drop(state);
+
+            observer(&event);
+        })
+    }
+
+    pub fn regions(&self) -> Vec<FlashRegionReport> {
+        self.state.borrow().regions.clone()
+    }
+
+    pub fn phase_times(&self) -> (Duration, Duration, Duration) {
+        let state = self.state.borrow();
+        (state.fill, state.erase, state.program)
+    }
+
+    /// Time spent after programming each batch, which is when probe-rs verifies if asked to
+    pub fn verify_time(&self) -> Duration {
+        let mut state = self.state.borrow_mut();
+        state.finish_verify();
+        state.verify
+    }
+}
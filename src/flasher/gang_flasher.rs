@@ -0,0 +1,346 @@
+use std::{
+    cell::Cell,
+    thread,
+    time::{Duration, Instant},
+};
+
+use napi::{
+    threadsafe_function::{ThreadSafeCallContext, ThreadsafeFunction, ThreadsafeFunctionCallMode},
+    CallContext, JsFunction, JsObject, JsUnknown, Task,
+};
+use probe_rs::flashing::ProgressEvent;
+use serde::{Deserialize, Serialize};
+
+use crate::common::{
+    cancel_token::CancelToken,
+    connect_options::{AttachMode, ConnectOptions},
+    probe_selector::ProbeSelector,
+};
+
+use super::{
+    firmware_image::FirmwareImage,
+    firmware_parser::FirmwareFormat,
+    flash_batches::FlashBatches,
+    flash_report::{to_millis, FlashPhaseTimes, FlashProbeReport, FlashRecorder, FlashReport},
+    generic_flasher::{check_cancelled, commit_image, FlashOptions, DEFAULT_FLASH_TIMEOUT_MS},
+};
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GangFlashOptions {
+    probes: Vec<ProbeSelector>,
+    image: String,
+    target_name: String,
+    format: Option<String>,
+    skip_erase: Option<bool>,
+    #[serde(flatten)]
+    flash: FlashOptions,
+    #[serde(flatten)]
+    connect: ConnectOptions,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum GangFlashPhase {
+    Attaching,
+    Erasing,
+    Programming,
+    Verifying,
+    Done,
+    Failed,
+}
+
+/// Progress of one probe, `bytes` and `totalBytes` count within the current phase
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GangFlashProgress {
+    index: usize,
+    phase: GangFlashPhase,
+    bytes: u32,
+    total_bytes: u32,
+    error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GangFlashResult {
+    index: usize,
+    probe: ProbeSelector,
+    ok: bool,
+    report: Option<FlashReport>,
+    error: Option<String>,
+}
+
+// Forwards the progress of one worker to the JS callback, if there is one
+#[derive(Clone)]
+struct ProgressSink {
+    index: usize,
+    callback: Option<ThreadsafeFunction<GangFlashProgress>>,
+}
+
+impl ProgressSink {
+    fn emit(&self, phase: GangFlashPhase, bytes: u32, total_bytes: u32, error: Option<String>) {
+        if let Some(callback) = &self.callback {
+            callback.call(
+                Ok(GangFlashProgress {
+                    index: self.index,
+                    phase,
+                    bytes,
+                    total_bytes,
+                    error,
+                }),
+                ThreadsafeFunctionCallMode::NonBlocking,
+            );
+        }
+    }
+}
+
+// Everything the workers share, the image is parsed once before any of them starts
+struct GangJob {
+    image: FirmwareImage,
+    target_name: String,
+    skip_erase: bool,
+    verify: bool,
+    connect: ConnectOptions,
+    cancel: CancelToken,
+    timeout: Duration,
+}
+
+impl GangJob {
+    fn flash_probe(
+        &self,
+        selector: &ProbeSelector,
+        sink: &ProgressSink,
+    ) -> napi::Result<FlashReport> {
+        let started_at = Instant::now();
+        let cancel = self.cancel.clone().with_timeout(self.timeout);
+        sink.emit(GangFlashPhase::Attaching, 0, 0, None);
+        cancel.check()?;
+
+        let selector = selector.resolve()?;
+        let _lock = self.connect.lock(&selector, &cancel)?;
+        let connection = self.connect.connect(
+            selector.clone(),
+            self.target_name.clone(),
+            AttachMode::ConnectUnderReset,
+        )?;
+
+        let probe_report = FlashProbeReport {
+            name: connection.probe_name,
+            vid: selector.vendor_id,
+            pid: selector.product_id,
+            serial_num: selector.serial_number,
+        };
+        let speed_khz = connection.speed_khz;
+        let mut session = connection.session;
+
+        let attach_time = started_at.elapsed();
+        check_cancelled(&cancel, &mut session)?;
+
+        let batches = FlashBatches::split(
+            &session.target().flash_algorithms,
+            &self.image.chunks,
+            self.skip_erase,
+        );
+
+        let load_time = started_at.elapsed() - attach_time;
+        check_cancelled(&cancel, &mut session)?;
+
+        let recorder = FlashRecorder::new(&session.target().memory_map);
+        let progress = {
+            let sink = sink.clone();
+            let verify = self.verify;
+            let (erase_total, program_total) = (batches.erase_bytes, batches.program_bytes);
+            // Counted over all batches, each of them erases and programs in turn
+            let erased = Cell::new(0u32);
+            let programmed = Cell::new(0u32);
+            recorder.progress_with(move |event| match event {
+                ProgressEvent::StartedErasing => {
+                    sink.emit(GangFlashPhase::Erasing, erased.get(), erase_total, None);
+                }
+                ProgressEvent::SectorErased { size, .. } => {
+                    erased.set(erased.get() + size);
+                    sink.emit(GangFlashPhase::Erasing, erased.get(), erase_total, None);
+                }
+                ProgressEvent::StartedProgramming => {
+                    let done = programmed.get();
+                    sink.emit(GangFlashPhase::Programming, done, program_total, None);
+                }
+                ProgressEvent::PageProgrammed { size, .. } => {
+                    programmed.set(programmed.get() + size);
+                    let done = programmed.get();
+                    sink.emit(GangFlashPhase::Programming, done, program_total, None);
+                }
+                ProgressEvent::FinishedProgramming if verify => {
+                    let done = programmed.get();
+                    sink.emit(GangFlashPhase::Verifying, done, program_total, None);
+                }
+                _ => (),
+            })
+        };
+
+        let verify_time = commit_image(
+            &mut session,
+            &batches,
+            &recorder,
+            &progress,
+            self.skip_erase,
+            self.verify,
+            &cancel,
+        )?;
+
+        let (fill_time, erase_time, program_time) = recorder.phase_times();
+
+        Ok(FlashReport {
+            target_name: self.target_name.clone(),
+            format: self.image.format,
+            probe: probe_report,
+            speed_khz,
+            regions: recorder.regions(),
+            timing: FlashPhaseTimes {
+                attach_ms: to_millis(attach_time),
+                load_ms: to_millis(load_time),
+                fill_ms: to_millis(fill_time),
+                erase_ms: to_millis(erase_time),
+                program_ms: to_millis(program_time),
+                verify_ms: to_millis(verify_time),
+                total_ms: to_millis(started_at.elapsed()),
+            },
+            image_size: self.image.size,
+            image_crc32: self.image.crc32,
+            image_sha256: self.image.sha256.clone(),
+            verified: self.verify,
+        })
+    }
+}
+
+pub struct GangFlasherTask {
+    image_path: String,
+    format: Option<FirmwareFormat>,
+    uf2_family_id: Option<u32>,
+    target_name: String,
+    skip_erase: bool,
+    verify: bool,
+    connect: ConnectOptions,
+    cancel: CancelToken,
+    timeout: Duration,
+    probes: Vec<ProbeSelector>,
+    progress: Option<ThreadsafeFunction<GangFlashProgress>>,
+}
+
+impl Task for GangFlasherTask {
+    type Output = Vec<GangFlashResult>;
+    type JsValue = JsUnknown;
+
+    fn compute(&mut self) -> napi::Result<Self::Output> {
+        let job = GangJob {
+            image: FirmwareImage::load(&self.image_path, self.format, self.uf2_family_id)?,
+            target_name: self.target_name.clone(),
+            skip_erase: self.skip_erase,
+            verify: self.verify,
+            connect: self.connect.clone(),
+            cancel: self.cancel.clone(),
+            timeout: self.timeout,
+        };
+        let job = &job;
+
+        // One worker per probe, a failing probe only ends its own worker
+        thread::scope(|scope| {
+            let workers: Vec<_> = self
+                .probes
+                .iter()
+                .enumerate()
+                .map(|(index, selector)| {
+                    let sink = ProgressSink {
+                        index,
+                        callback: self.progress.clone(),
+                    };
+                    scope.spawn(move || {
+                        let result = job.flash_probe(selector, &sink);
+                        match &result {
+                            Ok(_) => sink.emit(GangFlashPhase::Done, 0, 0, None),
+                            Err(err) => {
+                                sink.emit(GangFlashPhase::Failed, 0, 0, Some(err.reason.clone()))
+                            }
+                        }
+
+                        result
+                    })
+                })
+                .collect();
+
+            workers
+                .into_iter()
+                .enumerate()
+                .map(|(index, worker)| {
+                    let result = worker.join().unwrap_or_else(|_| {
+                        Err(napi::Error {
+                            status: napi::Status::Unknown,
+                            reason: "Flasher thread panicked".to_string(),
+                        })
+                    });
+
+                    Ok(GangFlashResult {
+                        index,
+                        probe: self.probes[index].clone(),
+                        ok: result.is_ok(),
+                        error: result.as_ref().err().map(|err| err.reason.clone()),
+                        report: result.ok(),
+                    })
+                })
+                .collect()
+        })
+    }
+
+    fn resolve(self, env: napi::Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+        env.to_js_value(&output)
+    }
+
+    fn reject(self, _env: napi::Env, err: napi::Error) -> napi::Result<Self::JsValue> {
+        Err(err)
+    }
+}
+
+#[js_function(2)]
+pub fn flash_many(ctx: CallContext) -> napi::Result<JsObject> {
+    let options: GangFlashOptions = ctx.env.from_js_value(ctx.get::<JsObject>(0)?)?;
+    options.connect.validate()?;
+    let flash = options.flash;
+    let format = match &options.format {
+        Some(name) => FirmwareFormat::from_type_name(name)?,
+        None => None,
+    };
+
+    if options.probes.is_empty() {
+        return Err(napi::Error {
+            status: napi::Status::InvalidArg,
+            reason: "No probes given to flash".to_string(),
+        });
+    }
+
+    let progress = match ctx.try_get::<JsFunction>(1)? {
+        napi::Either::A(func) => Some(ctx.env.create_threadsafe_function(
+            &func,
+            0,
+            |ctx: ThreadSafeCallContext<GangFlashProgress>| {
+                Ok(vec![ctx.env.to_js_value(&ctx.value)?])
+            },
+        )?),
+        napi::Either::B(_) => None,
+    };
+
+    let task = GangFlasherTask {
+        image_path: options.image,
+        format,
+        uf2_family_id: flash.uf2_family_id,
+        target_name: options.target_name,
+        skip_erase: options.skip_erase.unwrap_or(false),
+        verify: flash.verify.unwrap_or(true),
+        connect: options.connect,
+        cancel: CancelToken::from_id(flash.cancel_token)?,
+        timeout: Duration::from_millis(flash.timeout_ms.unwrap_or(DEFAULT_FLASH_TIMEOUT_MS)),
+        probes: options.probes,
+        progress,
+    };
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
@@ -1,63 +1,141 @@
-use std::{fs::File, u32};
+use std::{
+    time::{Duration, Instant},
+    u32,
+};
 
-use napi::{CallContext, JsBoolean, JsNumber, JsObject, JsString, JsUndefined, Task};
+use napi::{CallContext, JsBoolean, JsNumber, JsObject, JsString, JsUnknown, Task};
 use probe_rs::{
-    flashing::{BinOptions, DownloadOptions, FileDownloadError, FlashLoader},
-    DebugProbeSelector, Probe,
+    flashing::{DownloadOptions, FileDownloadError, FlashLoader, FlashProgress},
+    Session,
+};
+use serde::Deserialize;
+
+use crate::common::{
+    cancel_token::CancelToken,
+    connect_options::{AttachMode, ConnectOptions},
+    probe_selector::{probe_selector_from_args, ProbeSelector},
 };
 
+use super::{
+    firmware_image::FirmwareImage,
+    firmware_parser::FirmwareFormat,
+    flash_batches::FlashBatches,
+    flash_report::{to_millis, FlashPhaseTimes, FlashProbeReport, FlashRecorder, FlashReport},
+};
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct FlashOptions {
+    pub(super) uf2_family_id: Option<u32>,
+    pub(super) verify: Option<bool>,
+    pub(super) cancel_token: Option<u32>,
+    pub(super) timeout_ms: Option<u64>,
+}
+
 pub struct GenericFlasherTask {
-    probe_sn: Option<String>,
-    firmware_type: String,
-    speed_khz: Option<u32>,
-    probe_vid: u16,
-    probe_pid: u16,
+    probe: ProbeSelector,
+    firmware_format: Option<FirmwareFormat>,
     target_name: String,
     firmware_path: String,
     skip_erase: bool,
+    options: FlashOptions,
+    connect: ConnectOptions,
+    cancel: CancelToken,
+    timeout: Duration,
 }
 
-impl Task for GenericFlasherTask {
-    type Output = ();
-    type JsValue = JsUndefined;
+pub(super) const DEFAULT_FLASH_TIMEOUT_MS: u64 = 300_000;
 
-    fn compute(&mut self) -> napi::Result<Self::Output> {
-        let mut probe = match Probe::open(DebugProbeSelector {
-            product_id: self.probe_pid,
-            vendor_id: self.probe_vid,
-            serial_number: self.probe_sn.clone(),
-        }) {
-            Ok(p) => p,
-            Err(err) => {
-                return Err(napi::Error {
-                    reason: format!("Failed to open probe: {}", err),
-                    status: napi::Status::Unknown,
-                })
-            }
-        };
+// Leave a cancelled or timed out target halted in reset, rather than running a half-written image
+pub(super) fn check_cancelled(cancel: &CancelToken, session: &mut Session) -> napi::Result<()> {
+    if let Err(err) = cancel.check() {
+        if let Ok(mut core) = session.core(0) {
+            let _ = core.reset_and_halt(Duration::from_millis(100));
+        }
 
-        match probe.detach() {
-            Ok(_) => (),
-            Err(err) => {
+        return Err(err.into());
+    }
+
+    Ok(())
+}
+
+/// Erases and programs the image one batch at a time, probe-rs reads each one back if asked to.
+/// Returns the time spent verifying.
+pub(super) fn commit_image(
+    session: &mut Session,
+    batches: &FlashBatches,
+    recorder: &FlashRecorder,
+    progress: &FlashProgress,
+    skip_erase: bool,
+    verify: bool,
+    cancel: &CancelToken,
+) -> napi::Result<Duration> {
+    for batch in batches.batches.iter() {
+        check_cancelled(cancel, session)?;
+
+        let mut loader = FlashLoader::new(
+            session.target().memory_map.clone(),
+            probe_rs::config::TargetDescriptionSource::BuiltIn,
+        );
+        for chunk in batch.iter() {
+            if let Err(err) = loader.add_data(chunk.address, &chunk.data) {
                 return Err(napi::Error {
-                    reason: format!("Failed to detach probe: {}", err),
+                    reason: format!("Failed to open firmware: {}", err),
                     status: napi::Status::Unknown,
-                })
+                });
             }
-        };
+        }
 
-        match probe.set_speed(self.speed_khz.unwrap_or(1800)) {
+        let mut option = DownloadOptions::new();
+        option.progress = Some(progress);
+        option.verify = verify;
+
+        if skip_erase {
+            option.keep_unwritten_bytes = true;
+            option.skip_erase = true;
+        }
+
+        match loader
+            // TODO: hand out chip erase flag
+            .commit(session, option)
+            .map_err(FileDownloadError::Flash)
+        {
             Ok(_) => (),
             Err(err) => {
                 return Err(napi::Error {
-                    reason: format!("Failed to set speed: {}", err),
+                    reason: format!("Failed to download firmware: {}", err),
                     status: napi::Status::Unknown,
                 })
             }
         }
+    }
+
+    check_cancelled(cancel, session)?;
+
+    Ok(match verify {
+        true => recorder.verify_time(),
+        false => Duration::ZERO,
+    })
+}
+
+impl Task for GenericFlasherTask {
+    type Output = FlashReport;
+    type JsValue = JsUnknown;
 
-        let mut session = match probe.attach_under_reset(self.target_name.clone()) {
-            Ok(s) => s,
+    fn compute(&mut self) -> napi::Result<Self::Output> {
+        let started_at = Instant::now();
+        self.cancel = self.cancel.clone().with_timeout(self.timeout);
+        self.cancel.check()?;
+
+        let selector = self.probe.resolve()?;
+        let _lock = self.connect.lock(&selector, &self.cancel)?;
+
+        let connection = match self.connect.connect(
+            selector.clone(),
+            self.target_name.clone(),
+            AttachMode::ConnectUnderReset,
+        ) {
+            Ok(c) => c,
             Err(err) => {
                 return Err(napi::Error {
                     reason: format!("Failed to open session: {}", err),
@@ -66,8 +144,20 @@ impl Task for GenericFlasherTask {
             }
         };
 
-        let mut file = match File::open(self.firmware_path.clone()) {
-            Ok(file) => file,
+        let probe_report = FlashProbeReport {
+            name: connection.probe_name,
+            vid: selector.vendor_id,
+            pid: selector.product_id,
+            serial_num: selector.serial_number,
+        };
+        let speed_khz = connection.speed_khz;
+        let mut session = connection.session;
+
+        let attach_time = started_at.elapsed();
+        check_cancelled(&self.cancel, &mut session)?;
+
+        let content = match std::fs::read(self.firmware_path.clone()) {
+            Ok(content) => content,
             Err(err) => {
                 return Err(napi::Error {
                     reason: format!("Failed to open file: {}", err),
@@ -76,65 +166,67 @@ impl Task for GenericFlasherTask {
             }
         };
 
-        // IMPORTANT: Change this to an actual memory map of a real chip
-        let memory_map = session.target().memory_map.clone();
-        let mut loader = FlashLoader::new(
-            memory_map,
-            probe_rs::config::TargetDescriptionSource::BuiltIn,
-        );
-
-        let download_result = match self.firmware_type.as_str() {
-            "bin" | "Bin" | "BIN" => loader.load_bin_data(
-                &mut file,
-                BinOptions {
-                    base_address: Some(0x08000000), // TODO: change to a parameter instead of hard-coded here
-                    skip: 0,
-                },
-            ),
-
-            "hex" | "IHex" | "Hex" | "ihex" | "HEX" => loader.load_hex_data(&mut file),
-            "elf" | "Elf" | "ELF" => loader.load_elf_data(&mut file),
-            _ => Err(FileDownloadError::Object("Not a valid Bin/Hex/Elf file")),
-        };
-
-        match download_result {
-            Ok(_) => (),
+        let image = match FirmwareImage::parse(
+            &content,
+            self.firmware_format,
+            self.options.uf2_family_id,
+        ) {
+            Ok(image) => image,
             Err(err) => {
                 return Err(napi::Error {
                     reason: format!("Failed to open firmware: {}", err),
                     status: napi::Status::Unknown,
                 })
             }
-        }
+        };
+        let batches = FlashBatches::split(
+            &session.target().flash_algorithms,
+            &image.chunks,
+            self.skip_erase,
+        );
 
-        let mut option = DownloadOptions::new();
-        option.verify = true;
+        let load_time = started_at.elapsed() - attach_time;
+        check_cancelled(&self.cancel, &mut session)?;
 
-        if self.skip_erase {
-            option.keep_unwritten_bytes = true;
-            option.skip_erase = true;
-        }
+        let recorder = FlashRecorder::new(&session.target().memory_map);
+        let progress = recorder.progress();
+        let verified = self.options.verify.unwrap_or(true);
+        let verify_time = commit_image(
+            &mut session,
+            &batches,
+            &recorder,
+            &progress,
+            self.skip_erase,
+            verified,
+            &self.cancel,
+        )?;
 
-        match loader
-            // TODO: hand out chip erase flag
-            .commit(&mut session, option)
-            .map_err(FileDownloadError::Flash)
-        {
-            Ok(_) => (),
-            Err(err) => {
-                return Err(napi::Error {
-                    reason: format!("Failed to download firmware: {}", err),
-                    status: napi::Status::Unknown,
-                })
-            }
-        }
+        let (fill_time, erase_time, program_time) = recorder.phase_times();
 
-        Ok(())
+        Ok(FlashReport {
+            target_name: self.target_name.clone(),
+            format: image.format,
+            probe: probe_report,
+            speed_khz,
+            regions: recorder.regions(),
+            timing: FlashPhaseTimes {
+                attach_ms: to_millis(attach_time),
+                load_ms: to_millis(load_time),
+                fill_ms: to_millis(fill_time),
+                erase_ms: to_millis(erase_time),
+                program_ms: to_millis(program_time),
+                verify_ms: to_millis(verify_time),
+                total_ms: to_millis(started_at.elapsed()),
+            },
+            image_size: image.size,
+            image_crc32: image.crc32,
+            image_sha256: image.sha256,
+            verified,
+        })
     }
 
-    fn resolve(self, env: napi::Env, _output: Self::Output) -> napi::Result<Self::JsValue> {
-        // Does nothing?
-        env.get_undefined()
+    fn resolve(self, env: napi::Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+        env.to_js_value(&output)
     }
 
     fn reject(self, _env: napi::Env, err: napi::Error) -> napi::Result<Self::JsValue> {
@@ -142,13 +234,15 @@ impl Task for GenericFlasherTask {
     }
 }
 
-#[js_function(8)]
+#[js_function(9)]
 pub fn flash_firmware_file(ctx: CallContext) -> napi::Result<JsObject> {
     let firmware_path = ctx.get::<JsString>(0)?.into_utf8()?.as_str()?.to_string();
     let target_name = ctx.get::<JsString>(1)?.into_utf8()?.as_str()?.to_string();
-    let firmware_type = ctx.get::<JsString>(2)?.into_utf8()?.as_str()?.to_string();
-    let vid = ctx.get::<JsNumber>(3)?.get_int32()?;
-    let pid = ctx.get::<JsNumber>(4)?.get_int32()?;
+    let firmware_format = match ctx.try_get::<JsString>(2)? {
+        napi::Either::A(fw_type) => FirmwareFormat::from_type_name(fw_type.into_utf8()?.as_str()?)?,
+        napi::Either::B(_) => None,
+    };
+    let probe = probe_selector_from_args(&ctx, 3, 4, 7)?;
     let skip_erase = match ctx.try_get::<JsBoolean>(5)? {
         napi::Either::A(erase) => erase.get_value().unwrap_or(false),
         napi::Either::B(_) => false,
@@ -158,27 +252,28 @@ pub fn flash_firmware_file(ctx: CallContext) -> napi::Result<JsObject> {
         napi::Either::A(sn) => Some(sn.get_uint32().unwrap_or(1800)),
         napi::Either::B(_) => None,
     };
-    let serial_num = match ctx.try_get::<JsString>(7)? {
-        napi::Either::A(sn) => Some(sn.into_utf8()?.as_str()?.to_string()),
-        napi::Either::B(_) => None,
+    let options: FlashOptions = match ctx.try_get::<JsObject>(8)? {
+        napi::Either::A(obj) => ctx.env.from_js_value(obj)?,
+        napi::Either::B(_) => FlashOptions::default(),
     };
-
-    if vid > u16::MAX as i32 || pid > u16::MAX as i32 {
-        return Err(napi::Error {
-            status: napi::Status::InvalidArg,
-            reason: "Invalid VID/PID provided".to_string(),
-        });
+    let mut connect = ConnectOptions::from_arg(&ctx, 8)?;
+    // The positional speed predates the options object, which wins when both are given
+    if connect.speed_khz.is_none() {
+        connect.speed_khz = Some(speed_khz.unwrap_or(1800));
     }
+    let cancel = CancelToken::from_id(options.cancel_token)?;
+    let timeout = Duration::from_millis(options.timeout_ms.unwrap_or(DEFAULT_FLASH_TIMEOUT_MS));
 
     let task = GenericFlasherTask {
-        probe_sn: serial_num.clone(),
-        probe_vid: vid as u16,
-        probe_pid: pid as u16,
+        probe,
         target_name: target_name.clone(),
-        firmware_type,
-        speed_khz,
+        firmware_format,
         firmware_path,
         skip_erase,
+        options,
+        connect,
+        cancel,
+        timeout,
     };
     ctx.env.spawn(task).map(|t| t.promise_object())
 }
@@ -1 +1,6 @@
+pub mod firmware_image;
+pub mod firmware_parser;
+pub mod flash_batches;
+pub mod flash_report;
+pub mod gang_flasher;
 pub mod generic_flasher;
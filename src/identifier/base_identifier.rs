@@ -16,4 +16,5 @@ pub struct TargetIdentity {
     #[serde_as(as = "Option<Hex>")]
     pub unique_id: Option<Vec<u8>>,
     pub flash_size: Option<usize>,
+    pub speed_khz: Option<u32>,
 }
@@ -1,13 +1,28 @@
 use std::{collections::HashMap, sync::Mutex, time::Duration};
 
 use lazy_static::lazy_static;
-use napi::{CallContext, JsNumber, JsObject, JsString};
+use napi::{CallContext, JsObject, JsString};
+use serde::Deserialize;
 
-use crate::identifier::stm32l0_identifier::identify_stm32l0;
+use crate::{
+    common::{
+        cancel_token::CancelToken,
+        connect_options::ConnectOptions,
+        probe_selector::{probe_selector_from_args, ProbeSelector},
+    },
+    identifier::stm32l0_identifier::identify_stm32l0,
+};
 
 use super::base_identifier::TargetIdentity;
 
-type IdentifierFn = fn(String, u16, u16, Option<String>) -> napi::Result<TargetIdentity>;
+type IdentifierFn = fn(
+    String,
+    u16,
+    u16,
+    Option<String>,
+    ConnectOptions,
+    CancelToken,
+) -> napi::Result<TargetIdentity>;
 type IdentifierKV = HashMap<String, IdentifierFn>;
 
 lazy_static! {
@@ -18,13 +33,27 @@ lazy_static! {
     };
 }
 
+const DEFAULT_IDENTIFY_TIMEOUT_MS: u64 = 3_000;
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct IdentifyOptions {
+    cancel_token: Option<u32>,
+    timeout_ms: Option<u64>,
+}
+
 async fn identify_with_timeout(
     target_name: String,
-    vid: u16,
-    pid: u16,
-    serial_num: Option<String>,
+    probe: ProbeSelector,
+    connect: ConnectOptions,
+    cancel: CancelToken,
+    timeout: Duration,
 ) -> napi::Result<TargetIdentity> {
+    let cancel = cancel.with_timeout(timeout);
     let handle = tokio::task::spawn_blocking(move || {
+        let selector = probe.resolve()?;
+        let _lock = connect.lock(&selector, &cancel)?;
+
         let result = match IDENTIFIER_MAP.lock() {
             Ok(ret) => ret,
             Err(err) => {
@@ -37,7 +66,14 @@ async fn identify_with_timeout(
 
         for (key, val) in result.iter() {
             if target_name.contains(key) {
-                return Ok(val(target_name.clone(), vid, pid, serial_num.clone())?);
+                return Ok(val(
+                    target_name.clone(),
+                    selector.vendor_id,
+                    selector.product_id,
+                    selector.serial_number.clone(),
+                    connect.clone(),
+                    cancel,
+                )?);
             }
         }
 
@@ -47,43 +83,32 @@ async fn identify_with_timeout(
         })
     });
 
-    if let Ok(result) = tokio::time::timeout(Duration::from_secs(3), handle).await {
-        match result {
-            Ok(ret) => return ret,
-            Err(err) => {
-                return Err(napi::Error {
-                    status: napi::Status::Unknown,
-                    reason: format!("Unexpected failure to join identifier thread: {}", err),
-                })
-            }
-        }
-    } else {
-        return Err(napi::Error {
-            status: napi::Status::GenericFailure,
-            reason: "Identifier is not responding after 3 seconds".to_owned(),
-        });
+    // Like erase and flash, the deadline is checked between steps, the lock goes with the task
+    let joined = handle.await;
+
+    match joined {
+        Ok(ret) => ret,
+        Err(err) => Err(napi::Error {
+            status: napi::Status::Unknown,
+            reason: format!("Unexpected failure to join identifier thread: {}", err),
+        }),
     }
 }
 
-#[js_function(4)]
+#[js_function(5)]
 pub fn identify_target(ctx: CallContext) -> napi::Result<JsObject> {
     let target_name = ctx.get::<JsString>(0)?.into_utf8()?.as_str()?.to_string();
-    let vid = ctx.get::<JsNumber>(1)?.get_int32()?;
-    let pid = ctx.get::<JsNumber>(2)?.get_int32()?;
-    let serial_num = match ctx.try_get::<JsString>(3)? {
-        napi::Either::A(sn) => Some(sn.into_utf8()?.as_str()?.to_string()),
-        napi::Either::B(_) => None,
+    let probe = probe_selector_from_args(&ctx, 1, 2, 3)?;
+    let options: IdentifyOptions = match ctx.try_get::<JsObject>(4)? {
+        napi::Either::A(obj) => ctx.env.from_js_value(obj)?,
+        napi::Either::B(_) => IdentifyOptions::default(),
     };
-
-    if vid > u16::MAX as i32 || pid > u16::MAX as i32 {
-        return Err(napi::Error {
-            status: napi::Status::InvalidArg,
-            reason: "Invalid VID/PID provided".to_string(),
-        });
-    }
+    let connect = ConnectOptions::from_arg(&ctx, 4)?;
+    let cancel = CancelToken::from_id(options.cancel_token)?;
+    let timeout = Duration::from_millis(options.timeout_ms.unwrap_or(DEFAULT_IDENTIFY_TIMEOUT_MS));
 
     ctx.env.execute_tokio_future(
-        identify_with_timeout(target_name, vid as u16, pid as u16, serial_num),
+        identify_with_timeout(target_name, probe, connect, cancel, timeout),
         |&mut env, data| env.to_js_value(&data),
     )
 }
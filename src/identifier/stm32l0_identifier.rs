@@ -1,7 +1,12 @@
-use probe_rs::{DebugProbeSelector, MemoryInterface, Probe};
-use std::time::Duration;
+use probe_rs::{DebugProbeSelector, MemoryInterface, Session};
+use std::{cell::Cell, time::Duration};
 
-use crate::common::{plunger_error::PlungerError, probe_info::ProbeInfo};
+use crate::common::{
+    cancel_token::CancelToken,
+    connect_options::{AttachMode, ConnectOptions},
+    plunger_error::PlungerError,
+    probe_info::ProbeInfo,
+};
 
 use super::base_identifier::{BaseIdentifier, TargetIdentity};
 
@@ -13,10 +18,16 @@ const STM32L0_FL_SIZE: u32 = 0x1ff8007c;
 pub struct STM32L0Identifier {
     probe: DebugProbeSelector,
     target_name: String,
+    connect: ConnectOptions,
+    speed_khz: Cell<Option<u32>>,
 }
 
 impl STM32L0Identifier {
-    pub fn new(probe: &ProbeInfo, target_name: String) -> Result<STM32L0Identifier, PlungerError> {
+    pub fn new(
+        probe: &ProbeInfo,
+        target_name: String,
+        connect: ConnectOptions,
+    ) -> Result<STM32L0Identifier, PlungerError> {
         if !target_name.contains("STM32L0") && !target_name.contains("stm32l0") {
             return Err(PlungerError::InvalidTarget(format!(
                 "Target {} is not STM32L0!",
@@ -26,21 +37,30 @@ impl STM32L0Identifier {
             Ok(STM32L0Identifier {
                 probe: probe.into(),
                 target_name,
+                connect,
+                speed_khz: Cell::new(None),
             })
         }
     }
+
+    pub fn speed_khz(&self) -> Option<u32> {
+        self.speed_khz.get()
+    }
+
+    fn open_session(&self, mode: AttachMode) -> Result<Session, PlungerError> {
+        let connection =
+            self.connect
+                .connect(self.probe.clone(), self.target_name.clone(), mode)?;
+        self.speed_khz.set(Some(connection.speed_khz));
+
+        Ok(connection.session)
+    }
 }
 
 impl BaseIdentifier for STM32L0Identifier {
     fn get_uid(&self) -> Result<Vec<u8>, PlungerError> {
-        println!("Opening probe");
-        let probe = Probe::open(self.probe.clone())?;
-
-        println!("Detaching probe");
-        // probe.detach()?;
-
         println!("Attaching to session");
-        let mut session = probe.attach_under_reset(self.target_name.clone())?;
+        let mut session = self.open_session(AttachMode::ConnectUnderReset)?;
 
         println!("Attaching to core");
         let mut core = session.core(0)?;
@@ -68,11 +88,7 @@ impl BaseIdentifier for STM32L0Identifier {
     }
 
     fn get_flash_size(&self) -> Result<usize, PlungerError> {
-        let mut probe = Probe::open(self.probe.clone())?;
-
-        probe.detach()?;
-
-        let mut session = probe.attach(self.target_name.clone())?;
+        let mut session = self.open_session(AttachMode::Normal)?;
         let mut core = session.core(0)?;
 
         if !core.core_halted()? {
@@ -92,6 +108,8 @@ pub(crate) fn identify_stm32l0(
     vid: u16,
     pid: u16,
     sn: Option<String>,
+    connect: ConnectOptions,
+    cancel: CancelToken,
 ) -> napi::Result<TargetIdentity> {
     let identifier = STM32L0Identifier::new(
         &ProbeInfo {
@@ -100,13 +118,18 @@ pub(crate) fn identify_stm32l0(
             pid,
             probe_type: None,
             short_id: None,
+            ready: None,
         },
         target_name.clone(),
+        connect,
     )?;
+    cancel.check()?;
     let unique_id = Some(identifier.get_uid()?);
+    cancel.check()?;
     let flash_size = Some(identifier.get_flash_size()?);
     Ok(TargetIdentity {
         unique_id,
         flash_size,
+        speed_khz: identifier.speed_khz(),
     })
 }
@@ -8,18 +8,79 @@ mod eraser;
 mod flasher;
 mod identifier;
 mod probe;
+mod session;
+mod svd;
+mod symbols;
 
+use common::cancel_token::{cancel_operation, create_cancel_token, release_cancel_token};
 use eraser::eraser_binding::erase_target;
-use flasher::generic_flasher::flash_firmware_file;
+use flasher::{gang_flasher::flash_many, generic_flasher::flash_firmware_file};
 use identifier::identifier_binding::identify_target;
 use napi::{JsObject, Result};
-use probe::probe_binding::get_all_probes;
+use probe::{
+    probe_binding::get_all_probes,
+    probe_details::get_probe_details,
+    probe_power::{get_target_voltage, set_target_power},
+    probe_watcher::{unwatch_probes, watch_probes},
+};
+use session::{
+    breakpoints::{
+        clear_breakpoint, clear_watchpoint, halt_core, resume_core, set_breakpoint, set_watchpoint,
+        wait_for_halt,
+    },
+    core_dump::capture_core_dump,
+    fault::analyze_fault,
+    gdb_server::{start_gdb_server, stop_gdb_server},
+    memory::{read_memory, write_memory},
+    registers::{read_registers, write_register},
+    rtt::{rtt_write, start_rtt, stop_rtt},
+    semihosting::run_semihosting,
+    session_binding::{close_debug_session, open_debug_session},
+    swo::{start_swo, stop_swo},
+};
+use svd::svd_binding::{read_peripheral, read_register_field};
+use symbols::symbol_binding::lookup_symbol;
 
 #[module_exports]
 fn init(mut exports: JsObject) -> Result<()> {
     exports.create_named_method("eraseTarget", erase_target)?;
     exports.create_named_method("identifyTarget", identify_target)?;
     exports.create_named_method("flashFirmwareFile", flash_firmware_file)?;
+    exports.create_named_method("flashMany", flash_many)?;
     exports.create_named_method("listAllProbes", get_all_probes)?;
+    exports.create_named_method("getProbeDetails", get_probe_details)?;
+    exports.create_named_method("setTargetPower", set_target_power)?;
+    exports.create_named_method("readTargetVoltage", get_target_voltage)?;
+    exports.create_named_method("watchProbes", watch_probes)?;
+    exports.create_named_method("unwatchProbes", unwatch_probes)?;
+    exports.create_named_method("openSession", open_debug_session)?;
+    exports.create_named_method("closeSession", close_debug_session)?;
+    exports.create_named_method("startRtt", start_rtt)?;
+    exports.create_named_method("rttWrite", rtt_write)?;
+    exports.create_named_method("stopRtt", stop_rtt)?;
+    exports.create_named_method("runSemihosting", run_semihosting)?;
+    exports.create_named_method("startSwo", start_swo)?;
+    exports.create_named_method("stopSwo", stop_swo)?;
+    exports.create_named_method("startGdbServer", start_gdb_server)?;
+    exports.create_named_method("stopGdbServer", stop_gdb_server)?;
+    exports.create_named_method("readRegisters", read_registers)?;
+    exports.create_named_method("writeRegister", write_register)?;
+    exports.create_named_method("analyzeFault", analyze_fault)?;
+    exports.create_named_method("setBreakpoint", set_breakpoint)?;
+    exports.create_named_method("clearBreakpoint", clear_breakpoint)?;
+    exports.create_named_method("setWatchpoint", set_watchpoint)?;
+    exports.create_named_method("clearWatchpoint", clear_watchpoint)?;
+    exports.create_named_method("haltCore", halt_core)?;
+    exports.create_named_method("resumeCore", resume_core)?;
+    exports.create_named_method("waitForHalt", wait_for_halt)?;
+    exports.create_named_method("readMemory", read_memory)?;
+    exports.create_named_method("writeMemory", write_memory)?;
+    exports.create_named_method("lookupSymbol", lookup_symbol)?;
+    exports.create_named_method("captureCoreDump", capture_core_dump)?;
+    exports.create_named_method("readPeripheral", read_peripheral)?;
+    exports.create_named_method("readRegisterField", read_register_field)?;
+    exports.create_named_method("createCancelToken", create_cancel_token)?;
+    exports.create_named_method("cancelOperation", cancel_operation)?;
+    exports.create_named_method("releaseCancelToken", release_cancel_token)?;
     Ok(())
 }
@@ -1 +1,5 @@
 pub mod probe_binding;
+pub mod probe_details;
+pub mod probe_power;
+pub mod probe_readiness;
+pub mod probe_watcher;
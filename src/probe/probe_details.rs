@@ -0,0 +1,519 @@
+use std::time::Duration;
+
+use hidapi::{HidApi, HidDevice};
+use jaylink::{Capability, Interface, JayLink};
+use napi::{CallContext, JsObject, JsUnknown, Task};
+use probe_rs::{DebugProbeSelector, Probe};
+use rusb::{DeviceHandle, Direction, GlobalContext, TransferType};
+use serde::Serialize;
+
+use crate::common::{
+    cancel_token::CancelToken,
+    plunger_error::PlungerError,
+    probe_info::{ProbeInfo, ProbeProtocol, ProbeType},
+    probe_lock::{LockMode, ProbeLock},
+    probe_selector::{probe_selector_from_args, ProbeSelector},
+};
+
+use super::probe_binding::convert_probe;
+
+const USB_TIMEOUT: Duration = Duration::from_millis(1000);
+// How long a query waits for the operation holding the probe, a flash can take minutes
+pub(super) const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+const USB_CLASS_VENDOR: u8 = 0xff;
+
+const STLINK_CMD_LEN: usize = 16;
+const STLINK_GET_VERSION: u8 = 0xf1;
+const STLINK_GET_TARGET_VOLTAGE: u8 = 0xf7;
+const STLINK_GET_VERSION_EXT: u8 = 0xfb;
+const STLINK_V2_PID: u16 = 0x3748;
+// Multiple AP support came with V2J28, older firmware only talks to AP 0
+const STLINK_MIN_JTAG_VERSION_MULTI_AP: u8 = 28;
+
+const DAP_INFO: u8 = 0x00;
+const DAP_INFO_PROTOCOL_VERSION: u8 = 0x04;
+const DAP_INFO_FIRMWARE_VERSION: u8 = 0x09;
+const DAP_INFO_CAPABILITIES: u8 = 0xf0;
+const DAP_CAP_SWD: u8 = 1 << 0;
+const DAP_CAP_JTAG: u8 = 1 << 1;
+const DAP_CAP_SWO_UART: u8 = 1 << 2;
+const DAP_CAP_SWO_MANCHESTER: u8 = 1 << 3;
+const DAP_HID_REPORT_SIZE: usize = 64;
+const DAP_BULK_PACKET_SIZE: usize = 512;
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeFeatures {
+    pub swo: bool,
+    pub target_power: bool,
+    pub target_voltage: bool,
+    pub multi_ap: bool,
+    pub arm: bool,
+    pub riscv: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeDetails {
+    pub probe: ProbeInfo,
+    pub name: String,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub firmware_version: Option<String>,
+    pub hardware_version: Option<String>,
+    pub protocols: Vec<ProbeProtocol>,
+    pub max_speed_khz: Option<u32>,
+    pub target_voltage: Option<f32>,
+    pub features: ProbeFeatures,
+}
+
+impl ProbeDetails {
+    fn new(probe: ProbeInfo) -> ProbeDetails {
+        ProbeDetails {
+            probe,
+            name: String::new(),
+            manufacturer: None,
+            product: None,
+            firmware_version: None,
+            hardware_version: None,
+            protocols: Vec::new(),
+            max_speed_khz: None,
+            target_voltage: None,
+            features: ProbeFeatures::default(),
+        }
+    }
+}
+
+// Vendor interface with a bulk IN/OUT pair, used by ST-Link and CMSIS-DAP v2
+struct BulkLink {
+    handle: DeviceHandle<GlobalContext>,
+    interface: u8,
+    ep_in: u8,
+    ep_out: u8,
+}
+
+impl BulkLink {
+    fn open(
+        probe: &ProbeInfo,
+        name_filter: Option<&str>,
+    ) -> Result<Option<BulkLink>, PlungerError> {
+        let mut handle = open_usb_device(probe)?;
+        let config = handle.device().active_config_descriptor()?;
+
+        for interface in config.interfaces() {
+            for setting in interface.descriptors() {
+                if setting.class_code() != USB_CLASS_VENDOR {
+                    continue;
+                }
+
+                let bulk_ep = |direction: Direction| {
+                    setting
+                        .endpoint_descriptors()
+                        .find(|ep| {
+                            ep.direction() == direction && ep.transfer_type() == TransferType::Bulk
+                        })
+                        .map(|ep| ep.address())
+                };
+
+                let (ep_in, ep_out) = match (bulk_ep(Direction::In), bulk_ep(Direction::Out)) {
+                    (Some(ep_in), Some(ep_out)) => (ep_in, ep_out),
+                    _ => continue,
+                };
+
+                if let Some(filter) = name_filter {
+                    let name = setting
+                        .description_string_index()
+                        .and_then(|index| handle.read_string_descriptor_ascii(index).ok());
+                    if !name.is_some_and(|name| name.contains(filter)) {
+                        continue;
+                    }
+                }
+
+                let number = setting.interface_number();
+                handle.claim_interface(number)?;
+                return Ok(Some(BulkLink {
+                    handle,
+                    interface: number,
+                    ep_in,
+                    ep_out,
+                }));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn transfer(&self, command: &[u8], response_len: usize) -> Result<Vec<u8>, PlungerError> {
+        self.handle.write_bulk(self.ep_out, command, USB_TIMEOUT)?;
+
+        let mut response = vec![0u8; response_len];
+        let len = self
+            .handle
+            .read_bulk(self.ep_in, &mut response, USB_TIMEOUT)?;
+        response.truncate(len);
+
+        Ok(response)
+    }
+}
+
+impl Drop for BulkLink {
+    fn drop(&mut self) {
+        let _ = self.handle.release_interface(self.interface);
+    }
+}
+
+fn open_usb_device(probe: &ProbeInfo) -> Result<DeviceHandle<GlobalContext>, PlungerError> {
+    let mut open_error = None;
+
+    for device in rusb::devices()?.iter() {
+        let descriptor = match device.device_descriptor() {
+            Ok(descriptor) => descriptor,
+            Err(_) => continue,
+        };
+
+        if descriptor.vendor_id() != probe.vid || descriptor.product_id() != probe.pid {
+            continue;
+        }
+
+        // Another probe with the same VID/PID may be the one we are looking for
+        let handle = match device.open() {
+            Ok(handle) => handle,
+            Err(err) => {
+                open_error = Some(err);
+                continue;
+            }
+        };
+        if let Some(sn) = &probe.serial_num {
+            match handle.read_serial_number_string_ascii(&descriptor) {
+                Ok(found) if &found == sn => (),
+                _ => continue,
+            }
+        }
+
+        return Ok(handle);
+    }
+
+    Err(match open_error {
+        Some(err) => err.into(),
+        None => PlungerError::ProbeNotFound(format!(
+            "{:04x}:{:04x} is not on the USB bus",
+            probe.vid, probe.pid
+        )),
+    })
+}
+
+// Best effort, libusb cannot open HID-only probes on Windows or without a usbfs udev rule
+fn read_usb_strings(details: &mut ProbeDetails) {
+    let handle = match open_usb_device(&details.probe) {
+        Ok(handle) => handle,
+        Err(_) => return,
+    };
+    let descriptor = match handle.device().device_descriptor() {
+        Ok(descriptor) => descriptor,
+        Err(_) => return,
+    };
+
+    details.manufacturer = handle.read_manufacturer_string_ascii(&descriptor).ok();
+    details.product = handle.read_product_string_ascii(&descriptor).ok();
+}
+
+fn stlink_command(command: u8) -> [u8; STLINK_CMD_LEN] {
+    let mut buf = [0u8; STLINK_CMD_LEN];
+    buf[0] = command;
+    buf
+}
+
+fn stlink_target_voltage(link: &BulkLink) -> Result<Option<f32>, PlungerError> {
+    let voltage = link.transfer(&stlink_command(STLINK_GET_TARGET_VOLTAGE), 8)?;
+    if voltage.len() != 8 {
+        return Ok(None);
+    }
+
+    // Ratio of the target voltage to the 1.2 V internal reference, halved by a divider
+    let a0 = u32::from_le_bytes([voltage[0], voltage[1], voltage[2], voltage[3]]);
+    let a1 = u32::from_le_bytes([voltage[4], voltage[5], voltage[6], voltage[7]]);
+    if a0 == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(2.0 * a1 as f32 * 1.2 / a0 as f32))
+}
+
+fn query_stlink(details: &mut ProbeDetails) -> Result<(), PlungerError> {
+    let link = BulkLink::open(&details.probe, None)?
+        .ok_or_else(|| PlungerError::StateError("ST-Link has no bulk interface".to_string()))?;
+
+    let version = link.transfer(&stlink_command(STLINK_GET_VERSION), 6)?;
+    if version.len() < 2 {
+        return Err(PlungerError::StateError(
+            "Short ST-Link version response".to_string(),
+        ));
+    }
+
+    // [15:12] hardware, [11:6] JTAG/SWD, [5:0] SWIM (V2) or MSC (V2-1)
+    let raw = u16::from_be_bytes([version[0], version[1]]);
+    let hw_version = (raw >> 12) as u8 & 0x0f;
+    let mut jtag_version = (raw >> 6) as u8 & 0x3f;
+    let low_version = raw as u8 & 0x3f;
+
+    let firmware = if hw_version >= 3 {
+        // V3 reports the versions with a separate command
+        let ext = link.transfer(&stlink_command(STLINK_GET_VERSION_EXT), 12)?;
+        if ext.len() < 5 {
+            return Err(PlungerError::StateError(
+                "Short ST-Link version response".to_string(),
+            ));
+        }
+
+        jtag_version = ext[2];
+        format!("V{}J{}M{}B{}S{}", ext[0], ext[2], ext[3], ext[4], ext[1])
+    } else if details.probe.pid == STLINK_V2_PID {
+        format!("V{}J{}S{}", hw_version, jtag_version, low_version)
+    } else {
+        format!("V{}J{}M{}", hw_version, jtag_version, low_version)
+    };
+
+    details.target_voltage = stlink_target_voltage(&link)?;
+    details.firmware_version = Some(firmware);
+    details.hardware_version = Some(format!("V{}", hw_version));
+    details.protocols = vec![ProbeProtocol::Swd, ProbeProtocol::Jtag];
+    details.max_speed_khz = Some(if hw_version >= 3 { 24_000 } else { 4_000 });
+    details.features.target_voltage = true;
+    details.features.multi_ap = hw_version >= 3 || jtag_version >= STLINK_MIN_JTAG_VERSION_MULTI_AP;
+
+    Ok(())
+}
+
+fn query_jlink(details: &mut ProbeDetails) -> Result<(), PlungerError> {
+    let jlink = JayLink::open_by_serial(details.probe.serial_num.as_deref())?;
+    let caps = jlink.capabilities();
+
+    details.firmware_version = Some(jlink.read_firmware_version()?);
+    if caps.contains(Capability::GetHwVersion) {
+        details.hardware_version = Some(jlink.read_hardware_version()?.to_string());
+    }
+    if caps.contains(Capability::SpeedInfo) {
+        details.max_speed_khz = Some(jlink.read_speeds()?.max_speed_hz() / 1000);
+    }
+
+    let interfaces = jlink.available_interfaces();
+    if interfaces.contains(Interface::Swd) {
+        details.protocols.push(ProbeProtocol::Swd);
+    }
+    if interfaces.contains(Interface::Jtag) {
+        details.protocols.push(ProbeProtocol::Jtag);
+    }
+
+    details.target_voltage = jlink
+        .read_target_voltage()
+        .ok()
+        .map(|millivolts| millivolts as f32 / 1000.0);
+    details.features.target_voltage = true;
+    details.features.target_power = caps.contains(Capability::SetKsPower);
+    details.features.multi_ap = true;
+
+    Ok(())
+}
+
+enum DapLink {
+    Bulk(BulkLink),
+    Hid(HidDevice),
+}
+
+impl DapLink {
+    // CMSIS-DAP v2 first, v1 HID probes are only reachable through hidapi.
+    // Any libusb failure falls through to hidapi, which may still reach the probe.
+    fn open(probe: &ProbeInfo) -> Result<DapLink, PlungerError> {
+        if let Ok(Some(link)) = BulkLink::open(probe, Some("CMSIS-DAP")) {
+            return Ok(DapLink::Bulk(link));
+        }
+
+        let api = HidApi::new()?;
+        let info = api
+            .device_list()
+            .find(|info| {
+                info.vendor_id() == probe.vid
+                    && info.product_id() == probe.pid
+                    && probe
+                        .serial_num
+                        .as_deref()
+                        .is_none_or(|sn| info.serial_number() == Some(sn))
+                    && info
+                        .product_string()
+                        .is_some_and(|name| name.contains("CMSIS-DAP"))
+            })
+            .ok_or_else(|| {
+                PlungerError::StateError("No CMSIS-DAP interface found on probe".to_string())
+            })?;
+
+        Ok(DapLink::Hid(info.open_device(&api)?))
+    }
+
+    fn info(&self, id: u8) -> Result<Vec<u8>, PlungerError> {
+        let response = match self {
+            DapLink::Bulk(link) => link.transfer(&[DAP_INFO, id], DAP_BULK_PACKET_SIZE)?,
+            DapLink::Hid(device) => {
+                // Leading zero is the HID report ID
+                let mut report = [0u8; DAP_HID_REPORT_SIZE + 1];
+                report[1] = DAP_INFO;
+                report[2] = id;
+                device.write(&report)?;
+
+                let mut response = vec![0u8; DAP_HID_REPORT_SIZE];
+                let len = device.read_timeout(&mut response, USB_TIMEOUT.as_millis() as i32)?;
+                response.truncate(len);
+                response
+            }
+        };
+
+        match response.as_slice() {
+            [DAP_INFO, len, data @ ..] if data.len() >= *len as usize => {
+                Ok(data[..*len as usize].to_vec())
+            }
+            _ => Err(PlungerError::StateError(format!(
+                "Invalid DAP_Info response for ID 0x{:02x}",
+                id
+            ))),
+        }
+    }
+
+    fn info_string(&self, id: u8) -> Result<Option<String>, PlungerError> {
+        let data = self.info(id)?;
+        let text = String::from_utf8_lossy(&data)
+            .trim_end_matches('\0')
+            .to_string();
+
+        Ok(if text.is_empty() { None } else { Some(text) })
+    }
+}
+
+fn query_cmsis_dap(details: &mut ProbeDetails) -> Result<(), PlungerError> {
+    let link = DapLink::open(&details.probe)?;
+
+    // Fill in what libusb could not read
+    if let DapLink::Hid(device) = &link {
+        if details.manufacturer.is_none() {
+            details.manufacturer = device.get_manufacturer_string().ok().flatten();
+        }
+        if details.product.is_none() {
+            details.product = device.get_product_string().ok().flatten();
+        }
+    }
+
+    // The product firmware version only exists since CMSIS-DAP 2.1
+    details.hardware_version = link.info_string(DAP_INFO_PROTOCOL_VERSION)?;
+    details.firmware_version = link
+        .info_string(DAP_INFO_FIRMWARE_VERSION)
+        .ok()
+        .flatten()
+        .or_else(|| details.hardware_version.clone());
+
+    let caps = link
+        .info(DAP_INFO_CAPABILITIES)?
+        .first()
+        .copied()
+        .unwrap_or_default();
+    if caps & DAP_CAP_SWD != 0 {
+        details.protocols.push(ProbeProtocol::Swd);
+    }
+    if caps & DAP_CAP_JTAG != 0 {
+        details.protocols.push(ProbeProtocol::Jtag);
+    }
+
+    details.features.swo = caps & (DAP_CAP_SWO_UART | DAP_CAP_SWO_MANCHESTER) != 0;
+    details.features.multi_ap = true;
+
+    Ok(())
+}
+
+/// Looks up the probe type and short ID of an already resolved selector
+pub fn find_probe(selector: &DebugProbeSelector) -> Result<ProbeInfo, PlungerError> {
+    Probe::list_all()
+        .into_iter()
+        .map(convert_probe)
+        .find(|probe| {
+            probe.vid == selector.vendor_id
+                && probe.pid == selector.product_id
+                && selector
+                    .serial_number
+                    .as_ref()
+                    .is_none_or(|sn| Some(sn) == probe.serial_num.as_ref())
+        })
+        .ok_or_else(|| PlungerError::ProbeNotFound(format!("{:?}", selector)))
+}
+
+/// Target voltage in volts, as measured by the probe on its reference pin
+pub fn read_target_voltage(probe: &ProbeInfo) -> Result<Option<f32>, PlungerError> {
+    match probe.probe_type {
+        Some(ProbeType::StLink) => {
+            let link = BulkLink::open(probe, None)?.ok_or_else(|| {
+                PlungerError::StateError("ST-Link has no bulk interface".to_string())
+            })?;
+            stlink_target_voltage(&link)
+        }
+        Some(ProbeType::JLink) => {
+            let jlink = JayLink::open_by_serial(probe.serial_num.as_deref())?;
+            Ok(Some(jlink.read_target_voltage()? as f32 / 1000.0))
+        }
+        _ => Err(PlungerError::Unsupported(format!(
+            "Reading the target voltage with {:?} probes",
+            probe.probe_type
+        ))),
+    }
+}
+
+pub fn query_probe_details(selector: DebugProbeSelector) -> Result<ProbeDetails, PlungerError> {
+    let probe = find_probe(&selector)?;
+    let mut details = ProbeDetails::new(probe.clone());
+    read_usb_strings(&mut details);
+
+    // Vendor queries talk to the probe directly, so they have to finish before probe-rs opens it
+    match probe.probe_type {
+        Some(ProbeType::StLink) => query_stlink(&mut details)?,
+        Some(ProbeType::JLink) => query_jlink(&mut details)?,
+        Some(ProbeType::DapLink) => query_cmsis_dap(&mut details)?,
+        Some(ProbeType::Ftdi) | None => details.protocols.push(ProbeProtocol::Jtag),
+    }
+
+    let opened = Probe::open(&probe)?;
+    details.name = opened.get_name();
+    details.features.arm = opened.has_arm_interface();
+    details.features.riscv = opened.has_riscv_interface();
+    details.features.swo |= opened.get_swo_interface().is_some();
+
+    Ok(details)
+}
+
+pub struct ProbeDetailsTask {
+    selector: ProbeSelector,
+}
+
+impl Task for ProbeDetailsTask {
+    type Output = ProbeDetails;
+    type JsValue = JsUnknown;
+
+    fn compute(&mut self) -> napi::Result<Self::Output> {
+        let selector = self.selector.resolve()?;
+        let _lock = ProbeLock::acquire(
+            &selector,
+            LockMode::Queue,
+            &CancelToken::default().with_timeout(LOCK_TIMEOUT),
+        )?;
+        Ok(query_probe_details(selector)?)
+    }
+
+    fn resolve(self, env: napi::Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+        env.to_js_value(&output)
+    }
+
+    fn reject(self, _env: napi::Env, err: napi::Error) -> napi::Result<Self::JsValue> {
+        Err(err)
+    }
+}
+
+#[js_function(3)]
+pub fn get_probe_details(ctx: CallContext) -> napi::Result<JsObject> {
+    let selector = probe_selector_from_args(&ctx, 0, 1, 2)?;
+    let task = ProbeDetailsTask { selector };
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
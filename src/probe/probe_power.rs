@@ -0,0 +1,166 @@
+use std::{thread, time::Duration};
+
+use jaylink::{Capability, JayLink};
+use napi::{CallContext, JsBoolean, JsObject, JsUndefined, JsUnknown, Task};
+use serde::Deserialize;
+
+use crate::common::{
+    cancel_token::CancelToken,
+    plunger_error::PlungerError,
+    probe_info::{ProbeInfo, ProbeType},
+    probe_lock::{LockMode, ProbeLock},
+    probe_selector::{probe_selector_from_args, ProbeSelector},
+};
+
+use super::probe_details::{find_probe, read_target_voltage, LOCK_TIMEOUT};
+
+// J-Link kickstart power is a fixed 5 V supply on pin 19
+const JLINK_KS_POWER_VOLTAGE: f32 = 5.0;
+const VOLTAGE_TOLERANCE: f32 = 0.1;
+const POWER_SETTLE_TIME: Duration = Duration::from_millis(100);
+pub const DEFAULT_POWER_OFF_MS: u64 = 500;
+
+/// How the eraser resets the target once it is done
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum ResetStrategy {
+    #[default]
+    Default,
+    /// Switch the probe supplied target power off and on, for option bytes that only load on POR
+    PowerCycle,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TargetPowerOptions {
+    voltage: Option<f32>,
+}
+
+/// Only J-Link kickstart power for now, CMSIS-DAP has no standard command to switch target
+/// power and ST-Link does not expose one
+pub fn supports_target_power(probe: &ProbeInfo) -> bool {
+    matches!(probe.probe_type, Some(ProbeType::JLink))
+}
+
+pub fn apply_target_power(
+    probe: &ProbeInfo,
+    on: bool,
+    voltage: Option<f32>,
+) -> Result<(), PlungerError> {
+    match probe.probe_type {
+        Some(ProbeType::JLink) => {
+            if let Some(voltage) = voltage {
+                if (voltage - JLINK_KS_POWER_VOLTAGE).abs() > VOLTAGE_TOLERANCE {
+                    return Err(PlungerError::InvalidOption(format!(
+                        "J-Link can only supply {} V, {} V requested",
+                        JLINK_KS_POWER_VOLTAGE, voltage
+                    )));
+                }
+            }
+
+            let mut jlink = JayLink::open_by_serial(probe.serial_num.as_deref())?;
+            if !jlink.capabilities().contains(Capability::SetKsPower) {
+                return Err(PlungerError::Unsupported(
+                    "Target power on this J-Link".to_string(),
+                ));
+            }
+
+            Ok(jlink.set_kickstart_power(on)?)
+        }
+        _ => Err(PlungerError::Unsupported(format!(
+            "Target power control with {:?} probes",
+            probe.probe_type
+        ))),
+    }
+}
+
+pub fn power_cycle(probe: &ProbeInfo, off_time: Duration) -> Result<(), PlungerError> {
+    apply_target_power(probe, false, None)?;
+    thread::sleep(off_time);
+    apply_target_power(probe, true, None)?;
+    thread::sleep(POWER_SETTLE_TIME);
+
+    Ok(())
+}
+
+pub struct TargetPowerTask {
+    selector: ProbeSelector,
+    on: bool,
+    voltage: Option<f32>,
+}
+
+impl Task for TargetPowerTask {
+    type Output = ();
+    type JsValue = JsUndefined;
+
+    fn compute(&mut self) -> napi::Result<Self::Output> {
+        let selector = self.selector.resolve()?;
+        let _lock = ProbeLock::acquire(
+            &selector,
+            LockMode::Queue,
+            &CancelToken::default().with_timeout(LOCK_TIMEOUT),
+        )?;
+        let probe = find_probe(&selector)?;
+        Ok(apply_target_power(&probe, self.on, self.voltage)?)
+    }
+
+    fn resolve(self, env: napi::Env, _output: Self::Output) -> napi::Result<Self::JsValue> {
+        env.get_undefined()
+    }
+
+    fn reject(self, _env: napi::Env, err: napi::Error) -> napi::Result<Self::JsValue> {
+        Err(err)
+    }
+}
+
+pub struct TargetVoltageTask {
+    selector: ProbeSelector,
+}
+
+impl Task for TargetVoltageTask {
+    type Output = Option<f32>;
+    type JsValue = JsUnknown;
+
+    fn compute(&mut self) -> napi::Result<Self::Output> {
+        let selector = self.selector.resolve()?;
+        let _lock = ProbeLock::acquire(
+            &selector,
+            LockMode::Queue,
+            &CancelToken::default().with_timeout(LOCK_TIMEOUT),
+        )?;
+        let probe = find_probe(&selector)?;
+        Ok(read_target_voltage(&probe)?)
+    }
+
+    fn resolve(self, env: napi::Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+        env.to_js_value(&output)
+    }
+
+    fn reject(self, _env: napi::Env, err: napi::Error) -> napi::Result<Self::JsValue> {
+        Err(err)
+    }
+}
+
+#[js_function(5)]
+pub fn set_target_power(ctx: CallContext) -> napi::Result<JsObject> {
+    let on = ctx.get::<JsBoolean>(0)?.get_value()?;
+    let selector = probe_selector_from_args(&ctx, 1, 2, 3)?;
+    let options: TargetPowerOptions = match ctx.try_get::<JsObject>(4)? {
+        napi::Either::A(obj) => ctx.env.from_js_value(obj)?,
+        napi::Either::B(_) => TargetPowerOptions::default(),
+    };
+
+    let task = TargetPowerTask {
+        selector,
+        on,
+        voltage: options.voltage,
+    };
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
+
+#[js_function(3)]
+pub fn get_target_voltage(ctx: CallContext) -> napi::Result<JsObject> {
+    let selector = probe_selector_from_args(&ctx, 0, 1, 2)?;
+    let task = TargetVoltageTask { selector };
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
@@ -0,0 +1,149 @@
+use rusb::{Device, DeviceHandle, Direction, GlobalContext, TransferType};
+use serde::Deserialize;
+
+use crate::common::probe_info::{ProbeInfo, ProbeType};
+
+const USB_CLASS_HID: u8 = 0x03;
+const USB_CLASS_VENDOR: u8 = 0xff;
+
+/// How to decide whether a CMSIS-DAP probe has finished enumerating
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum ReadinessCheck {
+    /// Look for the CMSIS-DAP HID or bulk interface in the USB descriptors
+    #[default]
+    Usb,
+    /// Legacy check, waits for the DAPLINK mass storage volume to show up in udev
+    Udev,
+    /// Treat every probe as ready
+    #[serde(rename = "none")]
+    Disabled,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadinessOptions {
+    pub readiness_check: Option<ReadinessCheck>,
+    pub include_not_ready: Option<bool>,
+}
+
+impl ReadinessOptions {
+    pub fn check(&self) -> ReadinessCheck {
+        self.readiness_check.unwrap_or_default()
+    }
+}
+
+// Probe-rs tend to return all DAPLink probes even if it is not really ready,
+// which may cause random USB stack lockups if we talk to them too early.
+// Probes are assumed ready when the check itself is unavailable (e.g. no udev in containers,
+// or libusb cannot open a HID-only probe on Windows or hidraw-only Linux).
+pub fn is_probe_ready(probe: &ProbeInfo, check: ReadinessCheck) -> bool {
+    if !matches!(probe.probe_type, Some(ProbeType::DapLink)) {
+        return true;
+    }
+
+    match check {
+        ReadinessCheck::Usb => usb_ready(probe),
+        ReadinessCheck::Udev => probe.serial_num.as_ref().and_then(|sn| udev_ready(sn)),
+        ReadinessCheck::Disabled => None,
+    }
+    .unwrap_or(true)
+}
+
+fn usb_ready(probe: &ProbeInfo) -> Option<bool> {
+    let devices = rusb::devices().ok()?;
+    let mut unopened = false;
+
+    for device in devices.iter() {
+        let descriptor = match device.device_descriptor() {
+            Ok(descriptor) => descriptor,
+            Err(_) => continue,
+        };
+
+        if descriptor.vendor_id() != probe.vid || descriptor.product_id() != probe.pid {
+            continue;
+        }
+
+        let handle = match device.open() {
+            Ok(handle) => handle,
+            // Another probe with the same VID/PID may be the one we are looking for
+            Err(_) if probe.serial_num.is_some() => {
+                unopened = true;
+                continue;
+            }
+            // The descriptors can usually still be read, just not the interface strings
+            Err(_) => return has_cmsis_dap_interface(&device, None),
+        };
+        if let Some(sn) = &probe.serial_num {
+            match handle.read_serial_number_string_ascii(&descriptor) {
+                Ok(found) if &found == sn => (),
+                _ => continue,
+            }
+        }
+
+        return has_cmsis_dap_interface(&device, Some(&handle));
+    }
+
+    // Listed by probe-rs but not on the bus yet, unless it may be one libusb could not open
+    if unopened {
+        None
+    } else {
+        Some(false)
+    }
+}
+
+// CMSIS-DAP v1 is a HID interface with an interrupt IN endpoint, v2 is a vendor interface
+// with bulk IN and OUT endpoints. Both should have "CMSIS-DAP" in the interface string.
+fn has_cmsis_dap_interface(
+    device: &Device<GlobalContext>,
+    handle: Option<&DeviceHandle<GlobalContext>>,
+) -> Option<bool> {
+    let config = device.active_config_descriptor().ok()?;
+
+    for interface in config.interfaces() {
+        for setting in interface.descriptors() {
+            let endpoints: Vec<(Direction, TransferType)> = setting
+                .endpoint_descriptors()
+                .map(|endpoint| (endpoint.direction(), endpoint.transfer_type()))
+                .collect();
+
+            let usable = match setting.class_code() {
+                USB_CLASS_HID => endpoints.contains(&(Direction::In, TransferType::Interrupt)),
+                USB_CLASS_VENDOR => {
+                    endpoints.contains(&(Direction::In, TransferType::Bulk))
+                        && endpoints.contains(&(Direction::Out, TransferType::Bulk))
+                }
+                _ => false,
+            };
+            if !usable {
+                continue;
+            }
+
+            let name = setting
+                .description_string_index()
+                .and_then(|index| handle?.read_string_descriptor_ascii(index).ok());
+
+            // Without an interface string the endpoints are all we can go by
+            match name {
+                Some(name) if name.contains("CMSIS-DAP") => return Some(true),
+                Some(_) => continue,
+                None => return Some(true),
+            }
+        }
+    }
+
+    Some(false)
+}
+
+fn udev_ready(serial_num: &str) -> Option<bool> {
+    let mut enumerator = udev::Enumerator::new().ok()?;
+    enumerator.match_property("ID_FS_LABEL", "DAPLINK").ok()?;
+
+    let found = enumerator.scan_devices().ok()?.any(|device| {
+        device
+            .properties()
+            .any(|property| property.name() == "ID_SERIAL_SHORT" && property.value() == serial_num)
+    });
+
+    Some(found)
+}
@@ -0,0 +1,265 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use lazy_static::lazy_static;
+use napi::{
+    threadsafe_function::{ThreadSafeCallContext, ThreadsafeFunction, ThreadsafeFunctionCallMode},
+    CallContext, JsBoolean, JsFunction, JsNumber, JsObject,
+};
+use probe_rs::{DebugProbeSelector, Probe};
+use rusb::{Device, Hotplug, UsbContext};
+use serde::Serialize;
+
+use crate::common::{
+    cancel_token::CancelToken,
+    probe_info::ProbeInfo,
+    probe_lock::{LockMode, ProbeLock},
+};
+
+use super::{
+    probe_binding::convert_probe,
+    probe_readiness::{is_probe_ready, ReadinessCheck, ReadinessOptions},
+};
+
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+lazy_static! {
+    static ref WATCHERS: Mutex<HashMap<u32, Arc<AtomicBool>>> = Mutex::new(HashMap::new());
+}
+
+static NEXT_WATCHER_ID: AtomicU32 = AtomicU32::new(1);
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum ProbeEventType {
+    Attached,
+    Detached,
+    Ready,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeEvent {
+    event: ProbeEventType,
+    probe: ProbeInfo,
+}
+
+type ProbeKey = (u16, u16, Option<String>);
+
+// Only marks the probe list as dirty, the actual diff is done on the watcher thread
+struct HotplugFlag {
+    dirty: Arc<AtomicBool>,
+}
+
+impl<T: UsbContext> Hotplug<T> for HotplugFlag {
+    fn device_arrived(&mut self, _device: Device<T>) {
+        self.dirty.store(true, Ordering::SeqCst);
+    }
+
+    fn device_left(&mut self, _device: Device<T>) {
+        self.dirty.store(true, Ordering::SeqCst);
+    }
+}
+
+struct ProbeWatcher {
+    known: HashMap<ProbeKey, (ProbeInfo, bool)>,
+    check: ReadinessCheck,
+    callback: ThreadsafeFunction<ProbeEvent>,
+}
+
+impl ProbeWatcher {
+    fn emit(&self, event: ProbeEventType, probe: &ProbeInfo) {
+        self.callback.call(
+            Ok(ProbeEvent {
+                event,
+                probe: probe.clone(),
+            }),
+            ThreadsafeFunctionCallMode::NonBlocking,
+        );
+    }
+
+    fn has_pending(&self) -> bool {
+        self.known.values().any(|(_, ready)| !ready)
+    }
+
+    // The readiness check opens the probe, so it must not race a flash or debug session.
+    // A busy probe stays pending and is checked again on the next poll.
+    fn check_ready(&self, probe: &ProbeInfo) -> bool {
+        let selector = DebugProbeSelector {
+            vendor_id: probe.vid,
+            product_id: probe.pid,
+            serial_number: probe.serial_num.clone(),
+        };
+        match ProbeLock::acquire(&selector, LockMode::FailFast, &CancelToken::default()) {
+            Ok(_lock) => is_probe_ready(probe, self.check),
+            Err(_) => false,
+        }
+    }
+
+    fn rescan(&mut self) {
+        let probes: HashMap<ProbeKey, ProbeInfo> = Probe::list_all()
+            .into_iter()
+            .map(convert_probe)
+            .map(|probe| ((probe.vid, probe.pid, probe.serial_num.clone()), probe))
+            .collect();
+
+        let detached: Vec<ProbeKey> = self
+            .known
+            .keys()
+            .filter(|key| !probes.contains_key(*key))
+            .cloned()
+            .collect();
+
+        for key in detached {
+            if let Some((probe, _)) = self.known.remove(&key) {
+                self.emit(ProbeEventType::Detached, &probe);
+            }
+        }
+
+        for (key, probe) in probes {
+            let was_ready = match self.known.get(&key) {
+                Some((_, ready)) => *ready,
+                None => {
+                    self.emit(ProbeEventType::Attached, &probe);
+                    false
+                }
+            };
+
+            let ready = was_ready || self.check_ready(&probe);
+            let probe = ProbeInfo {
+                ready: Some(ready),
+                ..probe
+            };
+            if ready && !was_ready {
+                self.emit(ProbeEventType::Ready, &probe);
+            }
+
+            self.known.insert(key, (probe, ready));
+        }
+    }
+
+    fn run(mut self, stop: Arc<AtomicBool>) {
+        let dirty = Arc::new(AtomicBool::new(true));
+
+        // Fall back to polling if libusb has no hotplug support on this platform
+        let context = if rusb::has_hotplug() {
+            rusb::Context::new().ok()
+        } else {
+            None
+        };
+        let registration = context.as_ref().and_then(|ctx| {
+            ctx.register_callback(
+                None,
+                None,
+                None,
+                Box::new(HotplugFlag {
+                    dirty: dirty.clone(),
+                }),
+            )
+            .ok()
+        });
+
+        while !stop.load(Ordering::SeqCst) {
+            match (&context, &registration) {
+                (Some(ctx), Some(_)) => {
+                    if ctx.handle_events(Some(WATCH_INTERVAL)).is_err() {
+                        thread::sleep(WATCH_INTERVAL);
+                    }
+                }
+                _ => {
+                    thread::sleep(WATCH_INTERVAL);
+                    dirty.store(true, Ordering::SeqCst);
+                }
+            }
+
+            // DAPLink readiness does not come with a USB event, so keep polling until it settles
+            if dirty.swap(false, Ordering::SeqCst) || self.has_pending() {
+                self.rescan();
+            }
+        }
+    }
+}
+
+fn stop_watcher(id: u32) -> napi::Result<bool> {
+    let stop = match WATCHERS.lock() {
+        Ok(mut watchers) => watchers.remove(&id),
+        Err(err) => {
+            return Err(napi::Error {
+                status: napi::Status::Unknown,
+                reason: format!("Cannot acquire watcher lock: {}", err),
+            })
+        }
+    };
+
+    if let Some(stop) = &stop {
+        stop.store(true, Ordering::SeqCst);
+    }
+
+    Ok(stop.is_some())
+}
+
+#[js_function(1)]
+fn unwatch(ctx: CallContext) -> napi::Result<JsBoolean> {
+    let handle = ctx.this::<JsObject>()?;
+    let id = handle.get_named_property::<JsNumber>("id")?.get_uint32()?;
+    ctx.env.get_boolean(stop_watcher(id)?)
+}
+
+#[js_function(2)]
+pub fn watch_probes(ctx: CallContext) -> napi::Result<JsObject> {
+    let func = ctx.get::<JsFunction>(0)?;
+    let options: ReadinessOptions = match ctx.try_get::<JsObject>(1)? {
+        napi::Either::A(options) => ctx.env.from_js_value(options)?,
+        napi::Either::B(_) => ReadinessOptions::default(),
+    };
+    let callback = ctx.env.create_threadsafe_function(
+        &func,
+        0,
+        |ctx: ThreadSafeCallContext<ProbeEvent>| Ok(vec![ctx.env.to_js_value(&ctx.value)?]),
+    )?;
+
+    let id = NEXT_WATCHER_ID.fetch_add(1, Ordering::SeqCst);
+    let stop = Arc::new(AtomicBool::new(false));
+
+    match WATCHERS.lock() {
+        Ok(mut watchers) => watchers.insert(id, stop.clone()),
+        Err(err) => {
+            return Err(napi::Error {
+                status: napi::Status::Unknown,
+                reason: format!("Cannot acquire watcher lock: {}", err),
+            })
+        }
+    };
+
+    let watcher = ProbeWatcher {
+        known: HashMap::new(),
+        check: options.check(),
+        callback,
+    };
+    thread::spawn(move || watcher.run(stop));
+
+    let mut handle = ctx.env.create_object()?;
+    handle.set_named_property("id", ctx.env.create_uint32(id)?)?;
+    handle.set_named_property("unwatch", ctx.env.create_function("unwatch", unwatch)?)?;
+    Ok(handle)
+}
+
+// Accepts the handle returned by watchProbes or its numeric id
+#[js_function(1)]
+pub fn unwatch_probes(ctx: CallContext) -> napi::Result<JsBoolean> {
+    let id = match ctx.try_get::<JsNumber>(0)? {
+        napi::Either::A(id) => id.get_uint32()?,
+        napi::Either::B(_) => ctx
+            .get::<JsObject>(0)?
+            .get_named_property::<JsNumber>("id")?
+            .get_uint32()?,
+    };
+    ctx.env.get_boolean(stop_watcher(id)?)
+}
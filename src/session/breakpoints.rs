@@ -0,0 +1,697 @@
+use std::time::Duration;
+
+use napi::{CallContext, JsNumber, JsObject, JsUnknown, ValueType};
+use probe_rs::{Core, CoreStatus, HaltReason, MemoryInterface, Session};
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+use crate::{
+    common::{cancel_token::CancelToken, plunger_error::PlungerError},
+    symbols::elf_resolver::Location,
+};
+
+use super::{
+    debug_session::{with_session, SessionTask},
+    registers::core_register,
+};
+
+const HALT_TIMEOUT: Duration = Duration::from_millis(500);
+const DEFAULT_WAIT_TIMEOUT_MS: u64 = 10_000;
+
+const SCB_CPUID: u32 = 0xe000_ed00;
+const FP_CTRL: u32 = 0xe000_2000;
+const FP_CTRL_KEY: u32 = 1 << 1;
+const FP_CTRL_ENABLE: u32 = 1;
+const FP_COMP_BASE: u32 = 0xe000_2008;
+const FP_COMP_ENABLE: u32 = 1;
+// FPB revision 1 can only replace instructions in the code region
+const FP_REV1_CODE_LIMIT: u32 = 0x2000_0000;
+const FP_REV1_REPLACE_LOWER: u32 = 0b01 << 30;
+const FP_REV1_REPLACE_UPPER: u32 = 0b10 << 30;
+const DEMCR: u32 = 0xe000_edfc;
+const DEMCR_TRCENA: u32 = 1 << 24;
+const DWT_CTRL: u32 = 0xe000_1000;
+const DWT_COMP_BASE: u32 = 0xe000_1020;
+const DWT_COMP_STRIDE: u32 = 0x10;
+const DWT_MASK_OFFSET: u32 = 0x04;
+const DWT_FUNCTION_OFFSET: u32 = 0x08;
+const DWT_FUNCTION_MATCHED: u32 = 1 << 24;
+
+// Cortex-M23, M33, M55 and M85 have the ARMv8-M DWT, which encodes comparators differently
+const V8M_PART_NUMBERS: [u32; 4] = [0xd20, 0xd21, 0xd22, 0xd23];
+// ARMv8-M FUNCTION.ACTION: generate a debug event on match
+const V8M_ACTION_DEBUG_EVENT: u32 = 0x1 << 4;
+const V8M_DATAVSIZE_SHIFT: u32 = 10;
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LocationOptions {
+    /// Overrides the session's ELF file for symbol names
+    pub elf_path: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum WatchAccess {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// How FP_COMP encodes a breakpoint address, from FP_CTRL.REV
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FpbRevision {
+    /// ARMv6-M and most ARMv7-M parts: COMP holds address bits 28:2, REPLACE picks the halfword
+    Replace,
+    /// ARMv8-M and the Cortex-M7: BPADDR holds address bits 31:1, BE enables it
+    BreakpointAddress,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Breakpoint {
+    address: u32,
+    comparator: u32,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct Watchpoint {
+    pub address: u32,
+    pub size: u32,
+    pub access: WatchAccess,
+    comparator: u32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HaltInfo {
+    pub reason: &'static str,
+    pub pc: u32,
+    /// Set when the core stopped on one of our breakpoints
+    pub breakpoint: Option<u32>,
+    pub watchpoint: Option<Watchpoint>,
+}
+
+/// A `waitForHalt` call, answered by the session thread once the core halts
+#[derive(Debug)]
+struct HaltWaiter {
+    cancel: CancelToken,
+    done: oneshot::Sender<Result<HaltInfo, PlungerError>>,
+}
+
+/// Breakpoints (FPB) and watchpoints (DWT) set through the session API
+#[derive(Debug, Default)]
+pub struct HaltPoints {
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    waiters: Vec<HaltWaiter>,
+}
+
+/// The FP_COMP value that breaks on the instruction at `address`
+pub fn fpb_comparator(address: u32, revision: FpbRevision) -> Result<u32, PlungerError> {
+    if address & 1 != 0 {
+        return Err(PlungerError::InvalidOption(format!(
+            "Breakpoint address 0x{:08x} is not halfword aligned",
+            address
+        )));
+    }
+
+    match revision {
+        FpbRevision::Replace if address >= FP_REV1_CODE_LIMIT => {
+            Err(PlungerError::Unsupported(format!(
+                "This FPB can only break below 0x{:08x}, not at 0x{:08x}",
+                FP_REV1_CODE_LIMIT, address
+            )))
+        }
+        FpbRevision::Replace => {
+            let replace = if address & 0b10 == 0 {
+                FP_REV1_REPLACE_LOWER
+            } else {
+                FP_REV1_REPLACE_UPPER
+            };
+            Ok(replace | (address & 0x1fff_fffc) | FP_COMP_ENABLE)
+        }
+        FpbRevision::BreakpointAddress => Ok(address | FP_COMP_ENABLE),
+    }
+}
+
+/// The DWT FUNCTION value that halts on `access` to a `size` byte range. ARMv7-M takes the
+/// size from the separate MASK register, ARMv8-M from DATAVSIZE.
+pub fn dwt_function(access: WatchAccess, size: u32, v8m: bool) -> Result<u32, PlungerError> {
+    if !v8m {
+        return Ok(match access {
+            WatchAccess::Read => 0b0101,
+            WatchAccess::Write => 0b0110,
+            WatchAccess::ReadWrite => 0b0111,
+        });
+    }
+
+    if size > 4 {
+        return Err(PlungerError::Unsupported(
+            "ARMv8-M watchpoints cover at most 4 bytes".to_string(),
+        ));
+    }
+
+    let matching = match access {
+        WatchAccess::ReadWrite => 0b0100,
+        WatchAccess::Write => 0b0101,
+        WatchAccess::Read => 0b0110,
+    };
+    Ok(V8M_ACTION_DEBUG_EVENT | matching | (size.trailing_zeros() << V8M_DATAVSIZE_SHIFT))
+}
+
+fn comparator_register(comparator: u32, offset: u32) -> u32 {
+    DWT_COMP_BASE + comparator * DWT_COMP_STRIDE + offset
+}
+
+fn is_v8m(core: &mut Core) -> Result<bool, PlungerError> {
+    let part = (core.read_word_32(SCB_CPUID)? >> 4) & 0xfff;
+    Ok(V8M_PART_NUMBERS.contains(&part))
+}
+
+/// Reads the FPB revision and comparator count
+fn fpb_layout(core: &mut Core) -> Result<(FpbRevision, u32), PlungerError> {
+    let ctrl = core.read_word_32(FP_CTRL)?;
+    let revision = match ctrl >> 28 {
+        0 => FpbRevision::Replace,
+        1 => FpbRevision::BreakpointAddress,
+        rev => {
+            return Err(PlungerError::Unsupported(format!(
+                "FPB revision {} is not supported",
+                rev + 1
+            )))
+        }
+    };
+    let available = ((ctrl >> 8) & 0x70) | ((ctrl >> 4) & 0x0f);
+    Ok((revision, available))
+}
+
+fn halt_reason(status: &CoreStatus) -> Option<&'static str> {
+    let reason = match status {
+        CoreStatus::Halted(HaltReason::Breakpoint) => "breakpoint",
+        CoreStatus::Halted(HaltReason::Watchpoint) => "watchpoint",
+        CoreStatus::Halted(HaltReason::Step) => "step",
+        CoreStatus::Halted(HaltReason::Request) => "request",
+        CoreStatus::Halted(HaltReason::External) => "external",
+        CoreStatus::Halted(HaltReason::Exception) => "exception",
+        CoreStatus::Halted(HaltReason::Multiple) => "multiple",
+        CoreStatus::Halted(_) => "unknown",
+        CoreStatus::LockedUp => "lockedUp",
+        _ => return None,
+    };
+
+    Some(reason)
+}
+
+fn read_pc(core: &mut Core) -> Result<u32, PlungerError> {
+    core_register("pc")
+        .ok_or_else(|| PlungerError::StateError("No program counter".to_string()))?
+        .read(core)
+}
+
+impl HaltPoints {
+    fn has_breakpoint(&self, address: u32) -> bool {
+        self.breakpoints.iter().any(|bp| bp.address == address)
+    }
+
+    pub fn set_breakpoint(&mut self, core: &mut Core, address: u32) -> Result<(), PlungerError> {
+        if self.has_breakpoint(address) {
+            return Ok(());
+        }
+
+        let (revision, available) = fpb_layout(core)?;
+        let value = fpb_comparator(address, revision)?;
+
+        let mut comparator = None;
+        for idx in 0..available {
+            let in_use = self.breakpoints.iter().any(|bp| bp.comparator == idx)
+                || core.read_word_32(FP_COMP_BASE + idx * 4)? & FP_COMP_ENABLE != 0;
+            if !in_use {
+                comparator = Some(idx);
+                break;
+            }
+        }
+        let comparator = comparator.ok_or_else(|| {
+            PlungerError::StateError(format!("All {} FPB comparators are in use", available))
+        })?;
+
+        core.write_word_32(FP_CTRL, FP_CTRL_KEY | FP_CTRL_ENABLE)?;
+        core.write_word_32(FP_COMP_BASE + comparator * 4, value)?;
+        self.breakpoints.push(Breakpoint {
+            address,
+            comparator,
+        });
+        Ok(())
+    }
+
+    /// Clears one breakpoint, or all of them without an address. Returns how many went.
+    pub fn clear_breakpoints(
+        &mut self,
+        core: &mut Core,
+        address: Option<u32>,
+    ) -> Result<usize, PlungerError> {
+        let cleared: Vec<Breakpoint> = self
+            .breakpoints
+            .iter()
+            .copied()
+            .filter(|bp| address.map(|address| address == bp.address) != Some(false))
+            .collect();
+
+        for bp in cleared.iter() {
+            core.write_word_32(FP_COMP_BASE + bp.comparator * 4, 0)?;
+            self.breakpoints
+                .retain(|other| other.comparator != bp.comparator);
+        }
+
+        Ok(cleared.len())
+    }
+
+    pub fn set_watchpoint(
+        &mut self,
+        core: &mut Core,
+        address: u32,
+        size: u32,
+        access: WatchAccess,
+    ) -> Result<Watchpoint, PlungerError> {
+        if !size.is_power_of_two() || address & (size - 1) != 0 {
+            return Err(PlungerError::InvalidOption(format!(
+                "Watchpoint size {} must be a power of two and 0x{:08x} aligned to it",
+                size, address
+            )));
+        }
+
+        let v8m = is_v8m(core)?;
+        let function = dwt_function(access, size, v8m)?;
+
+        let demcr = core.read_word_32(DEMCR)?;
+        core.write_word_32(DEMCR, demcr | DEMCR_TRCENA)?;
+
+        let available = core.read_word_32(DWT_CTRL)? >> 28;
+        let mut comparator = None;
+        for idx in 0..available {
+            let in_use = self.watchpoints.iter().any(|watch| watch.comparator == idx)
+                || core.read_word_32(comparator_register(idx, DWT_FUNCTION_OFFSET))? & 0x0f != 0;
+            if !in_use {
+                comparator = Some(idx);
+                break;
+            }
+        }
+        let comparator = comparator.ok_or_else(|| {
+            PlungerError::StateError(format!("All {} DWT comparators are in use", available))
+        })?;
+
+        core.write_word_32(comparator_register(comparator, 0), address)?;
+        if !v8m {
+            core.write_word_32(
+                comparator_register(comparator, DWT_MASK_OFFSET),
+                size.trailing_zeros(),
+            )?;
+        }
+        core.write_word_32(
+            comparator_register(comparator, DWT_FUNCTION_OFFSET),
+            function,
+        )?;
+
+        let watchpoint = Watchpoint {
+            address,
+            size,
+            access,
+            comparator,
+        };
+        self.watchpoints.push(watchpoint);
+        Ok(watchpoint)
+    }
+
+    /// Clears one watchpoint, or all of them without an address. Returns how many went.
+    pub fn clear_watchpoints(
+        &mut self,
+        core: &mut Core,
+        address: Option<u32>,
+    ) -> Result<usize, PlungerError> {
+        let cleared: Vec<Watchpoint> = self
+            .watchpoints
+            .iter()
+            .copied()
+            .filter(|watch| address.map(|address| address == watch.address) != Some(false))
+            .collect();
+
+        for watch in cleared.iter() {
+            core.write_word_32(
+                comparator_register(watch.comparator, DWT_FUNCTION_OFFSET),
+                0,
+            )?;
+            self.watchpoints
+                .retain(|other| other.comparator != watch.comparator);
+        }
+
+        Ok(cleared.len())
+    }
+
+    /// Why the core stopped, or None while it is still running
+    pub fn halt_info(&self, core: &mut Core) -> Result<Option<HaltInfo>, PlungerError> {
+        let reason = match halt_reason(&core.status()?) {
+            Some(reason) => reason,
+            None => return Ok(None),
+        };
+
+        let pc = read_pc(core)?;
+        let breakpoint = Some(pc).filter(|pc| self.has_breakpoint(*pc));
+
+        // Reading FUNCTION clears MATCHED, so this only reports each hit once
+        let mut watchpoint = None;
+        for watch in self.watchpoints.iter() {
+            let function =
+                core.read_word_32(comparator_register(watch.comparator, DWT_FUNCTION_OFFSET))?;
+            if function & DWT_FUNCTION_MATCHED != 0 {
+                watchpoint = Some(*watch);
+            }
+        }
+
+        Ok(Some(HaltInfo {
+            reason,
+            pc,
+            breakpoint,
+            watchpoint,
+        }))
+    }
+
+    /// Checks the core for the pending `waitForHalt` calls, all of them get the same halt
+    pub fn poll(&mut self, session: &mut Session) {
+        if self.waiters.is_empty() {
+            return;
+        }
+
+        let halted = session
+            .core(0)
+            .map_err(PlungerError::from)
+            .and_then(|mut core| self.halt_info(&mut core));
+
+        for waiter in std::mem::take(&mut self.waiters) {
+            let result = match &halted {
+                Ok(Some(info)) => Ok(info.clone()),
+                Ok(None) => match waiter.cancel.check() {
+                    Ok(_) => {
+                        self.waiters.push(waiter);
+                        continue;
+                    }
+                    Err(err) => Err(err),
+                },
+                Err(err) => Err(PlungerError::StateError(format!(
+                    "Cannot read core status: {}",
+                    err
+                ))),
+            };
+
+            let _ = waiter.done.send(result);
+        }
+    }
+
+    // The FPB would stop the core straight away again if it resumed on a breakpoint
+    pub fn resume(&self, core: &mut Core) -> Result<(), PlungerError> {
+        if core.core_halted()? && self.has_breakpoint(read_pc(core)?) {
+            core.step()?;
+        }
+
+        core.run()?;
+        Ok(())
+    }
+}
+
+pub fn location_arg(ctx: &CallContext, index: usize) -> napi::Result<Option<Location>> {
+    let arg = ctx.get::<JsUnknown>(index)?;
+    match arg.get_type()? {
+        ValueType::Undefined | ValueType::Null => Ok(None),
+        _ => Ok(Some(ctx.env.from_js_value(arg)?)),
+    }
+}
+
+pub fn location_options(ctx: &CallContext, index: usize) -> napi::Result<LocationOptions> {
+    match ctx.try_get::<JsObject>(index)? {
+        napi::Either::A(obj) => ctx.env.from_js_value(obj),
+        napi::Either::B(_) => Ok(LocationOptions::default()),
+    }
+}
+
+#[js_function(3)]
+pub fn set_breakpoint(ctx: CallContext) -> napi::Result<JsObject> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let location = location_arg(&ctx, 1)?
+        .ok_or_else(|| PlungerError::InvalidOption("Missing breakpoint address".to_string()))?;
+    let options = location_options(&ctx, 2)?;
+
+    let task = SessionTask::new(id, move |session| {
+        let address = location.resolve(options.elf_path.as_deref(), session.symbols.as_ref())?;
+        let mut core = session.session.core(0)?;
+        session.halt_points.set_breakpoint(&mut core, address)?;
+        Ok(address)
+    });
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
+
+#[js_function(3)]
+pub fn clear_breakpoint(ctx: CallContext) -> napi::Result<JsObject> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let location = location_arg(&ctx, 1)?;
+    let options = location_options(&ctx, 2)?;
+
+    let task = SessionTask::new(id, move |session| {
+        let address = location
+            .map(|location| location.resolve(options.elf_path.as_deref(), session.symbols.as_ref()))
+            .transpose()?;
+        let mut core = session.session.core(0)?;
+        session.halt_points.clear_breakpoints(&mut core, address)
+    });
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchpointOptions {
+    /// Defaults to write
+    access: Option<WatchAccess>,
+    elf_path: Option<String>,
+}
+
+#[js_function(4)]
+pub fn set_watchpoint(ctx: CallContext) -> napi::Result<JsObject> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let location = location_arg(&ctx, 1)?
+        .ok_or_else(|| PlungerError::InvalidOption("Missing watchpoint address".to_string()))?;
+    let size = ctx.get::<JsNumber>(2)?.get_uint32()?;
+    let options: WatchpointOptions = match ctx.try_get::<JsObject>(3)? {
+        napi::Either::A(obj) => ctx.env.from_js_value(obj)?,
+        napi::Either::B(_) => WatchpointOptions::default(),
+    };
+
+    let task = SessionTask::new(id, move |session| {
+        let address = location.resolve(options.elf_path.as_deref(), session.symbols.as_ref())?;
+        let access = options.access.unwrap_or(WatchAccess::Write);
+        let mut core = session.session.core(0)?;
+        session
+            .halt_points
+            .set_watchpoint(&mut core, address, size, access)
+    });
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
+
+#[js_function(3)]
+pub fn clear_watchpoint(ctx: CallContext) -> napi::Result<JsObject> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let location = location_arg(&ctx, 1)?;
+    let options = location_options(&ctx, 2)?;
+
+    let task = SessionTask::new(id, move |session| {
+        let address = location
+            .map(|location| location.resolve(options.elf_path.as_deref(), session.symbols.as_ref()))
+            .transpose()?;
+        let mut core = session.session.core(0)?;
+        session.halt_points.clear_watchpoints(&mut core, address)
+    });
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
+
+#[js_function(1)]
+pub fn halt_core(ctx: CallContext) -> napi::Result<JsObject> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let task = SessionTask::new(id, |session| {
+        let mut core = session.session.core(0)?;
+        if !core.core_halted()? {
+            core.halt(HALT_TIMEOUT)?;
+        }
+        session.halt_points.halt_info(&mut core)
+    });
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
+
+#[js_function(1)]
+pub fn resume_core(ctx: CallContext) -> napi::Result<JsObject> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let task = SessionTask::new(id, |session| {
+        let mut core = session.session.core(0)?;
+        session.halt_points.resume(&mut core)
+    });
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct WaitOptions {
+    cancel_token: Option<u32>,
+    timeout_ms: Option<u64>,
+}
+
+// Other session calls keep working while this waits, the session thread checks the core
+// between them and hands back the halt
+async fn halted(id: u32, cancel: CancelToken) -> napi::Result<HaltInfo> {
+    let (done, halt) = oneshot::channel();
+
+    let waiting = tokio::task::spawn_blocking(move || {
+        with_session(id, move |session| {
+            session
+                .halt_points
+                .waiters
+                .push(HaltWaiter { cancel, done });
+            Ok(())
+        })
+    })
+    .await;
+
+    match waiting {
+        Ok(waiting) => waiting?,
+        Err(err) => {
+            return Err(napi::Error {
+                status: napi::Status::Unknown,
+                reason: format!("Unexpected failure to wait for halt: {}", err),
+            })
+        }
+    }
+
+    // The sender goes away without a result if the session is closed meanwhile
+    Ok(halt
+        .await
+        .map_err(|_| PlungerError::StateError("Debug session has been closed".to_string()))??)
+}
+
+// Takes the options object or just the timeout in milliseconds
+#[js_function(2)]
+pub fn wait_for_halt(ctx: CallContext) -> napi::Result<JsObject> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let arg = ctx.get::<JsUnknown>(1)?;
+    let options: WaitOptions = match arg.get_type()? {
+        ValueType::Number => WaitOptions {
+            cancel_token: None,
+            timeout_ms: Some(ctx.get::<JsNumber>(1)?.get_int64()?.max(0) as u64),
+        },
+        ValueType::Object => ctx.env.from_js_value(arg)?,
+        _ => WaitOptions::default(),
+    };
+    let cancel = CancelToken::from_id(options.cancel_token)?.with_timeout(Duration::from_millis(
+        options.timeout_ms.unwrap_or(DEFAULT_WAIT_TIMEOUT_MS),
+    ));
+
+    ctx.env
+        .execute_tokio_future(halted(id, cancel), |&mut env, data| env.to_js_value(&data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fpb_rev1_picks_the_halfword() {
+        let cases = [
+            (0x0000_1234, 0x4000_1235),
+            (0x0000_1236, 0x8000_1235),
+            (0x0800_0100, 0x4800_0101),
+            (0x1fff_fffe, 0x9fff_fffd),
+        ];
+        for (address, expected) in cases.iter() {
+            assert_eq!(
+                fpb_comparator(*address, FpbRevision::Replace).unwrap(),
+                *expected,
+                "0x{:08x}",
+                address
+            );
+        }
+    }
+
+    #[test]
+    fn fpb_rev1_rejects_addresses_outside_code() {
+        assert!(matches!(
+            fpb_comparator(0x2000_0000, FpbRevision::Replace),
+            Err(PlungerError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn fpb_rev2_sets_breakpoint_enable() {
+        let cases = [
+            (0x0000_1234, 0x0000_1235),
+            (0x0000_1236, 0x0000_1237),
+            (0x2000_0100, 0x2000_0101),
+            (0xffff_fffe, 0xffff_ffff),
+        ];
+        for (address, expected) in cases.iter() {
+            assert_eq!(
+                fpb_comparator(*address, FpbRevision::BreakpointAddress).unwrap(),
+                *expected,
+                "0x{:08x}",
+                address
+            );
+        }
+    }
+
+    #[test]
+    fn fpb_rejects_odd_addresses() {
+        for revision in [FpbRevision::Replace, FpbRevision::BreakpointAddress].iter() {
+            assert!(matches!(
+                fpb_comparator(0x0000_1235, *revision),
+                Err(PlungerError::InvalidOption(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn dwt_v7m_function() {
+        let cases = [
+            (WatchAccess::Read, 0b0101),
+            (WatchAccess::Write, 0b0110),
+            (WatchAccess::ReadWrite, 0b0111),
+        ];
+        for (access, expected) in cases.iter() {
+            // The size goes into MASK on ARMv7-M, FUNCTION does not change with it
+            for size in [1, 4, 64].iter() {
+                assert_eq!(dwt_function(*access, *size, false).unwrap(), *expected);
+            }
+        }
+    }
+
+    #[test]
+    fn dwt_v8m_function() {
+        let cases = [
+            (WatchAccess::Read, 1, 0x0016),
+            (WatchAccess::Write, 1, 0x0015),
+            (WatchAccess::ReadWrite, 1, 0x0014),
+            (WatchAccess::Write, 2, 0x0415),
+            (WatchAccess::Write, 4, 0x0815),
+            (WatchAccess::ReadWrite, 4, 0x0814),
+        ];
+        for (access, size, expected) in cases.iter() {
+            assert_eq!(
+                dwt_function(*access, *size, true).unwrap(),
+                *expected,
+                "{:?} of {} bytes",
+                access,
+                size
+            );
+        }
+    }
+
+    #[test]
+    fn dwt_v8m_rejects_wide_ranges() {
+        assert!(matches!(
+            dwt_function(WatchAccess::Write, 8, true),
+            Err(PlungerError::Unsupported(_))
+        ));
+    }
+}
@@ -0,0 +1,512 @@
+use std::{
+    convert::TryFrom,
+    fs::File,
+    io::{BufWriter, Write},
+    time::Duration,
+};
+
+use napi::{
+    threadsafe_function::{ThreadSafeCallContext, ThreadsafeFunction, ThreadsafeFunctionCallMode},
+    CallContext, JsFunction, JsNumber, JsObject,
+};
+use probe_rs::{config::MemoryRegion, Core, MemoryInterface, Session};
+use serde::{Deserialize, Serialize};
+
+use crate::common::{cancel_token::CancelToken, plunger_error::PlungerError};
+
+use super::{
+    debug_session::SessionTask,
+    registers::{has_fpu, read_all, RegisterDump},
+};
+
+const HALT_TIMEOUT: Duration = Duration::from_millis(500);
+// Memory is read and written out in pieces this big, with a progress event after each
+const DUMP_CHUNK: u32 = 16 * 1024;
+
+const ELF_HEADER_SIZE: usize = 52;
+const ELF_PHDR_SIZE: usize = 32;
+const ET_CORE: u16 = 4;
+const EM_ARM: u16 = 40;
+const EF_ARM_EABI_VER5: u32 = 0x0500_0000;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+// Notes GDB reads from ARM core files, with the Linux layouts it expects
+const NT_PRSTATUS: u32 = 1;
+const NT_ARM_VFP: u32 = 0x400;
+const PRSTATUS_SIZE: usize = 148;
+const PRSTATUS_REGS_OFFSET: usize = 72;
+const VFP_SIZE: usize = 32 * 8 + 4;
+const NOTE_NAME: &[u8] = b"CORE\0";
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DumpRange {
+    start: u32,
+    end: u32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CoreDumpOptions {
+    path: String,
+    /// Peripheral registers to include next to RAM, reading some of them has side effects
+    peripheral_ranges: Option<Vec<DumpRange>>,
+    cancel_token: Option<u32>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum DumpRegionKind {
+    Ram,
+    Peripheral,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DumpRegion {
+    kind: DumpRegionKind,
+    start: u32,
+    size: u32,
+}
+
+/// `bytes` and `totalBytes` count over all regions
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CoreDumpProgress {
+    region: usize,
+    address: u32,
+    bytes: u64,
+    total_bytes: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoreDumpInfo {
+    path: String,
+    regions: Vec<DumpRegion>,
+    file_size: u64,
+    /// Also in the file, MSP, PSP and the other system registers only here
+    registers: RegisterDump,
+}
+
+fn dump_regions(session: &Session, options: &CoreDumpOptions) -> Vec<DumpRegion> {
+    let ram = session
+        .target()
+        .memory_map
+        .iter()
+        .filter_map(|region| match region {
+            MemoryRegion::Ram(ram) => Some((DumpRegionKind::Ram, &ram.range)),
+            _ => None,
+        })
+        .map(|(kind, range)| (kind, range.start, range.end));
+    let peripherals = options
+        .peripheral_ranges
+        .iter()
+        .flatten()
+        .map(|range| (DumpRegionKind::Peripheral, range.start, range.end));
+
+    let mut regions: Vec<DumpRegion> = Vec::new();
+    for (kind, start, end) in ram.chain(peripherals) {
+        // Some targets list the same RAM more than once, e.g. once per core
+        if end <= start || regions.iter().any(|region| region.start == start) {
+            continue;
+        }
+        regions.push(DumpRegion {
+            kind,
+            start,
+            size: end - start,
+        });
+    }
+
+    regions
+}
+
+fn note(kind: u32, desc: &[u8]) -> Vec<u8> {
+    let mut note = Vec::new();
+    note.extend_from_slice(&(NOTE_NAME.len() as u32).to_le_bytes());
+    note.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+    note.extend_from_slice(&kind.to_le_bytes());
+    note.extend_from_slice(NOTE_NAME);
+    note.resize((note.len() + 3) & !3, 0);
+    note.extend_from_slice(desc);
+    note.resize((note.len() + 3) & !3, 0);
+    note
+}
+
+fn register(registers: &RegisterDump, name: &str) -> u32 {
+    registers.get(name).unwrap_or_default()
+}
+
+// r0-r15 and xPSR in the CPSR slot, everything else in elf_prstatus stays zero
+fn prstatus_note(registers: &RegisterDump) -> Vec<u8> {
+    let mut prstatus = vec![0u8; PRSTATUS_SIZE];
+    let names = (0..13).map(|idx| format!("r{}", idx)).chain(
+        ["sp", "lr", "pc", "xpsr"]
+            .iter()
+            .map(|name| name.to_string()),
+    );
+
+    for (idx, name) in names.enumerate() {
+        let offset = PRSTATUS_REGS_OFFSET + idx * 4;
+        prstatus[offset..offset + 4].copy_from_slice(&register(registers, &name).to_le_bytes());
+    }
+
+    note(NT_PRSTATUS, &prstatus)
+}
+
+// Pairs of single precision registers make up d0-d15, d16-d31 do not exist on Cortex-M
+fn vfp_note(registers: &RegisterDump) -> Vec<u8> {
+    let mut vfp = vec![0u8; VFP_SIZE];
+    for idx in 0..32 {
+        let offset = idx * 4;
+        let value = register(registers, &format!("s{}", idx));
+        vfp[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+    vfp[VFP_SIZE - 4..].copy_from_slice(&register(registers, "fpscr").to_le_bytes());
+
+    note(NT_ARM_VFP, &vfp)
+}
+
+fn elf_header(phnum: usize) -> Vec<u8> {
+    let mut header = Vec::with_capacity(ELF_HEADER_SIZE);
+    // 32 bit, little endian, version 1, System V ABI
+    header.extend_from_slice(b"\x7fELF\x01\x01\x01\x00");
+    header.resize(16, 0);
+    header.extend_from_slice(&ET_CORE.to_le_bytes());
+    header.extend_from_slice(&EM_ARM.to_le_bytes());
+    header.extend_from_slice(&1u32.to_le_bytes());
+    // Entry point, program and section header offsets
+    header.extend_from_slice(&0u32.to_le_bytes());
+    header.extend_from_slice(&(ELF_HEADER_SIZE as u32).to_le_bytes());
+    header.extend_from_slice(&0u32.to_le_bytes());
+    header.extend_from_slice(&EF_ARM_EABI_VER5.to_le_bytes());
+    header.extend_from_slice(&(ELF_HEADER_SIZE as u16).to_le_bytes());
+    header.extend_from_slice(&(ELF_PHDR_SIZE as u16).to_le_bytes());
+    header.extend_from_slice(&(phnum as u16).to_le_bytes());
+    // No section headers
+    header.extend_from_slice(&[0u8; 6]);
+    header
+}
+
+fn program_header(kind: u32, offset: u32, address: u32, size: u32, flags: u32) -> Vec<u8> {
+    [kind, offset, address, address, size, size, flags, 1]
+        .iter()
+        .flat_map(|field| field.to_le_bytes())
+        .collect()
+}
+
+/// Everything in the file before the memory contents: the ELF and program headers and the
+/// notes. Also returns the size of the whole file.
+fn dump_headers(
+    regions: &[DumpRegion],
+    registers: &RegisterDump,
+    fpu: bool,
+) -> Result<(Vec<u8>, u64), PlungerError> {
+    let mut notes = prstatus_note(registers);
+    if fpu {
+        notes.extend(vfp_note(registers));
+    }
+
+    let phnum = regions.len() + 1;
+    let notes_offset = ELF_HEADER_SIZE + phnum * ELF_PHDR_SIZE;
+    let mut headers = elf_header(phnum);
+    headers.extend(program_header(
+        PT_NOTE,
+        notes_offset as u32,
+        0,
+        notes.len() as u32,
+        0,
+    ));
+
+    // ELF32 offsets are 32 bit, so the regions have to fit in 4 GiB together
+    let too_large =
+        || PlungerError::InvalidOption("Core dump regions do not fit in a 4 GiB file".to_string());
+    let mut offset = u32::try_from(notes_offset + notes.len()).map_err(|_| too_large())?;
+    for region in regions {
+        headers.extend(program_header(
+            PT_LOAD,
+            offset,
+            region.start,
+            region.size,
+            PF_R | PF_W,
+        ));
+        offset = offset.checked_add(region.size).ok_or_else(too_large)?;
+    }
+
+    headers.extend(notes);
+    Ok((headers, offset as u64))
+}
+
+fn write_dump(
+    core: &mut Core,
+    options: &CoreDumpOptions,
+    regions: &[DumpRegion],
+    registers: &RegisterDump,
+    fpu: bool,
+    cancel: &CancelToken,
+    progress: &Option<ThreadsafeFunction<CoreDumpProgress>>,
+) -> Result<u64, PlungerError> {
+    let (headers, file_size) = dump_headers(regions, registers, fpu)?;
+
+    let io_error = |err: std::io::Error| {
+        PlungerError::InvalidOption(format!("Cannot write core dump {}: {}", options.path, err))
+    };
+    let mut file = BufWriter::new(File::create(&options.path).map_err(io_error)?);
+    file.write_all(&headers).map_err(io_error)?;
+
+    let total_bytes: u64 = regions.iter().map(|region| region.size as u64).sum();
+    let mut bytes = 0u64;
+    let mut chunk = vec![0u8; DUMP_CHUNK as usize];
+    for (index, region) in regions.iter().enumerate() {
+        let end = region.start as u64 + region.size as u64;
+        let mut address = region.start;
+        while (address as u64) < end {
+            cancel.check()?;
+
+            let len = (end - address as u64).min(DUMP_CHUNK as u64) as usize;
+            core.read_8(address, &mut chunk[..len])?;
+            file.write_all(&chunk[..len]).map_err(io_error)?;
+
+            address = address.wrapping_add(len as u32);
+            bytes += len as u64;
+            if let Some(callback) = progress {
+                callback.call(
+                    Ok(CoreDumpProgress {
+                        region: index,
+                        address,
+                        bytes,
+                        total_bytes,
+                    }),
+                    ThreadsafeFunctionCallMode::NonBlocking,
+                );
+            }
+        }
+    }
+
+    file.flush().map_err(io_error)?;
+    Ok(file_size)
+}
+
+/// Halts the core and writes its registers and memory to an ELF core file GDB can load
+/// next to the firmware's ELF file. The core is left halted.
+pub fn capture(
+    session: &mut Session,
+    options: &CoreDumpOptions,
+    cancel: &CancelToken,
+    progress: &Option<ThreadsafeFunction<CoreDumpProgress>>,
+) -> Result<CoreDumpInfo, PlungerError> {
+    let regions = dump_regions(session, options);
+    let mut core = session.core(0)?;
+    if !core.core_halted()? {
+        core.halt(HALT_TIMEOUT)?;
+    }
+
+    let registers = read_all(&mut core)?;
+    let fpu = has_fpu(&mut core)?;
+    let file_size = write_dump(
+        &mut core, options, &regions, &registers, fpu, cancel, progress,
+    )?;
+
+    Ok(CoreDumpInfo {
+        path: options.path.clone(),
+        regions,
+        file_size,
+        registers,
+    })
+}
+
+#[js_function(3)]
+pub fn capture_core_dump(ctx: CallContext) -> napi::Result<JsObject> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let options: CoreDumpOptions = ctx.env.from_js_value(ctx.get::<JsObject>(1)?)?;
+    let cancel = CancelToken::from_id(options.cancel_token)?;
+    let progress = match ctx.try_get::<JsFunction>(2)? {
+        napi::Either::A(func) => Some(ctx.env.create_threadsafe_function(
+            &func,
+            0,
+            |ctx: ThreadSafeCallContext<CoreDumpProgress>| {
+                Ok(vec![ctx.env.to_js_value(&ctx.value)?])
+            },
+        )?),
+        napi::Either::B(_) => None,
+    };
+
+    let task = SessionTask::new(id, move |session| {
+        capture(&mut session.session, &options, &cancel, &progress)
+    });
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
+
+#[cfg(test)]
+mod tests {
+    use object::{
+        elf::{FileHeader32, ET_CORE as ELF_ET_CORE},
+        read::elf::{FileHeader, ProgramHeader},
+        LittleEndian,
+    };
+
+    use super::*;
+
+    fn registers(fpu: bool) -> RegisterDump {
+        let mut values: Vec<(String, u32)> = (0..13)
+            .map(|idx| (format!("r{}", idx), 0x100 + idx))
+            .collect();
+        values.push(("sp".to_string(), 0x2000_fff0));
+        values.push(("lr".to_string(), 0xffff_fff9));
+        values.push(("pc".to_string(), 0x0000_1234));
+        values.push(("xpsr".to_string(), 0x0100_0003));
+        values.push(("msp".to_string(), 0x2000_fff0));
+        if fpu {
+            values.extend((0..32).map(|idx| (format!("s{}", idx), 0x3f80_0000 + idx)));
+            values.push(("fpscr".to_string(), 0x0300_0000));
+        }
+        RegisterDump(values)
+    }
+
+    fn regions() -> Vec<DumpRegion> {
+        vec![
+            DumpRegion {
+                kind: DumpRegionKind::Ram,
+                start: 0x2000_0000,
+                size: 0x100,
+            },
+            DumpRegion {
+                kind: DumpRegionKind::Peripheral,
+                start: 0x4000_0000,
+                size: 0x10,
+            },
+        ]
+    }
+
+    // Headers followed by each region filled with its index, like write_dump lays it out
+    fn build(regions: &[DumpRegion], fpu: bool) -> (Vec<u8>, u64) {
+        let (mut dump, file_size) = dump_headers(regions, &registers(fpu), fpu).unwrap();
+        for (idx, region) in regions.iter().enumerate() {
+            dump.resize(dump.len() + region.size as usize, idx as u8 + 1);
+        }
+        (dump, file_size)
+    }
+
+    fn word(bytes: &[u8], offset: usize) -> u32 {
+        let mut word = [0u8; 4];
+        word.copy_from_slice(&bytes[offset..offset + 4]);
+        u32::from_le_bytes(word)
+    }
+
+    #[test]
+    fn writes_an_arm_core_file() {
+        let (dump, file_size) = build(&regions(), true);
+        assert_eq!(dump.len() as u64, file_size);
+
+        let endian = LittleEndian;
+        let header = FileHeader32::<LittleEndian>::parse(&*dump).unwrap();
+        assert!(!header.is_class_64());
+        assert!(header.is_little_endian());
+        assert_eq!(header.e_type(endian), ELF_ET_CORE);
+        assert_eq!(header.e_machine(endian), EM_ARM);
+        assert_eq!(header.e_flags(endian), EF_ARM_EABI_VER5);
+        assert_eq!(header.e_phoff(endian), ELF_HEADER_SIZE as u32);
+        assert_eq!(header.e_phentsize(endian), ELF_PHDR_SIZE as u16);
+        assert_eq!(header.e_shnum(endian), 0);
+
+        let phdrs = header.program_headers(endian, &*dump).unwrap();
+        assert_eq!(phdrs.len(), 3);
+        assert_eq!(phdrs[0].p_type(endian), PT_NOTE);
+        assert_eq!(
+            phdrs[0].p_offset(endian),
+            (ELF_HEADER_SIZE + 3 * ELF_PHDR_SIZE) as u32
+        );
+        assert_eq!(
+            phdrs[1].p_offset(endian),
+            phdrs[0].p_offset(endian) + phdrs[0].p_filesz(endian)
+        );
+    }
+
+    #[test]
+    fn writes_prstatus_and_vfp_notes() {
+        let (dump, _) = build(&regions(), true);
+        let endian = LittleEndian;
+        let header = FileHeader32::<LittleEndian>::parse(&*dump).unwrap();
+        let phdrs = header.program_headers(endian, &*dump).unwrap();
+        let mut notes = phdrs[0].notes(endian, &*dump).unwrap().unwrap();
+
+        let prstatus = notes.next().unwrap().unwrap();
+        assert_eq!(prstatus.name(), b"CORE");
+        assert_eq!(prstatus.n_type(endian), NT_PRSTATUS);
+        let desc = prstatus.desc();
+        assert_eq!(desc.len(), 148);
+        assert_eq!(word(desc, 72), 0x100);
+        assert_eq!(word(desc, 72 + 12 * 4), 0x10c);
+        assert_eq!(word(desc, 72 + 13 * 4), 0x2000_fff0);
+        assert_eq!(word(desc, 72 + 15 * 4), 0x0000_1234);
+        assert_eq!(word(desc, 72 + 16 * 4), 0x0100_0003);
+        assert_eq!(word(desc, 72 + 17 * 4), 0);
+
+        let vfp = notes.next().unwrap().unwrap();
+        assert_eq!(vfp.name(), b"CORE");
+        assert_eq!(vfp.n_type(endian), NT_ARM_VFP);
+        let desc = vfp.desc();
+        assert_eq!(desc.len(), 260);
+        assert_eq!(word(desc, 0), 0x3f80_0000);
+        assert_eq!(word(desc, 31 * 4), 0x3f80_001f);
+        assert_eq!(word(desc, 256), 0x0300_0000);
+
+        assert!(notes.next().unwrap().is_none());
+    }
+
+    #[test]
+    fn leaves_out_vfp_without_fpu() {
+        let (dump, _) = build(&regions(), false);
+        let endian = LittleEndian;
+        let header = FileHeader32::<LittleEndian>::parse(&*dump).unwrap();
+        let phdrs = header.program_headers(endian, &*dump).unwrap();
+        let mut notes = phdrs[0].notes(endian, &*dump).unwrap().unwrap();
+
+        assert_eq!(notes.next().unwrap().unwrap().n_type(endian), NT_PRSTATUS);
+        assert!(notes.next().unwrap().is_none());
+    }
+
+    #[test]
+    fn loads_point_at_region_contents() {
+        let regions = regions();
+        let (dump, _) = build(&regions, true);
+        let endian = LittleEndian;
+        let header = FileHeader32::<LittleEndian>::parse(&*dump).unwrap();
+        let phdrs = header.program_headers(endian, &*dump).unwrap();
+
+        for (idx, (phdr, region)) in phdrs[1..].iter().zip(regions.iter()).enumerate() {
+            assert_eq!(phdr.p_type(endian), PT_LOAD);
+            assert_eq!(phdr.p_vaddr(endian), region.start);
+            assert_eq!(phdr.p_paddr(endian), region.start);
+            assert_eq!(phdr.p_filesz(endian), region.size);
+            assert_eq!(phdr.p_memsz(endian), region.size);
+            assert_eq!(phdr.p_flags(endian), PF_R | PF_W);
+
+            let data = phdr.data(endian, &*dump).unwrap();
+            assert!(data.iter().all(|byte| *byte == idx as u8 + 1));
+        }
+    }
+
+    #[test]
+    fn rejects_regions_past_4_gib() {
+        let regions = [
+            DumpRegion {
+                kind: DumpRegionKind::Ram,
+                start: 0,
+                size: 0xffff_0000,
+            },
+            DumpRegion {
+                kind: DumpRegionKind::Peripheral,
+                start: 0xffff_0000,
+                size: 0x1_0000,
+            },
+        ];
+        assert!(matches!(
+            dump_headers(&regions, &registers(false), false),
+            Err(PlungerError::InvalidOption(_))
+        ));
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use lazy_static::lazy_static;
+use napi::{JsUnknown, Task};
+use probe_rs::{DebugProbeSelector, Session};
+use serde::Serialize;
+
+use crate::{
+    common::{
+        cancel_token::CancelToken,
+        connect_options::{AttachMode, ConnectOptions},
+        plunger_error::PlungerError,
+        probe_lock::ProbeLock,
+    },
+    svd::device::Device,
+    symbols::elf_resolver::ElfResolver,
+};
+
+use super::{
+    breakpoints::HaltPoints, gdb_server::GdbServer, rtt::RttLink, semihosting::SemihostingRun,
+    swo::SwoLink,
+};
+
+// How long the session thread sleeps between background polls (RTT, SWO and friends)
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+type SessionCommand = Box<dyn FnOnce(&mut DebugSession) + Send>;
+
+struct SessionHandle {
+    commands: mpsc::Sender<SessionCommand>,
+    thread: JoinHandle<()>,
+}
+
+lazy_static! {
+    static ref SESSIONS: Mutex<HashMap<u32, SessionHandle>> = Mutex::new(HashMap::new());
+}
+
+static NEXT_SESSION_ID: AtomicU32 = AtomicU32::new(1);
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo {
+    pub id: u32,
+    pub target_name: String,
+    pub probe_name: String,
+    pub speed_khz: u32,
+}
+
+/// A probe-rs session kept open between calls. It lives on its own thread, which also
+/// holds the probe lock, so other operations on the probe wait (or fail fast) until it closes.
+pub struct DebugSession {
+    pub session: Session,
+    pub lock: ProbeLock,
+    pub halt_points: HaltPoints,
+    pub rtt: Option<RttLink>,
+    pub semihosting: Option<SemihostingRun>,
+    pub swo: Option<SwoLink>,
+    pub gdb: Option<GdbServer>,
+    /// Firmware given when opening the session, for APIs that take symbol names
+    pub symbols: Option<ElfResolver>,
+    /// SVD file given when opening the session, for decoding peripheral registers
+    pub svd: Option<Device>,
+}
+
+impl DebugSession {
+    // Background work done between commands
+    fn poll(&mut self) {
+        self.halt_points.poll(&mut self.session);
+
+        if let Some(rtt) = &mut self.rtt {
+            if let Err(err) = rtt.poll(&mut self.session) {
+                rtt.report_error(err);
+                self.rtt = None;
+            }
+        }
+
+        if let Some(swo) = &mut self.swo {
+            if let Err(err) = swo.poll(&mut self.session) {
+                swo.report_error(err);
+                self.swo = None;
+            }
+        }
+
+        if let Some(run) = &mut self.semihosting {
+            if run.poll(&mut self.session) {
+                self.semihosting = None;
+            }
+        }
+    }
+}
+
+fn lock_error<T>(err: T) -> PlungerError
+where
+    T: std::fmt::Display,
+{
+    PlungerError::StateError(format!("Cannot acquire session lock: {}", err))
+}
+
+fn closed_error<T>(_err: T) -> PlungerError {
+    PlungerError::StateError("Debug session has been closed".to_string())
+}
+
+pub fn open_session(
+    selector: DebugProbeSelector,
+    target_name: String,
+    connect: ConnectOptions,
+    symbols: Option<ElfResolver>,
+    svd: Option<Device>,
+    cancel: CancelToken,
+) -> Result<SessionInfo, PlungerError> {
+    let id = NEXT_SESSION_ID.fetch_add(1, Ordering::SeqCst);
+    let (ready_tx, ready_rx) = mpsc::channel::<Result<SessionInfo, PlungerError>>();
+    let (commands, command_rx) = mpsc::channel::<SessionCommand>();
+
+    let thread = thread::spawn(move || {
+        let opened = connect.lock(&selector, &cancel).and_then(|lock| {
+            let connection = connect.connect(selector, target_name.clone(), AttachMode::Normal)?;
+            Ok((lock, connection))
+        });
+
+        let (lock, connection) = match opened {
+            Ok(opened) => opened,
+            Err(err) => {
+                let _ = ready_tx.send(Err(err));
+                return;
+            }
+        };
+
+        let _ = ready_tx.send(Ok(SessionInfo {
+            id,
+            target_name,
+            probe_name: connection.probe_name,
+            speed_khz: connection.speed_khz,
+        }));
+
+        let mut session = DebugSession {
+            session: connection.session,
+            lock,
+            halt_points: HaltPoints::default(),
+            rtt: None,
+            semihosting: None,
+            swo: None,
+            gdb: None,
+            symbols,
+            svd,
+        };
+
+        // Runs until the handle is dropped by `close_session`
+        loop {
+            match command_rx.recv_timeout(POLL_INTERVAL) {
+                Ok(command) => command(&mut session),
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            session.poll();
+        }
+    });
+
+    let info = ready_rx.recv().map_err(closed_error)??;
+    SESSIONS
+        .lock()
+        .map_err(lock_error)?
+        .insert(id, SessionHandle { commands, thread });
+
+    Ok(info)
+}
+
+/// Returns once the session thread has let go of the probe
+pub fn close_session(id: u32) -> Result<bool, PlungerError> {
+    let handle = match SESSIONS.lock().map_err(lock_error)?.remove(&id) {
+        Some(handle) => handle,
+        None => return Ok(false),
+    };
+
+    drop(handle.commands);
+    handle
+        .thread
+        .join()
+        .map_err(|_| PlungerError::StateError("Debug session thread panicked".to_string()))?;
+
+    Ok(true)
+}
+
+/// Runs `command` on the session thread and waits for its result
+pub fn with_session<T, F>(id: u32, command: F) -> Result<T, PlungerError>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut DebugSession) -> Result<T, PlungerError> + Send + 'static,
+{
+    let commands = SESSIONS
+        .lock()
+        .map_err(lock_error)?
+        .get(&id)
+        .map(|handle| handle.commands.clone())
+        .ok_or(PlungerError::SessionNotFound(id))?;
+
+    let (result_tx, result_rx) = mpsc::channel();
+    commands
+        .send(Box::new(move |session: &mut DebugSession| {
+            let _ = result_tx.send(command(session));
+        }))
+        .map_err(closed_error)?;
+
+    result_rx.recv().map_err(closed_error)?
+}
+
+pub type SessionFn<T> = Box<dyn FnOnce(&mut DebugSession) -> Result<T, PlungerError> + Send>;
+
+/// Resolves with whatever the command returns on the session thread
+pub struct SessionTask<T> {
+    id: u32,
+    command: Option<SessionFn<T>>,
+}
+
+impl<T> SessionTask<T> {
+    pub fn new(
+        id: u32,
+        command: impl FnOnce(&mut DebugSession) -> Result<T, PlungerError> + Send + 'static,
+    ) -> SessionTask<T> {
+        SessionTask {
+            id,
+            command: Some(Box::new(command)),
+        }
+    }
+}
+
+impl<T: Serialize + Send + 'static> Task for SessionTask<T> {
+    type Output = T;
+    type JsValue = JsUnknown;
+
+    fn compute(&mut self) -> napi::Result<Self::Output> {
+        let command = self
+            .command
+            .take()
+            .ok_or_else(|| PlungerError::StateError("Session command already ran".to_string()))?;
+
+        Ok(with_session(self.id, command)?)
+    }
+
+    fn resolve(self, env: napi::Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+        env.to_js_value(&output)
+    }
+
+    fn reject(self, _env: napi::Env, err: napi::Error) -> napi::Result<Self::JsValue> {
+        Err(err)
+    }
+}
@@ -0,0 +1,611 @@
+use std::time::Duration;
+
+use napi::{CallContext, JsNumber, JsObject};
+use probe_rs::{Core, CoreStatus, MemoryInterface};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::plunger_error::PlungerError,
+    symbols::elf_resolver::{ElfResolver, SourceLocation},
+};
+
+use super::{debug_session::SessionTask, registers::core_register};
+
+const HALT_TIMEOUT: Duration = Duration::from_millis(500);
+
+const SCB_CPUID: u32 = 0xe000_ed00;
+const SCB_CFSR: u32 = 0xe000_ed28;
+const SCB_HFSR: u32 = 0xe000_ed2c;
+const SCB_DFSR: u32 = 0xe000_ed30;
+const SCB_MMFAR: u32 = 0xe000_ed34;
+const SCB_BFAR: u32 = 0xe000_ed38;
+const SCB_AFSR: u32 = 0xe000_ed3c;
+
+// CPUID architecture field of ARMv6-M parts, which have no configurable fault status registers
+const ARCH_V6M: u32 = 0xc;
+
+const CFSR_MSTKERR: u32 = 1 << 4;
+const CFSR_MMARVALID: u32 = 1 << 7;
+const CFSR_STKERR: u32 = 1 << 12;
+const CFSR_BFARVALID: u32 = 1 << 15;
+const HFSR_FORCED: u32 = 1 << 30;
+
+// EXC_RETURN: bit 2 picks the process stack, bit 4 clear means an FP context was stacked
+const EXC_RETURN_PSP: u32 = 1 << 2;
+const EXC_RETURN_STANDARD_FRAME: u32 = 1 << 4;
+// R0-R3, R12, LR, PC and xPSR, then S0-S15, FPSCR and a reserved word
+const STANDARD_FRAME_SIZE: u32 = 0x20;
+const EXTENDED_FRAME_SIZE: u32 = 0x68;
+// Stacked xPSR bit 9, set when the core padded the stack to 8-byte alignment
+const XPSR_STACK_ALIGN: u32 = 1 << 9;
+
+// Bit, name and what it means, in the order they are worth reporting
+const CFSR_FLAGS: [(u32, &str, &str); 17] = [
+    (9, "PRECISERR", "precise data bus error"),
+    (10, "IMPRECISERR", "imprecise data bus error"),
+    (8, "IBUSERR", "instruction bus error"),
+    (12, "STKERR", "bus error while stacking for an exception"),
+    (
+        11,
+        "UNSTKERR",
+        "bus error while unstacking from an exception",
+    ),
+    (13, "LSPERR", "bus error during lazy FP state preservation"),
+    (1, "DACCVIOL", "MPU data access violation"),
+    (0, "IACCVIOL", "MPU instruction fetch violation"),
+    (
+        4,
+        "MSTKERR",
+        "MPU violation while stacking for an exception",
+    ),
+    (
+        3,
+        "MUNSTKERR",
+        "MPU violation while unstacking from an exception",
+    ),
+    (
+        5,
+        "MLSPERR",
+        "MPU violation during lazy FP state preservation",
+    ),
+    (16, "UNDEFINSTR", "undefined instruction"),
+    (
+        17,
+        "INVSTATE",
+        "invalid execution state, e.g. branch to an address without the Thumb bit",
+    ),
+    (18, "INVPC", "invalid EXC_RETURN value on exception return"),
+    (
+        19,
+        "NOCP",
+        "coprocessor access while it is disabled, e.g. FPU not enabled",
+    ),
+    (24, "UNALIGNED", "unaligned memory access"),
+    (25, "DIVBYZERO", "division by zero"),
+];
+
+const HFSR_FLAGS: [(u32, &str, &str); 3] = [
+    (1, "VECTTBL", "bus fault reading the vector table"),
+    (30, "FORCED", "configurable fault escalated to HardFault"),
+    (
+        31,
+        "DEBUGEVT",
+        "debug event while halting debug is disabled",
+    ),
+];
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct FaultOptions {
+    /// Firmware the target runs, used to name the functions PC and LR are in.
+    /// Defaults to the session's ELF file
+    elf_path: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FaultStatus {
+    cfsr: u32,
+    hfsr: u32,
+    dfsr: u32,
+    mmfar: u32,
+    bfar: u32,
+    afsr: u32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FaultFlag {
+    register: &'static str,
+    name: &'static str,
+    description: &'static str,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExceptionFrame {
+    stack: &'static str,
+    address: u32,
+    r0: u32,
+    r1: u32,
+    r2: u32,
+    r3: u32,
+    r12: u32,
+    lr: u32,
+    pc: u32,
+    xpsr: u32,
+    fp_context: bool,
+    /// Bytes pushed on exception entry, so `address + size` is the stack pointer before it
+    size: u32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeLocation {
+    address: u32,
+    symbol: Option<String>,
+    offset: Option<u32>,
+    /// Source line from the DWARF info, if the ELF file has any
+    source: Option<SourceLocation>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FaultReport {
+    /// Active exception number from IPSR, zero in thread mode
+    exception_number: u32,
+    exception: Option<String>,
+    locked_up: bool,
+    /// Not present on ARMv6-M (Cortex-M0/M0+) cores
+    status: Option<FaultStatus>,
+    flags: Vec<FaultFlag>,
+    fault_address: Option<u32>,
+    /// Registers the core pushed on exception entry, missing if LR no longer holds EXC_RETURN
+    /// or stacking failed
+    frame: Option<ExceptionFrame>,
+    /// Where the fault happened, taken from the frame when there is one
+    pc: CodeLocation,
+    lr: CodeLocation,
+    summary: String,
+}
+
+fn exception_name(number: u32) -> Option<String> {
+    let name = match number {
+        0 => return None,
+        2 => "NMI",
+        3 => "HardFault",
+        4 => "MemManage",
+        5 => "BusFault",
+        6 => "UsageFault",
+        7 => "SecureFault",
+        11 => "SVCall",
+        12 => "DebugMonitor",
+        14 => "PendSV",
+        15 => "SysTick",
+        irq if irq >= 16 => return Some(format!("IRQ{}", irq - 16)),
+        _ => "Reserved",
+    };
+
+    Some(name.to_string())
+}
+
+fn decode_flags(status: &FaultStatus) -> Vec<FaultFlag> {
+    let cfsr = CFSR_FLAGS
+        .iter()
+        .filter(|(bit, _, _)| status.cfsr & (1 << bit) != 0)
+        .map(|(_, name, description)| FaultFlag {
+            register: "CFSR",
+            name,
+            description,
+        });
+    let hfsr = HFSR_FLAGS
+        .iter()
+        .filter(|(bit, _, _)| status.hfsr & (1 << bit) != 0)
+        .map(|(_, name, description)| FaultFlag {
+            register: "HFSR",
+            name,
+            description,
+        });
+
+    cfsr.chain(hfsr).collect()
+}
+
+// MMFAR and BFAR only hold the faulting address while their valid bit is set
+fn fault_address(status: &FaultStatus) -> Option<u32> {
+    if status.cfsr & CFSR_MMARVALID != 0 {
+        Some(status.mmfar)
+    } else if status.cfsr & CFSR_BFARVALID != 0 {
+        Some(status.bfar)
+    } else {
+        None
+    }
+}
+
+fn frame_stack(exc_return: u32) -> &'static str {
+    match exc_return & EXC_RETURN_PSP {
+        0 => "msp",
+        _ => "psp",
+    }
+}
+
+fn frame_size(exc_return: u32, stacked_xpsr: u32) -> u32 {
+    let size = match exc_return & EXC_RETURN_STANDARD_FRAME {
+        0 => EXTENDED_FRAME_SIZE,
+        _ => STANDARD_FRAME_SIZE,
+    };
+
+    match stacked_xpsr & XPSR_STACK_ALIGN {
+        0 => size,
+        _ => size + 4,
+    }
+}
+
+fn read_status(core: &mut Core) -> Result<Option<FaultStatus>, PlungerError> {
+    let cpuid = core.read_word_32(SCB_CPUID)?;
+    if (cpuid >> 16) & 0x0f == ARCH_V6M {
+        return Ok(None);
+    }
+
+    Ok(Some(FaultStatus {
+        cfsr: core.read_word_32(SCB_CFSR)?,
+        hfsr: core.read_word_32(SCB_HFSR)?,
+        dfsr: core.read_word_32(SCB_DFSR)?,
+        mmfar: core.read_word_32(SCB_MMFAR)?,
+        bfar: core.read_word_32(SCB_BFAR)?,
+        afsr: core.read_word_32(SCB_AFSR)?,
+    }))
+}
+
+// Only possible while LR still holds EXC_RETURN, i.e. the handler has not called anything,
+// and the core managed to push the frame
+fn read_frame(
+    core: &mut Core,
+    lr: u32,
+    status: Option<&FaultStatus>,
+) -> Result<Option<ExceptionFrame>, PlungerError> {
+    if lr >> 24 != 0xff {
+        return Ok(None);
+    }
+    if let Some(status) = status {
+        if status.cfsr & (CFSR_STKERR | CFSR_MSTKERR) != 0 {
+            return Ok(None);
+        }
+    }
+
+    let stack = frame_stack(lr);
+    let address = read_register(core, stack)?;
+
+    // A stack pointer run off the end of RAM leaves nothing to read, ARMv6-M has no STKERR
+    let mut words = [0u32; 8];
+    if core.read_32(address, &mut words).is_err() {
+        return Ok(None);
+    }
+
+    Ok(Some(ExceptionFrame {
+        stack,
+        address,
+        r0: words[0],
+        r1: words[1],
+        r2: words[2],
+        r3: words[3],
+        r12: words[4],
+        lr: words[5],
+        pc: words[6],
+        xpsr: words[7],
+        fp_context: lr & EXC_RETURN_STANDARD_FRAME == 0,
+        size: frame_size(lr, words[7]),
+    }))
+}
+
+fn read_register(core: &mut Core, name: &str) -> Result<u32, PlungerError> {
+    let reg = core_register(name)
+        .ok_or_else(|| PlungerError::StateError(format!("No core register {}", name)))?;
+    reg.read(core)
+}
+
+fn locate(address: u32, symbols: Option<&ElfResolver>) -> Result<CodeLocation, PlungerError> {
+    let (symbol, source) = match symbols {
+        Some(symbols) => (
+            symbols.find_at(address & !1)?,
+            symbols.source_location(address & !1)?,
+        ),
+        None => (None, None),
+    };
+
+    Ok(CodeLocation {
+        address,
+        offset: symbol
+            .as_ref()
+            .map(|symbol| (address & !1) - symbol.address),
+        symbol: symbol.map(|symbol| symbol.name),
+        source,
+    })
+}
+
+impl std::fmt::Display for CodeLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.symbol, self.offset) {
+            (Some(symbol), Some(offset)) => {
+                write!(f, "0x{:08x} ({}+0x{:x})", self.address, symbol, offset)?
+            }
+            _ => write!(f, "0x{:08x}", self.address)?,
+        }
+
+        match &self.source {
+            Some(source) => write!(f, " in {}", source),
+            None => Ok(()),
+        }
+    }
+}
+
+fn summarise(report: &FaultReport) -> String {
+    let cause = report
+        .flags
+        .iter()
+        .find(|flag| flag.register == "CFSR")
+        .or_else(|| report.flags.first());
+
+    let mut summary = match (cause, &report.exception) {
+        (Some(flag), _) => flag.description.to_string(),
+        (None, Some(exception)) => format!("{} without fault status", exception),
+        (None, None) => "no active exception".to_string(),
+    };
+
+    if let Some(address) = report.fault_address {
+        summary.push_str(&format!(" at 0x{:08x}", address));
+    }
+    if report
+        .status
+        .as_ref()
+        .map(|status| status.hfsr & HFSR_FORCED != 0)
+        == Some(true)
+    {
+        summary.push_str(", escalated to HardFault");
+    }
+    if report.locked_up {
+        summary.push_str(", core locked up");
+    }
+
+    summary.push_str(&format!(", PC {}", report.pc));
+    summary
+}
+
+pub fn analyze(
+    core: &mut Core,
+    symbols: Option<&ElfResolver>,
+) -> Result<FaultReport, PlungerError> {
+    let locked_up = matches!(core.status()?, CoreStatus::LockedUp);
+    if !core.core_halted()? {
+        core.halt(HALT_TIMEOUT)?;
+    }
+
+    let xpsr = read_register(core, "xpsr")?;
+    let lr = read_register(core, "lr")?;
+    let pc = read_register(core, "pc")?;
+    let exception_number = xpsr & 0x1ff;
+
+    let status = read_status(core)?;
+    let flags = status.as_ref().map(decode_flags).unwrap_or_default();
+    let fault_address = status.as_ref().and_then(fault_address);
+
+    let frame = match exception_number {
+        0 => None,
+        _ => read_frame(core, lr, status.as_ref())?,
+    };
+    let (fault_pc, fault_lr) = match &frame {
+        Some(frame) => (frame.pc, frame.lr),
+        None => (pc, lr),
+    };
+
+    let mut report = FaultReport {
+        exception_number,
+        exception: exception_name(exception_number),
+        locked_up,
+        status,
+        flags,
+        fault_address,
+        frame,
+        pc: locate(fault_pc, symbols)?,
+        lr: locate(fault_lr, symbols)?,
+        summary: String::new(),
+    };
+    report.summary = summarise(&report);
+
+    Ok(report)
+}
+
+#[js_function(2)]
+pub fn analyze_fault(ctx: CallContext) -> napi::Result<JsObject> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let options: FaultOptions = match ctx.try_get::<JsObject>(1)? {
+        napi::Either::A(obj) => ctx.env.from_js_value(obj)?,
+        napi::Either::B(_) => FaultOptions::default(),
+    };
+
+    let task = SessionTask::new(id, move |session| {
+        let loaded = options
+            .elf_path
+            .as_deref()
+            .map(ElfResolver::load)
+            .transpose()?;
+        let symbols = loaded.as_ref().or(session.symbols.as_ref());
+
+        analyze(&mut session.session.core(0)?, symbols)
+    });
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(cfsr: u32, hfsr: u32) -> FaultStatus {
+        FaultStatus {
+            cfsr,
+            hfsr,
+            dfsr: 0,
+            mmfar: 0x2000_1000,
+            bfar: 0x4002_1000,
+            afsr: 0,
+        }
+    }
+
+    fn names(status: &FaultStatus) -> Vec<(&'static str, &'static str)> {
+        decode_flags(status)
+            .into_iter()
+            .map(|flag| (flag.register, flag.name))
+            .collect()
+    }
+
+    fn location(address: u32) -> CodeLocation {
+        CodeLocation {
+            address,
+            symbol: Some("main".to_string()),
+            offset: Some(0x2a),
+            source: None,
+        }
+    }
+
+    fn report(exception_number: u32, status: Option<FaultStatus>, locked_up: bool) -> FaultReport {
+        FaultReport {
+            exception_number,
+            exception: exception_name(exception_number),
+            locked_up,
+            flags: status.as_ref().map(decode_flags).unwrap_or_default(),
+            fault_address: status.as_ref().and_then(fault_address),
+            status,
+            frame: None,
+            pc: location(0x0800_0412),
+            lr: location(0x0800_0401),
+            summary: String::new(),
+        }
+    }
+
+    #[test]
+    fn names_exceptions() {
+        let cases = [
+            (0, None),
+            (1, Some("Reserved")),
+            (2, Some("NMI")),
+            (3, Some("HardFault")),
+            (6, Some("UsageFault")),
+            (8, Some("Reserved")),
+            (15, Some("SysTick")),
+            (16, Some("IRQ0")),
+            (255, Some("IRQ239")),
+        ];
+        for (number, name) in cases {
+            assert_eq!(exception_name(number).as_deref(), name, "{}", number);
+        }
+    }
+
+    type FlagCase = (u32, u32, &'static [(&'static str, &'static str)]);
+
+    #[test]
+    fn decodes_cfsr_and_hfsr_bits() {
+        let cases: [FlagCase; 6] = [
+            (0, 0, &[]),
+            (1 << 1, 0, &[("CFSR", "DACCVIOL")]),
+            (1 << 9 | CFSR_BFARVALID, 0, &[("CFSR", "PRECISERR")]),
+            (1 << 25, 0, &[("CFSR", "DIVBYZERO")]),
+            // Listed in reporting order, not bit order
+            (
+                1 << 16 | 1 << 10 | CFSR_STKERR,
+                0,
+                &[
+                    ("CFSR", "IMPRECISERR"),
+                    ("CFSR", "STKERR"),
+                    ("CFSR", "UNDEFINSTR"),
+                ],
+            ),
+            (
+                1 << 17,
+                HFSR_FORCED | 1 << 1,
+                &[
+                    ("CFSR", "INVSTATE"),
+                    ("HFSR", "VECTTBL"),
+                    ("HFSR", "FORCED"),
+                ],
+            ),
+        ];
+        for (cfsr, hfsr, expected) in cases {
+            assert_eq!(
+                names(&status(cfsr, hfsr)),
+                expected.to_vec(),
+                "{:08x}",
+                cfsr
+            );
+        }
+    }
+
+    #[test]
+    fn takes_the_fault_address_only_when_valid() {
+        assert_eq!(fault_address(&status(1 << 9, 0)), None);
+        assert_eq!(
+            fault_address(&status(1 << 9 | CFSR_BFARVALID, 0)),
+            Some(0x4002_1000)
+        );
+        assert_eq!(
+            fault_address(&status(1 << 1 | CFSR_MMARVALID, 0)),
+            Some(0x2000_1000)
+        );
+        assert_eq!(
+            fault_address(&status(CFSR_MMARVALID | CFSR_BFARVALID, 0)),
+            Some(0x2000_1000)
+        );
+    }
+
+    #[test]
+    fn picks_the_stack_and_frame_size_from_exc_return() {
+        let cases = [
+            // Handler mode, thread mode on MSP, thread mode on PSP
+            (0xffff_fff1, "msp", STANDARD_FRAME_SIZE),
+            (0xffff_fff9, "msp", STANDARD_FRAME_SIZE),
+            (0xffff_fffd, "psp", STANDARD_FRAME_SIZE),
+            // Same with an FP context stacked
+            (0xffff_ffe1, "msp", EXTENDED_FRAME_SIZE),
+            (0xffff_ffe9, "msp", EXTENDED_FRAME_SIZE),
+            (0xffff_ffed, "psp", EXTENDED_FRAME_SIZE),
+        ];
+        for (exc_return, stack, size) in cases {
+            assert_eq!(frame_stack(exc_return), stack, "{:08x}", exc_return);
+            assert_eq!(
+                frame_size(exc_return, 0x0100_0000),
+                size,
+                "{:08x}",
+                exc_return
+            );
+            assert_eq!(
+                frame_size(exc_return, 0x0100_0000 | XPSR_STACK_ALIGN),
+                size + 4,
+                "{:08x}",
+                exc_return
+            );
+        }
+    }
+
+    #[test]
+    fn summarises_the_first_cause() {
+        let cases = [
+            (
+                report(3, Some(status(1 << 9 | CFSR_BFARVALID, HFSR_FORCED)), false),
+                "precise data bus error at 0x40021000, escalated to HardFault, PC 0x08000412 (main+0x2a)",
+            ),
+            (
+                report(3, Some(status(0, 1 << 1)), true),
+                "bus fault reading the vector table, core locked up, PC 0x08000412 (main+0x2a)",
+            ),
+            (
+                report(3, None, false),
+                "HardFault without fault status, PC 0x08000412 (main+0x2a)",
+            ),
+            (
+                report(0, Some(status(0, 0)), false),
+                "no active exception, PC 0x08000412 (main+0x2a)",
+            ),
+        ];
+        for (report, summary) in cases {
+            assert_eq!(summarise(&report), summary);
+        }
+    }
+}
@@ -0,0 +1,1165 @@
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use napi::{CallContext, JsNumber, JsObject, JsUnknown, Task};
+use probe_rs::{
+    architecture::arm::{ap::AccessPortError, DapError},
+    config::{MemoryRegion, Target, TargetDescriptionSource},
+    flashing::{DownloadOptions, FlashLoader},
+    CoreStatus, MemoryInterface,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::common::plunger_error::PlungerError;
+
+use super::{
+    breakpoints::{HaltInfo, WatchAccess},
+    debug_session::{with_session, SessionTask},
+    registers::{CoreRegister, CORE_REGISTERS},
+};
+
+const DEFAULT_GDB_HOST: &str = "127.0.0.1";
+const DEFAULT_GDB_PORT: u16 = 3333;
+// How often the server checks whether it has been stopped, and a running core has halted
+const GDB_POLL_INTERVAL: Duration = Duration::from_millis(50);
+const HALT_TIMEOUT: Duration = Duration::from_millis(500);
+// Largest packet we accept, advertised to GDB in qSupported
+const PACKET_SIZE: usize = 0x4000;
+// A packet plus its '$' and "#xx" framing, anything longer drops the connection
+const MAX_FRAME_SIZE: usize = PACKET_SIZE + 4;
+const FALLBACK_FLASH_BLOCK_SIZE: u32 = 0x400;
+const RSP_INTERRUPT: u8 = 0x03;
+const RSP_ESCAPE: u8 = b'}';
+
+// Cortex-M peripheral and system regions, GDB refuses to touch memory outside the map
+const PERIPHERAL_REGIONS: [(u32, u32); 2] =
+    [(0x4000_0000, 0x2000_0000), (0xe000_0000, 0x2000_0000)];
+
+// CORE_REGISTERS from msp onwards go in the m-system feature
+const FIRST_SYSTEM_REGISTER: usize = 17;
+
+fn register_type(name: &str) -> &'static str {
+    match name {
+        "sp" => "data_ptr",
+        "pc" => "code_ptr",
+        _ => "int",
+    }
+}
+
+fn encode_register(reg: &CoreRegister, value: u32) -> String {
+    hex_encode(&value.to_le_bytes()[..(reg.bits / 8) as usize])
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GdbServerOptions {
+    port: Option<u16>,
+    /// Defaults to loopback, only widen it on a trusted network
+    host: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GdbServerInfo {
+    address: String,
+    port: u16,
+}
+
+/// A listening GDB server, dropping it stops serving and waits until the port is released.
+/// The serve thread may be waiting on a session command, so `stop_gdb_server` takes the
+/// server off the session thread before dropping it.
+pub struct GdbServer {
+    stop: Arc<AtomicBool>,
+    info: GdbServerInfo,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for GdbServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// What GDB fetches with qXfer, built once from the target description
+struct TargetDocuments {
+    target_xml: String,
+    memory_map_xml: String,
+    memory_map: Vec<MemoryRegion>,
+}
+
+fn target_xml() -> String {
+    let features = [
+        ("org.gnu.gdb.arm.m-profile", false),
+        ("org.gnu.gdb.arm.m-system", true),
+    ];
+
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n<!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n<target version=\"1.0\">\n<architecture>arm</architecture>\n",
+    );
+    for (feature, system) in features.iter() {
+        xml.push_str(&format!("<feature name=\"{}\">\n", feature));
+        for (regnum, (name, reg)) in CORE_REGISTERS.iter().enumerate() {
+            if (regnum >= FIRST_SYSTEM_REGISTER) == *system {
+                xml.push_str(&format!(
+                    "<reg name=\"{}\" bitsize=\"{}\" regnum=\"{}\" type=\"{}\"/>\n",
+                    name,
+                    reg.bits,
+                    regnum,
+                    register_type(name)
+                ));
+            }
+        }
+        xml.push_str("</feature>\n");
+    }
+    xml.push_str("</target>\n");
+    xml
+}
+
+fn flash_block_size(target: &Target, start: u32) -> u32 {
+    target
+        .flash_algorithms
+        .iter()
+        .map(|algorithm| &algorithm.flash_properties)
+        .find(|properties| properties.address_range.contains(&start))
+        .and_then(|properties| properties.sectors.first())
+        .map(|sector| sector.size)
+        .unwrap_or(FALLBACK_FLASH_BLOCK_SIZE)
+}
+
+fn memory_map_xml(target: &Target) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n<!DOCTYPE memory-map PUBLIC \"+//IDN gnu.org//DTD GDB Memory Map V1.0//EN\" \"http://sourceware.org/gdb/gdb-memory-map.dtd\">\n<memory-map>\n",
+    );
+
+    let mut ranges = Vec::new();
+    for region in target.memory_map.iter() {
+        match region {
+            MemoryRegion::Nvm(nvm) => {
+                xml.push_str(&format!(
+                    "<memory type=\"flash\" start=\"0x{:08x}\" length=\"0x{:x}\"><property name=\"blocksize\">0x{:x}</property></memory>\n",
+                    nvm.range.start,
+                    nvm.range.end - nvm.range.start,
+                    flash_block_size(target, nvm.range.start)
+                ));
+                ranges.push(nvm.range.clone());
+            }
+            MemoryRegion::Ram(ram) => {
+                xml.push_str(&format!(
+                    "<memory type=\"ram\" start=\"0x{:08x}\" length=\"0x{:x}\"/>\n",
+                    ram.range.start,
+                    ram.range.end - ram.range.start
+                ));
+                ranges.push(ram.range.clone());
+            }
+            MemoryRegion::Generic(generic) => {
+                xml.push_str(&format!(
+                    "<memory type=\"ram\" start=\"0x{:08x}\" length=\"0x{:x}\"/>\n",
+                    generic.range.start,
+                    generic.range.end - generic.range.start
+                ));
+                ranges.push(generic.range.clone());
+            }
+        }
+    }
+
+    for (start, length) in PERIPHERAL_REGIONS.iter() {
+        let end = start.saturating_add(*length);
+        if ranges
+            .iter()
+            .all(|range| range.end <= *start || range.start >= end)
+        {
+            xml.push_str(&format!(
+                "<memory type=\"ram\" start=\"0x{:08x}\" length=\"0x{:x}\"/>\n",
+                start, length
+            ));
+        }
+    }
+
+    xml.push_str("</memory-map>\n");
+    xml
+}
+
+fn hex_encode(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hex_decode(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 == 1 {
+        return None;
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(text.get(idx..idx + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_hex(text: &str) -> Option<u32> {
+    u32::from_str_radix(text, 16).ok()
+}
+
+// "addr,length" as sent by m, M, X, Z and qXfer
+fn parse_range(text: &str) -> Option<(u32, u32)> {
+    let (addr, len) = text.split_once(',')?;
+    Some((parse_hex(addr)?, parse_hex(len)?))
+}
+
+// Splits a binary packet such as X or vFlashWrite at the first ':'
+fn split_binary(packet: &[u8]) -> Option<(&str, &[u8])> {
+    let colon = packet.iter().position(|byte| *byte == b':')?;
+    let header = std::str::from_utf8(&packet[..colon]).ok()?;
+    Some((header, &packet[colon + 1..]))
+}
+
+fn xfer(document: &str, args: &str) -> Vec<u8> {
+    let (offset, length) = match parse_range(args) {
+        Some(range) => range,
+        None => return b"E01".to_vec(),
+    };
+
+    let bytes = document.as_bytes();
+    let start = (offset as usize).min(bytes.len());
+    let end = start.saturating_add(length as usize).min(bytes.len());
+    let prefix = if end < bytes.len() { b'm' } else { b'l' };
+
+    let mut reply = vec![prefix];
+    reply.extend_from_slice(&bytes[start..end]);
+    reply
+}
+
+// Bus faults and rejected accesses from the memory AP, as opposed to probe or session trouble
+fn is_memory_fault(err: &PlungerError) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(err);
+    while let Some(err) = source {
+        if matches!(
+            err.downcast_ref::<AccessPortError>(),
+            Some(AccessPortError::MemoryNotAligned { .. })
+                | Some(AccessPortError::OutOfBoundsError)
+        ) || matches!(
+            err.downcast_ref::<DapError>(),
+            Some(DapError::FaultResponse)
+        ) {
+            return true;
+        }
+        source = err.source();
+    }
+
+    false
+}
+
+// EFAULT for memory faults, so GDB can tell an unreadable address from a failing probe
+fn error_reply(err: PlungerError) -> Vec<u8> {
+    if is_memory_fault(&err) {
+        b"E0E".to_vec()
+    } else {
+        b"E01".to_vec()
+    }
+}
+
+fn ok_reply(result: Result<(), PlungerError>) -> Vec<u8> {
+    match result {
+        Ok(_) => b"OK".to_vec(),
+        Err(err) => error_reply(err),
+    }
+}
+
+fn closed() -> io::Error {
+    io::Error::new(ErrorKind::ConnectionAborted, "GDB server stopped")
+}
+
+enum Incoming {
+    Packet(Vec<u8>),
+    Interrupt,
+}
+
+#[derive(Debug, PartialEq)]
+enum Frame {
+    Interrupt,
+    // GDB asks for the last reply again
+    Nack,
+    Packet { packet: Vec<u8>, valid: bool },
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+// Escapes and frames a reply as "$body#xx"
+fn encode_packet(reply: &[u8]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(reply.len());
+    for byte in reply {
+        match byte {
+            b'#' | b'$' | b'}' | b'*' => body.extend_from_slice(&[RSP_ESCAPE, byte ^ 0x20]),
+            _ => body.push(*byte),
+        }
+    }
+
+    let mut frame = Vec::with_capacity(body.len() + 4);
+    frame.push(b'$');
+    frame.extend_from_slice(&body);
+    frame.extend_from_slice(format!("#{:02x}", checksum(&body)).as_bytes());
+    frame
+}
+
+// Takes one frame off the front of the input, None until a whole packet has arrived.
+// Acks and line noise before it are dropped in one go.
+fn take_frame(input: &mut Vec<u8>) -> Option<Frame> {
+    let start = input
+        .iter()
+        .position(|byte| matches!(*byte, RSP_INTERRUPT | b'-' | b'$'));
+    input.drain(..start.unwrap_or(input.len()));
+
+    match *input.first()? {
+        RSP_INTERRUPT => {
+            input.drain(..1);
+            Some(Frame::Interrupt)
+        }
+        b'-' => {
+            input.drain(..1);
+            Some(Frame::Nack)
+        }
+        _ => {
+            let hash = match input.iter().position(|byte| *byte == b'#') {
+                Some(hash) if input.len() >= hash + 3 => hash,
+                _ => return None,
+            };
+
+            let frame: Vec<u8> = input.drain(..hash + 3).collect();
+            let body = &frame[1..hash];
+            let expected = std::str::from_utf8(&frame[hash + 1..])
+                .ok()
+                .and_then(|text| u8::from_str_radix(text, 16).ok());
+
+            let mut packet = Vec::with_capacity(body.len());
+            let mut bytes = body.iter();
+            while let Some(byte) = bytes.next() {
+                match byte {
+                    &RSP_ESCAPE => packet.extend(bytes.next().map(|next| next ^ 0x20)),
+                    _ => packet.push(*byte),
+                }
+            }
+
+            Some(Frame::Packet {
+                packet,
+                valid: expected == Some(checksum(body)),
+            })
+        }
+    }
+}
+
+struct GdbClient<'a> {
+    id: u32,
+    stream: TcpStream,
+    stop: &'a AtomicBool,
+    documents: &'a TargetDocuments,
+    input: Vec<u8>,
+    last_reply: Vec<u8>,
+    no_ack: bool,
+    breakpoints: Vec<u32>,
+    watchpoints: Vec<u32>,
+    flash: Option<FlashLoader>,
+}
+
+impl<'a> GdbClient<'a> {
+    fn send(&mut self, reply: &[u8]) -> io::Result<()> {
+        let frame = encode_packet(reply);
+        self.stream.write_all(&frame)?;
+        self.last_reply = frame;
+        Ok(())
+    }
+
+    // Takes one complete packet (or interrupt) off the input buffer
+    fn parse(&mut self) -> io::Result<Option<Incoming>> {
+        while let Some(frame) = take_frame(&mut self.input) {
+            match frame {
+                Frame::Interrupt => return Ok(Some(Incoming::Interrupt)),
+                Frame::Nack => {
+                    let frame = self.last_reply.clone();
+                    self.stream.write_all(&frame)?;
+                }
+                Frame::Packet { packet, valid } => {
+                    if !self.no_ack {
+                        if !valid {
+                            self.stream.write_all(b"-")?;
+                            continue;
+                        }
+                        self.stream.write_all(b"+")?;
+                    }
+                    return Ok(Some(Incoming::Packet(packet)));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Returns None when nothing arrived within the poll interval
+    fn receive(&mut self) -> io::Result<Option<Incoming>> {
+        if let Some(incoming) = self.parse()? {
+            return Ok(Some(incoming));
+        }
+        if self.stop.load(Ordering::SeqCst) {
+            return Err(closed());
+        }
+
+        let mut buffer = [0u8; 1024];
+        match self.stream.read(&mut buffer) {
+            Ok(0) => Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "GDB closed the connection",
+            )),
+            Ok(count) => {
+                self.input.extend_from_slice(&buffer[..count]);
+                let incoming = self.parse()?;
+                if incoming.is_none() && self.input.len() > MAX_FRAME_SIZE {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        "GDB packet exceeds PacketSize",
+                    ));
+                }
+                Ok(incoming)
+            }
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        loop {
+            let packet = match self.receive()? {
+                Some(Incoming::Packet(packet)) => packet,
+                // Only meaningful while the core runs
+                Some(Incoming::Interrupt) | None => continue,
+            };
+
+            match self.handle(&packet)? {
+                Some(reply) => self.send(&reply)?,
+                None => return Ok(()),
+            }
+
+            if packet == b"QStartNoAckMode" {
+                self.no_ack = true;
+            }
+        }
+    }
+
+    // Replies to one packet, None ends the connection
+    fn handle(&mut self, packet: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        let text = String::from_utf8_lossy(packet).to_string();
+        let command = text.as_str();
+
+        let reply = match command {
+            "?" => self.stop_reply(false),
+            "qAttached" => b"1".to_vec(),
+            "qC" => b"QC1".to_vec(),
+            "qfThreadInfo" => b"m1".to_vec(),
+            "qsThreadInfo" => b"l".to_vec(),
+            "QStartNoAckMode" => b"OK".to_vec(),
+            "g" => self.read_registers(),
+            "c" => self.resume(None)?,
+            "s" => self.step(None),
+            "vFlashDone" => self.flash_done(),
+            "k" => return Ok(None),
+            "D" => {
+                let reply = ok_reply(self.session(|core| Ok(core.run()?)));
+                self.send(&reply)?;
+                return Ok(None);
+            }
+            _ if command.starts_with("qSupported") => format!(
+                "PacketSize={:x};qXfer:features:read+;qXfer:memory-map:read+;QStartNoAckMode+;hwbreak+",
+                PACKET_SIZE
+            )
+            .into_bytes(),
+            _ if command.starts_with("qXfer:features:read:target.xml:") => xfer(
+                &self.documents.target_xml,
+                &command["qXfer:features:read:target.xml:".len()..],
+            ),
+            _ if command.starts_with("qXfer:memory-map:read::") => xfer(
+                &self.documents.memory_map_xml,
+                &command["qXfer:memory-map:read::".len()..],
+            ),
+            _ if command.starts_with("qRcmd,") => self.monitor(&command["qRcmd,".len()..]),
+            _ if command.starts_with('H') || command.starts_with('T') => b"OK".to_vec(),
+            _ if command.starts_with('G') => self.write_registers(&command[1..]),
+            _ if command.starts_with('p') => self.read_register(&command[1..]),
+            _ if command.starts_with('P') => self.write_register(&command[1..]),
+            _ if command.starts_with('m') => self.read_memory(&command[1..]),
+            _ if command.starts_with('M') => match command[1..].split_once(':') {
+                Some((range, data)) => match hex_decode(data) {
+                    Some(data) => self.write_memory(range, data),
+                    None => b"E01".to_vec(),
+                },
+                None => b"E01".to_vec(),
+            },
+            _ if command.starts_with('X') => match split_binary(&packet[1..]) {
+                Some((range, data)) => self.write_memory(range, data.to_vec()),
+                None => b"E01".to_vec(),
+            },
+            _ if command.starts_with('c') => self.resume(parse_hex(&command[1..]))?,
+            _ if command.starts_with('s') => self.step(parse_hex(&command[1..])),
+            _ if command.starts_with("Z0,") || command.starts_with("Z1,") => {
+                self.set_breakpoint(&command[3..])
+            }
+            _ if command.starts_with("z0,") || command.starts_with("z1,") => {
+                self.clear_breakpoint(&command[3..])
+            }
+            _ if command.starts_with("Z2,") => self.set_watchpoint(&command[3..], WatchAccess::Write),
+            _ if command.starts_with("Z3,") => self.set_watchpoint(&command[3..], WatchAccess::Read),
+            _ if command.starts_with("Z4,") => {
+                self.set_watchpoint(&command[3..], WatchAccess::ReadWrite)
+            }
+            _ if command.starts_with("z2,")
+                || command.starts_with("z3,")
+                || command.starts_with("z4,") =>
+            {
+                self.clear_watchpoint(&command[3..])
+            }
+            _ if command.starts_with("vFlashErase:") => {
+                self.flash_loader();
+                b"OK".to_vec()
+            }
+            _ if command.starts_with("vFlashWrite:") => {
+                match split_binary(&packet["vFlashWrite:".len()..]) {
+                    Some((addr, data)) => self.flash_write(addr, data),
+                    None => b"E01".to_vec(),
+                }
+            }
+            // Anything else, including vCont, is reported as unsupported
+            _ => Vec::new(),
+        };
+
+        Ok(Some(reply))
+    }
+
+    fn session<T, F>(&self, command: F) -> Result<T, PlungerError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut probe_rs::Core) -> Result<T, PlungerError> + Send + 'static,
+    {
+        with_session(self.id, move |session| {
+            let mut core = session.session.core(0)?;
+            command(&mut core)
+        })
+    }
+
+    fn stop_reply(&self, interrupted: bool) -> Vec<u8> {
+        if interrupted {
+            return b"S02".to_vec();
+        }
+
+        let halted = with_session(self.id, |session| {
+            let mut core = session.session.core(0)?;
+            session.halt_points.halt_info(&mut core)
+        });
+
+        match halted {
+            Ok(Some(HaltInfo {
+                watchpoint: Some(watch),
+                ..
+            })) => {
+                let kind = match watch.access {
+                    WatchAccess::Write => "watch",
+                    WatchAccess::Read => "rwatch",
+                    WatchAccess::ReadWrite => "awatch",
+                };
+                format!("T05{}:{:08x};", kind, watch.address).into_bytes()
+            }
+            Ok(Some(HaltInfo {
+                reason: "breakpoint",
+                ..
+            })) => b"T05hwbreak:;".to_vec(),
+            _ => b"S05".to_vec(),
+        }
+    }
+
+    fn read_registers(&self) -> Vec<u8> {
+        let result = self.session(|core| {
+            CORE_REGISTERS
+                .iter()
+                .map(|(_, reg)| Ok(encode_register(reg, reg.read(core)?)))
+                .collect::<Result<String, PlungerError>>()
+        });
+
+        match result {
+            Ok(hex) => hex.into_bytes(),
+            Err(err) => error_reply(err),
+        }
+    }
+
+    fn write_registers(&self, args: &str) -> Vec<u8> {
+        let data = match hex_decode(args) {
+            Some(data) => data,
+            None => return b"E01".to_vec(),
+        };
+
+        let mut values = Vec::with_capacity(CORE_REGISTERS.len());
+        let mut offset = 0;
+        for (regnum, (_, reg)) in CORE_REGISTERS.iter().enumerate() {
+            let size = (reg.bits / 8) as usize;
+            let bytes = match data.get(offset..offset + size) {
+                Some(bytes) => bytes,
+                None => break,
+            };
+            let value = bytes
+                .iter()
+                .rev()
+                .fold(0u32, |acc, byte| (acc << 8) | *byte as u32);
+            values.push((regnum, value));
+            offset += size;
+        }
+
+        ok_reply(self.session(move |core| {
+            for (regnum, value) in values {
+                CORE_REGISTERS[regnum].1.write(core, value)?;
+            }
+            Ok(())
+        }))
+    }
+
+    fn read_register(&self, args: &str) -> Vec<u8> {
+        let regnum = match parse_hex(args) {
+            Some(regnum) if (regnum as usize) < CORE_REGISTERS.len() => regnum as usize,
+            _ => return b"E01".to_vec(),
+        };
+
+        let reg = CORE_REGISTERS[regnum].1;
+        match self.session(move |core| reg.read(core)) {
+            Ok(value) => encode_register(&reg, value).into_bytes(),
+            Err(err) => error_reply(err),
+        }
+    }
+
+    fn write_register(&self, args: &str) -> Vec<u8> {
+        let parsed = args.split_once('=').and_then(|(regnum, value)| {
+            let regnum = parse_hex(regnum)? as usize;
+            let bytes = hex_decode(value)?;
+            let value = bytes
+                .iter()
+                .rev()
+                .fold(0u32, |acc, byte| (acc << 8) | *byte as u32);
+            Some((regnum, value))
+        });
+
+        match parsed {
+            Some((regnum, value)) if regnum < CORE_REGISTERS.len() => {
+                let reg = CORE_REGISTERS[regnum].1;
+                ok_reply(self.session(move |core| reg.write(core, value)))
+            }
+            _ => b"E01".to_vec(),
+        }
+    }
+
+    fn read_memory(&self, args: &str) -> Vec<u8> {
+        let (addr, len) = match parse_range(args) {
+            Some(range) => range,
+            None => return b"E01".to_vec(),
+        };
+
+        let len = (len as usize).min(PACKET_SIZE / 2);
+        let result = self.session(move |core| {
+            let mut data = vec![0u8; len];
+            core.read_8(addr, &mut data)?;
+            Ok(data)
+        });
+
+        match result {
+            Ok(data) => hex_encode(&data).into_bytes(),
+            Err(err) => error_reply(err),
+        }
+    }
+
+    fn write_memory(&self, range: &str, data: Vec<u8>) -> Vec<u8> {
+        match parse_range(range) {
+            Some((addr, len)) if len as usize == data.len() => {
+                ok_reply(self.session(move |core| Ok(core.write_8(addr, &data)?)))
+            }
+            _ => b"E01".to_vec(),
+        }
+    }
+
+    fn step(&self, addr: Option<u32>) -> Vec<u8> {
+        let result = self.session(move |core| {
+            if let Some(addr) = addr {
+                core.write_core_reg(core.registers().program_counter().into(), addr)?;
+            }
+            core.step()?;
+            Ok(())
+        });
+
+        match result {
+            Ok(_) => b"S05".to_vec(),
+            Err(err) => error_reply(err),
+        }
+    }
+
+    // Lets the core run until it halts on its own or GDB sends an interrupt
+    fn resume(&mut self, addr: Option<u32>) -> io::Result<Vec<u8>> {
+        let started = self.session(move |core| {
+            if let Some(addr) = addr {
+                core.write_core_reg(core.registers().program_counter().into(), addr)?;
+            }
+            Ok(core.run()?)
+        });
+        if let Err(err) = started {
+            return Ok(error_reply(err));
+        }
+
+        loop {
+            if let Some(Incoming::Interrupt) = self.receive()? {
+                return Ok(match self.session(|core| Ok(core.halt(HALT_TIMEOUT)?)) {
+                    Ok(_) => self.stop_reply(true),
+                    Err(err) => error_reply(err),
+                });
+            }
+
+            match self.session(|core| Ok(core.status()?)) {
+                Ok(CoreStatus::Halted(_)) | Ok(CoreStatus::LockedUp) => {
+                    return Ok(self.stop_reply(false))
+                }
+                Ok(_) => (),
+                Err(err) => return Ok(error_reply(err)),
+            }
+        }
+    }
+
+    // GDB's software breakpoints would be written to flash, use the FPB for both kinds
+    fn set_breakpoint(&mut self, args: &str) -> Vec<u8> {
+        let addr = match parse_range(args) {
+            Some((addr, _)) => addr,
+            None => return b"E01".to_vec(),
+        };
+
+        // Shares the session's FPB bookkeeping, like the watchpoints below
+        let result = with_session(self.id, move |session| {
+            let mut core = session.session.core(0)?;
+            session.halt_points.set_breakpoint(&mut core, addr)
+        });
+        if result.is_ok() && !self.breakpoints.contains(&addr) {
+            self.breakpoints.push(addr);
+        }
+        ok_reply(result)
+    }
+
+    fn clear_breakpoint(&mut self, args: &str) -> Vec<u8> {
+        let addr = match parse_range(args) {
+            Some((addr, _)) => addr,
+            None => return b"E01".to_vec(),
+        };
+
+        self.breakpoints.retain(|existing| *existing != addr);
+        ok_reply(clear_breakpoints(self.id, vec![addr]))
+    }
+
+    // Shares the session's DWT bookkeeping so comparators are not handed out twice
+    fn set_watchpoint(&mut self, args: &str, access: WatchAccess) -> Vec<u8> {
+        let (addr, size) = match parse_range(args) {
+            Some(range) => range,
+            None => return b"E01".to_vec(),
+        };
+
+        let result = with_session(self.id, move |session| {
+            let mut core = session.session.core(0)?;
+            session
+                .halt_points
+                .set_watchpoint(&mut core, addr, size, access)
+        });
+        if result.is_ok() && !self.watchpoints.contains(&addr) {
+            self.watchpoints.push(addr);
+        }
+        ok_reply(result.map(|_| ()))
+    }
+
+    fn clear_watchpoint(&mut self, args: &str) -> Vec<u8> {
+        let addr = match parse_range(args) {
+            Some((addr, _)) => addr,
+            None => return b"E01".to_vec(),
+        };
+
+        self.watchpoints.retain(|existing| *existing != addr);
+        ok_reply(clear_watchpoints(self.id, vec![addr]))
+    }
+
+    fn monitor(&self, args: &str) -> Vec<u8> {
+        let command = hex_decode(args)
+            .map(|bytes| String::from_utf8_lossy(&bytes).trim().to_string())
+            .unwrap_or_default();
+
+        let output = match command.as_str() {
+            "reset" | "reset halt" => self
+                .session(|core| Ok(core.reset_and_halt(HALT_TIMEOUT)?))
+                .map(|info| format!("Target reset, halted at 0x{:08x}\n", info.pc)),
+            "halt" => self
+                .session(|core| Ok(core.halt(HALT_TIMEOUT)?))
+                .map(|info| format!("Target halted at 0x{:08x}\n", info.pc)),
+            _ => Ok("Supported monitor commands: reset, halt\n".to_string()),
+        };
+
+        match output {
+            Ok(output) => hex_encode(output.as_bytes()).into_bytes(),
+            Err(err) => hex_encode(format!("{}\n", err).as_bytes()).into_bytes(),
+        }
+    }
+
+    fn flash_loader(&mut self) -> &mut FlashLoader {
+        let memory_map = &self.documents.memory_map;
+        self.flash.get_or_insert_with(|| {
+            FlashLoader::new(memory_map.clone(), TargetDescriptionSource::BuiltIn)
+        })
+    }
+
+    fn flash_write(&mut self, addr: &str, data: &[u8]) -> Vec<u8> {
+        let addr = match parse_hex(addr) {
+            Some(addr) => addr,
+            None => return b"E01".to_vec(),
+        };
+
+        match self.flash_loader().add_data(addr, data) {
+            Ok(_) => b"OK".to_vec(),
+            Err(err) => error_reply(err.into()),
+        }
+    }
+
+    // Erasing happens here too, probe-rs works out the sectors from the collected data
+    fn flash_done(&mut self) -> Vec<u8> {
+        let loader = match self.flash.take() {
+            Some(loader) => loader,
+            None => return b"OK".to_vec(),
+        };
+
+        ok_reply(with_session(self.id, move |session| {
+            loader.commit(&mut session.session, DownloadOptions::new())?;
+            Ok(())
+        }))
+    }
+
+    // Leaves the core as it is, only our breakpoints and watchpoints are removed
+    fn detach(&mut self) {
+        let _ = clear_breakpoints(self.id, std::mem::take(&mut self.breakpoints));
+        let _ = clear_watchpoints(self.id, std::mem::take(&mut self.watchpoints));
+        let _ = with_session(self.id, |session| session.lock.set_debugger_attached(false));
+    }
+}
+
+fn clear_breakpoints(id: u32, addresses: Vec<u32>) -> Result<(), PlungerError> {
+    with_session(id, move |session| {
+        let mut core = session.session.core(0)?;
+        for addr in addresses {
+            session
+                .halt_points
+                .clear_breakpoints(&mut core, Some(addr))?;
+        }
+        Ok(())
+    })
+}
+
+fn clear_watchpoints(id: u32, addresses: Vec<u32>) -> Result<(), PlungerError> {
+    with_session(id, move |session| {
+        let mut core = session.session.core(0)?;
+        for addr in addresses {
+            session
+                .halt_points
+                .clear_watchpoints(&mut core, Some(addr))?;
+        }
+        Ok(())
+    })
+}
+
+fn attach(id: u32) -> Result<(), PlungerError> {
+    with_session(id, |session| {
+        session.lock.set_debugger_attached(true)?;
+        session.session.core(0)?.halt(HALT_TIMEOUT)?;
+        Ok(())
+    })
+}
+
+// One client at a time, GDB has no use for more
+fn serve(id: u32, listener: TcpListener, stop: Arc<AtomicBool>, documents: TargetDocuments) {
+    while !stop.load(Ordering::SeqCst) {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(GDB_POLL_INTERVAL);
+                continue;
+            }
+            Err(_) => break,
+        };
+
+        let ready = stream
+            .set_nonblocking(false)
+            .and_then(|_| stream.set_read_timeout(Some(GDB_POLL_INTERVAL)))
+            .and_then(|_| stream.set_nodelay(true));
+        if ready.is_err() || attach(id).is_err() {
+            continue;
+        }
+
+        let mut client = GdbClient {
+            id,
+            stream,
+            stop: &stop,
+            documents: &documents,
+            input: Vec::new(),
+            last_reply: Vec::new(),
+            no_ack: false,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            flash: None,
+        };
+        let _ = client.run();
+        client.detach();
+    }
+}
+
+impl GdbServer {
+    pub fn start(
+        id: u32,
+        session: &mut probe_rs::Session,
+        options: &GdbServerOptions,
+    ) -> Result<GdbServer, PlungerError> {
+        let host = options.host.as_deref().unwrap_or(DEFAULT_GDB_HOST);
+        let port = options.port.unwrap_or(DEFAULT_GDB_PORT);
+        let listener = TcpListener::bind((host, port))
+            .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+            .map_err(|err| {
+                PlungerError::InvalidOption(format!("Cannot listen on {}:{}: {}", host, port, err))
+            })?;
+        let local = listener.local_addr().map_err(|err| {
+            PlungerError::StateError(format!("Cannot read GDB server address: {}", err))
+        })?;
+
+        let target = session.target();
+        let documents = TargetDocuments {
+            target_xml: target_xml(),
+            memory_map_xml: memory_map_xml(target),
+            memory_map: target.memory_map.clone(),
+        };
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let server_stop = stop.clone();
+        let thread = thread::spawn(move || serve(id, listener, server_stop, documents));
+
+        Ok(GdbServer {
+            stop,
+            thread: Some(thread),
+            info: GdbServerInfo {
+                address: local.ip().to_string(),
+                port: local.port(),
+            },
+        })
+    }
+
+    pub fn info(&self) -> GdbServerInfo {
+        self.info.clone()
+    }
+}
+
+#[js_function(2)]
+pub fn start_gdb_server(ctx: CallContext) -> napi::Result<JsObject> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let options: GdbServerOptions = match ctx.try_get::<JsObject>(1)? {
+        napi::Either::A(obj) => ctx.env.from_js_value(obj)?,
+        napi::Either::B(_) => GdbServerOptions::default(),
+    };
+
+    let task = SessionTask::new(id, move |session| {
+        if let Some(server) = &session.gdb {
+            return Err(PlungerError::StateError(format!(
+                "GDB server already listening on port {}",
+                server.info.port
+            )));
+        }
+
+        let server = GdbServer::start(id, &mut session.session, &options)?;
+        let info = server.info();
+        session.gdb = Some(server);
+        Ok(info)
+    });
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
+
+pub struct StopGdbServerTask {
+    id: u32,
+}
+
+impl Task for StopGdbServerTask {
+    type Output = bool;
+    type JsValue = JsUnknown;
+
+    // The server is dropped here rather than on the session thread, which the connected
+    // client needs to detach before the serve thread can be joined
+    fn compute(&mut self) -> napi::Result<Self::Output> {
+        let server = with_session(self.id, |session| Ok(session.gdb.take()))?;
+        Ok(server.is_some())
+    }
+
+    fn resolve(self, env: napi::Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+        env.to_js_value(&output)
+    }
+
+    fn reject(self, _env: napi::Env, err: napi::Error) -> napi::Result<Self::JsValue> {
+        Err(err)
+    }
+}
+
+#[js_function(1)]
+pub fn stop_gdb_server(ctx: CallContext) -> napi::Result<JsObject> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let task = StopGdbServerTask { id };
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
+
+#[cfg(test)]
+mod tests {
+    use probe_rs::DebugProbeError;
+
+    use super::*;
+
+    #[test]
+    fn frames_replies_with_a_checksum() {
+        assert_eq!(encode_packet(b"OK"), b"$OK#9a".to_vec());
+        assert_eq!(encode_packet(b""), b"$#00".to_vec());
+        assert_eq!(encode_packet(b"a}#$*"), b"$a}]}\x03}\x04}\x0a#c3".to_vec());
+    }
+
+    #[test]
+    fn unescapes_what_it_escapes() {
+        let data = b"X0,5:}#$*\x00".to_vec();
+        let mut input = encode_packet(&data);
+
+        assert_eq!(
+            take_frame(&mut input),
+            Some(Frame::Packet {
+                packet: data,
+                valid: true
+            })
+        );
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn skips_acks_and_noise_before_a_packet() {
+        let mut input = b"++junk\r\n$g#67$m0".to_vec();
+
+        assert_eq!(
+            take_frame(&mut input),
+            Some(Frame::Packet {
+                packet: b"g".to_vec(),
+                valid: true
+            })
+        );
+        // The second packet has not fully arrived yet
+        assert_eq!(take_frame(&mut input), None);
+        assert_eq!(input, b"$m0".to_vec());
+
+        input.extend_from_slice(b",4#fd");
+        assert_eq!(
+            take_frame(&mut input),
+            Some(Frame::Packet {
+                packet: b"m0,4".to_vec(),
+                valid: true
+            })
+        );
+
+        let mut input = b"noise only".to_vec();
+        assert_eq!(take_frame(&mut input), None);
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn reports_bad_checksums_interrupts_and_nacks() {
+        let mut input = b"$g#00\x03-$g#6".to_vec();
+
+        assert_eq!(
+            take_frame(&mut input),
+            Some(Frame::Packet {
+                packet: b"g".to_vec(),
+                valid: false
+            })
+        );
+        assert_eq!(take_frame(&mut input), Some(Frame::Interrupt));
+        assert_eq!(take_frame(&mut input), Some(Frame::Nack));
+        assert_eq!(take_frame(&mut input), None);
+    }
+
+    #[test]
+    fn decodes_hex() {
+        assert_eq!(hex_decode("0a0B"), Some(vec![0x0a, 0x0b]));
+        assert_eq!(hex_decode(""), Some(vec![]));
+        assert_eq!(hex_decode("abc"), None);
+        assert_eq!(hex_decode("zz"), None);
+        assert_eq!(hex_decode("\u{e9}"), None);
+        assert_eq!(hex_encode(&[0x00, 0xff]), "00ff");
+    }
+
+    #[test]
+    fn parses_address_ranges() {
+        assert_eq!(parse_range("8000000,4"), Some((0x0800_0000, 4)));
+        assert_eq!(parse_range("ffffffff,0"), Some((0xffff_ffff, 0)));
+        assert_eq!(parse_range("8000000"), None);
+        assert_eq!(parse_range("x,4"), None);
+        assert_eq!(parse_range("100000000,1"), None);
+    }
+
+    #[test]
+    fn slices_qxfer_documents() {
+        assert_eq!(xfer("abcdef", "0,4"), b"mabcd".to_vec());
+        assert_eq!(xfer("abcdef", "4,4"), b"lef".to_vec());
+        assert_eq!(xfer("abcdef", "0,6"), b"labcdef".to_vec());
+        assert_eq!(xfer("abcdef", "10,4"), b"l".to_vec());
+        assert_eq!(xfer("abcdef", "ffffffff,ffffffff"), b"l".to_vec());
+        assert_eq!(xfer("abcdef", "0"), b"E01".to_vec());
+    }
+
+    #[test]
+    fn maps_flash_ram_and_free_peripheral_regions() {
+        let target = probe_rs::config::get_target_by_name("nRF52840_xxAA").unwrap();
+        let xml = memory_map_xml(&target);
+
+        assert!(xml.contains(
+            "<memory type=\"flash\" start=\"0x00000000\" length=\"0x100000\"><property name=\"blocksize\">0x1000</property></memory>"
+        ));
+        assert!(xml.contains("<memory type=\"ram\" start=\"0x20000000\" length=\"0x40000\"/>"));
+        assert!(xml.contains("<memory type=\"ram\" start=\"0x40000000\" length=\"0x20000000\"/>"));
+        assert!(xml.contains("<memory type=\"ram\" start=\"0xe0000000\" length=\"0x20000000\"/>"));
+        assert!(xml.ends_with("</memory-map>\n"));
+    }
+
+    #[test]
+    fn tells_memory_faults_from_other_errors() {
+        let fault = PlungerError::ProbeRsSessionError(probe_rs::Error::architecture_specific(
+            AccessPortError::RegisterReadError {
+                address: 0x0c,
+                name: "DRW",
+                source: Box::new(DapError::FaultResponse),
+            },
+        ));
+        assert_eq!(error_reply(fault), b"E0E".to_vec());
+
+        let unaligned = PlungerError::ProbeRsCommError(DebugProbeError::ArchitectureSpecific(
+            Box::new(AccessPortError::MemoryNotAligned {
+                address: 0x2000_0001,
+                alignment: 4,
+            }),
+        ));
+        assert_eq!(error_reply(unaligned), b"E0E".to_vec());
+
+        let usb = PlungerError::ProbeRsCommError(DebugProbeError::Usb(None));
+        assert_eq!(error_reply(usb), b"E01".to_vec());
+        assert_eq!(
+            error_reply(PlungerError::SessionNotFound(1)),
+            b"E01".to_vec()
+        );
+    }
+}
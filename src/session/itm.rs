@@ -0,0 +1,246 @@
+use serde::Serialize;
+
+const ITM_OVERFLOW: u8 = 0x70;
+const ITM_GTS1: u8 = 0x94;
+const ITM_GTS2: u8 = 0xb4;
+const ITM_CONTINUATION: u8 = 0x80;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum ItmPacket {
+    /// Written by software to a stimulus port
+    #[serde(rename_all = "camelCase")]
+    Instrumentation { port: u8, value: u32, size: u8 },
+    /// Generated by the DWT, e.g. PC samples, event counters and data trace
+    #[serde(rename_all = "camelCase")]
+    Hardware {
+        discriminator: u8,
+        value: u32,
+        size: u8,
+    },
+    /// Cycles since the previous local timestamp
+    #[serde(rename_all = "camelCase")]
+    Timestamp { delta: u32 },
+    #[serde(rename_all = "camelCase")]
+    GlobalTimestamp { value: u32 },
+    /// The ITM dropped packets because the SWO line could not keep up
+    Overflow,
+}
+
+/// Streaming ITM decoder, packets split across SWO reads are kept until the rest arrives
+#[derive(Debug, Default)]
+pub struct ItmDecoder {
+    pending: Vec<u8>,
+}
+
+// Reads continuation bytes (bit 7 set means another byte follows) holding 7 bits each.
+// Returns the value and the number of bytes used, or None if the packet is incomplete.
+fn read_continued(data: &[u8]) -> Option<(u32, usize)> {
+    let mut value: u32 = 0;
+    for (idx, byte) in data.iter().enumerate().take(5) {
+        value |= ((byte & !ITM_CONTINUATION) as u32) << (7 * idx);
+        if byte & ITM_CONTINUATION == 0 {
+            return Some((value, idx + 1));
+        }
+    }
+
+    // Longer than any packet the ITM sends, resynchronise on the next byte
+    if data.len() >= 5 {
+        return Some((value, 5));
+    }
+
+    None
+}
+
+fn read_payload(data: &[u8], size: usize) -> u32 {
+    data[..size]
+        .iter()
+        .rev()
+        .fold(0u32, |acc, byte| (acc << 8) | *byte as u32)
+}
+
+impl ItmDecoder {
+    pub fn new() -> ItmDecoder {
+        ItmDecoder::default()
+    }
+
+    pub fn feed(&mut self, data: &[u8]) -> Vec<ItmPacket> {
+        self.pending.extend_from_slice(data);
+
+        let mut packets = Vec::new();
+        let mut offset = 0;
+        while offset < self.pending.len() {
+            match self.decode(&self.pending[offset..]) {
+                Some((packet, used)) => {
+                    packets.extend(packet);
+                    offset += used;
+                }
+                None => break,
+            }
+        }
+
+        self.pending.drain(..offset);
+        packets
+    }
+
+    // Decodes one packet at the start of `data`, None if more bytes are needed
+    fn decode(&self, data: &[u8]) -> Option<(Option<ItmPacket>, usize)> {
+        let header = data[0];
+        let payload = &data[1..];
+
+        match header {
+            // Part of a synchronisation packet
+            0x00 | 0x80 => Some((None, 1)),
+            ITM_OVERFLOW => Some((Some(ItmPacket::Overflow), 1)),
+            ITM_GTS1 | ITM_GTS2 => {
+                let (value, used) = read_continued(payload)?;
+                Some((Some(ItmPacket::GlobalTimestamp { value }), used + 1))
+            }
+            // Local timestamp, the short form carries the delta in the header
+            _ if header & 0x0f == 0 => {
+                if header & ITM_CONTINUATION == 0 {
+                    let delta = ((header >> 4) & 0x07) as u32;
+                    return Some((Some(ItmPacket::Timestamp { delta }), 1));
+                }
+
+                let (delta, used) = read_continued(payload)?;
+                Some((Some(ItmPacket::Timestamp { delta }), used + 1))
+            }
+            // Extension packet, nothing we report
+            _ if header & 0x0b == 0x08 => {
+                if header & ITM_CONTINUATION == 0 {
+                    return Some((None, 1));
+                }
+
+                let (_, used) = read_continued(payload)?;
+                Some((None, used + 1))
+            }
+            _ if header & 0x03 != 0 => {
+                let size = match header & 0x03 {
+                    1 => 1,
+                    2 => 2,
+                    _ => 4,
+                };
+                if payload.len() < size {
+                    return None;
+                }
+
+                let value = read_payload(payload, size);
+                let id = header >> 3;
+                let packet = if header & 0x04 == 0 {
+                    ItmPacket::Instrumentation {
+                        port: id,
+                        value,
+                        size: size as u8,
+                    }
+                } else {
+                    ItmPacket::Hardware {
+                        discriminator: id,
+                        value,
+                        size: size as u8,
+                    }
+                };
+
+                Some((Some(packet), size + 1))
+            }
+            // Reserved header, skip it and try to resynchronise
+            _ => Some((None, 1)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_stimulus_writes_of_each_size() {
+        let mut decoder = ItmDecoder::new();
+        // Port 0 byte, port 1 halfword, port 31 word
+        let packets = decoder.feed(&[0x01, b'A', 0x0a, 0x34, 0x12, 0xfb, 0x78, 0x56, 0x34, 0x12]);
+
+        assert_eq!(
+            packets,
+            vec![
+                ItmPacket::Instrumentation {
+                    port: 0,
+                    value: b'A' as u32,
+                    size: 1
+                },
+                ItmPacket::Instrumentation {
+                    port: 1,
+                    value: 0x1234,
+                    size: 2
+                },
+                ItmPacket::Instrumentation {
+                    port: 31,
+                    value: 0x1234_5678,
+                    size: 4
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_packets_split_across_reads() {
+        let mut decoder = ItmDecoder::new();
+
+        assert!(decoder.feed(&[0x03, 0xef, 0xbe]).is_empty());
+        assert!(decoder.feed(&[0xad]).is_empty());
+        assert_eq!(
+            decoder.feed(&[0xde, 0x01]),
+            vec![ItmPacket::Instrumentation {
+                port: 0,
+                value: 0xdead_beef,
+                size: 4
+            }]
+        );
+        assert_eq!(
+            decoder.feed(b"x"),
+            vec![ItmPacket::Instrumentation {
+                port: 0,
+                value: b'x' as u32,
+                size: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn keeps_timestamps_split_across_reads() {
+        let mut decoder = ItmDecoder::new();
+
+        // Long local timestamp, 0x81 0x01 = 1 + (1 << 7)
+        assert!(decoder.feed(&[0xc0, 0x81]).is_empty());
+        assert_eq!(
+            decoder.feed(&[0x01]),
+            vec![ItmPacket::Timestamp { delta: 129 }]
+        );
+
+        assert!(decoder.feed(&[ITM_GTS1, 0xff]).is_empty());
+        assert_eq!(
+            decoder.feed(&[0x7f]),
+            vec![ItmPacket::GlobalTimestamp { value: 0x3fff }]
+        );
+    }
+
+    #[test]
+    fn decodes_sync_overflow_and_hardware_packets() {
+        let mut decoder = ItmDecoder::new();
+        let mut data = vec![0x00, 0x00, 0x00, 0x00, 0x00, 0x80];
+        // Overflow, short timestamp of 3 cycles, then a PC sample (discriminator 2)
+        data.extend_from_slice(&[ITM_OVERFLOW, 0x30, 0x17, 0x00, 0x10, 0x00, 0x08]);
+
+        assert_eq!(
+            decoder.feed(&data),
+            vec![
+                ItmPacket::Overflow,
+                ItmPacket::Timestamp { delta: 3 },
+                ItmPacket::Hardware {
+                    discriminator: 2,
+                    value: 0x0800_1000,
+                    size: 4
+                },
+            ]
+        );
+    }
+}
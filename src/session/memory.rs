@@ -0,0 +1,85 @@
+use napi::{CallContext, JsBuffer, JsNumber, JsObject, Task};
+use probe_rs::MemoryInterface;
+
+use crate::common::plunger_error::PlungerError;
+
+use super::{
+    breakpoints::{location_arg, location_options},
+    debug_session::{with_session, SessionFn, SessionTask},
+};
+
+// Anything bigger is almost certainly a mistake, e.g. a negative length
+const MAX_READ_LENGTH: u32 = 16 * 1024 * 1024;
+
+/// Like `SessionTask`, but resolves with a Buffer rather than an array of numbers
+pub struct ReadMemoryTask {
+    id: u32,
+    command: Option<SessionFn<Vec<u8>>>,
+}
+
+impl Task for ReadMemoryTask {
+    type Output = Vec<u8>;
+    type JsValue = JsBuffer;
+
+    fn compute(&mut self) -> napi::Result<Self::Output> {
+        let command = self
+            .command
+            .take()
+            .ok_or_else(|| PlungerError::StateError("Memory read already ran".to_string()))?;
+
+        Ok(with_session(self.id, command)?)
+    }
+
+    fn resolve(self, env: napi::Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+        Ok(env.create_buffer_with_data(output)?.into_raw())
+    }
+
+    fn reject(self, _env: napi::Env, err: napi::Error) -> napi::Result<Self::JsValue> {
+        Err(err)
+    }
+}
+
+#[js_function(4)]
+pub fn read_memory(ctx: CallContext) -> napi::Result<JsObject> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let location = location_arg(&ctx, 1)?
+        .ok_or_else(|| PlungerError::InvalidOption("Missing memory address".to_string()))?;
+    let length = ctx.get::<JsNumber>(2)?.get_uint32()?;
+    let options = location_options(&ctx, 3)?;
+
+    if length > MAX_READ_LENGTH {
+        return Err(PlungerError::InvalidOption(format!(
+            "Cannot read {} bytes at once, the limit is {}",
+            length, MAX_READ_LENGTH
+        ))
+        .into());
+    }
+
+    let task = ReadMemoryTask {
+        id,
+        command: Some(Box::new(move |session| {
+            let address =
+                location.resolve(options.elf_path.as_deref(), session.symbols.as_ref())?;
+            let mut data = vec![0u8; length as usize];
+            session.session.core(0)?.read_8(address, &mut data)?;
+            Ok(data)
+        })),
+    };
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
+
+#[js_function(4)]
+pub fn write_memory(ctx: CallContext) -> napi::Result<JsObject> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let location = location_arg(&ctx, 1)?
+        .ok_or_else(|| PlungerError::InvalidOption("Missing memory address".to_string()))?;
+    let data = ctx.get::<JsBuffer>(2)?.into_value()?.to_vec();
+    let options = location_options(&ctx, 3)?;
+
+    let task = SessionTask::new(id, move |session| {
+        let address = location.resolve(options.elf_path.as_deref(), session.symbols.as_ref())?;
+        session.session.core(0)?.write_8(address, &data)?;
+        Ok(data.len() as u32)
+    });
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
@@ -0,0 +1,12 @@
+pub mod breakpoints;
+pub mod core_dump;
+pub mod debug_session;
+pub mod fault;
+pub mod gdb_server;
+pub mod itm;
+pub mod memory;
+pub mod registers;
+pub mod rtt;
+pub mod semihosting;
+pub mod session_binding;
+pub mod swo;
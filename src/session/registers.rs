@@ -0,0 +1,206 @@
+use napi::{CallContext, JsNumber, JsObject, JsString};
+use probe_rs::{Core, CoreRegisterAddress, MemoryInterface};
+use serde::{ser::SerializeMap, Serialize, Serializer};
+
+use crate::common::plunger_error::PlungerError;
+
+use super::debug_session::SessionTask;
+
+// probe-rs packs CONTROL, FAULTMASK, BASEPRI and PRIMASK into one register
+const SPECIAL_REGISTERS: u16 = 20;
+const FPSCR: u16 = 33;
+const FP_S0: u16 = 64;
+const FP_SINGLE_COUNT: u16 = 32;
+// Media and VFP feature register 0, reads as zero without an FPU
+const MVFR0: u32 = 0xe000_ef40;
+
+#[derive(Debug, Clone, Copy)]
+pub struct CoreRegister {
+    pub address: u16,
+    pub shift: u32,
+    pub bits: u32,
+}
+
+const fn full(address: u16) -> CoreRegister {
+    CoreRegister {
+        address,
+        shift: 0,
+        bits: 32,
+    }
+}
+
+const fn special(shift: u32) -> CoreRegister {
+    CoreRegister {
+        address: SPECIAL_REGISTERS,
+        shift,
+        bits: 8,
+    }
+}
+
+/// Integer and system registers every Cortex-M core has
+pub const CORE_REGISTERS: [(&str, CoreRegister); 23] = [
+    ("r0", full(0)),
+    ("r1", full(1)),
+    ("r2", full(2)),
+    ("r3", full(3)),
+    ("r4", full(4)),
+    ("r5", full(5)),
+    ("r6", full(6)),
+    ("r7", full(7)),
+    ("r8", full(8)),
+    ("r9", full(9)),
+    ("r10", full(10)),
+    ("r11", full(11)),
+    ("r12", full(12)),
+    ("sp", full(13)),
+    ("lr", full(14)),
+    ("pc", full(15)),
+    ("xpsr", full(16)),
+    ("msp", full(17)),
+    ("psp", full(18)),
+    ("primask", special(0)),
+    ("basepri", special(8)),
+    ("faultmask", special(16)),
+    ("control", special(24)),
+];
+
+impl CoreRegister {
+    fn mask(&self) -> u32 {
+        match self.bits {
+            32 => u32::MAX,
+            bits => (1 << bits) - 1,
+        }
+    }
+
+    pub fn read(&self, core: &mut Core) -> Result<u32, PlungerError> {
+        let raw = core.read_core_reg(self.address)?;
+        Ok((raw >> self.shift) & self.mask())
+    }
+
+    /// Registers sharing a word with others are read back and merged
+    pub fn write(&self, core: &mut Core, value: u32) -> Result<(), PlungerError> {
+        let raw = match self.bits {
+            32 => value,
+            _ => {
+                let mask = self.mask() << self.shift;
+                let raw = core.read_core_reg(self.address)?;
+                (raw & !mask) | ((value << self.shift) & mask)
+            }
+        };
+
+        core.write_core_reg(CoreRegisterAddress(self.address), raw)?;
+        Ok(())
+    }
+}
+
+pub fn has_fpu(core: &mut Core) -> Result<bool, PlungerError> {
+    Ok(core.read_word_32(MVFR0)? != 0)
+}
+
+fn fp_registers() -> impl Iterator<Item = (String, CoreRegister)> {
+    std::iter::once(("fpscr".to_string(), full(FPSCR)))
+        .chain((0..FP_SINGLE_COUNT).map(|idx| (format!("s{}", idx), full(FP_S0 + idx))))
+}
+
+/// One of CORE_REGISTERS by name, accepting R13-R15 for sp, lr and pc
+pub fn core_register(name: &str) -> Option<CoreRegister> {
+    let canonical = match name {
+        "r13" => "sp",
+        "r14" => "lr",
+        "r15" => "pc",
+        other => other,
+    };
+
+    CORE_REGISTERS
+        .iter()
+        .find(|(reg, _)| *reg == canonical)
+        .map(|(_, reg)| *reg)
+}
+
+fn find_register(core: &mut Core, name: &str) -> Result<CoreRegister, PlungerError> {
+    let name = name.to_lowercase();
+    if let Some(reg) = core_register(&name) {
+        return Ok(reg);
+    }
+
+    if has_fpu(core)? {
+        if let Some((_, reg)) = fp_registers().find(|(reg, _)| *reg == name) {
+            return Ok(reg);
+        }
+    }
+
+    Err(PlungerError::InvalidOption(format!(
+        "Unknown register {}",
+        name
+    )))
+}
+
+fn check_halted(core: &mut Core) -> Result<(), PlungerError> {
+    match core.core_halted()? {
+        true => Ok(()),
+        false => Err(PlungerError::StateError(
+            "Core is running, halt it before accessing registers".to_string(),
+        )),
+    }
+}
+
+/// Register values in the order they were read, serialised as a plain object
+pub struct RegisterDump(pub(crate) Vec<(String, u32)>);
+
+impl RegisterDump {
+    pub fn get(&self, name: &str) -> Option<u32> {
+        self.0
+            .iter()
+            .find(|(reg, _)| reg == name)
+            .map(|(_, value)| *value)
+    }
+}
+
+impl Serialize for RegisterDump {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, value) in self.0.iter() {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+pub fn read_all(core: &mut Core) -> Result<RegisterDump, PlungerError> {
+    check_halted(core)?;
+
+    let mut values = CORE_REGISTERS
+        .iter()
+        .map(|(name, reg)| Ok((name.to_string(), reg.read(core)?)))
+        .collect::<Result<Vec<(String, u32)>, PlungerError>>()?;
+
+    if has_fpu(core)? {
+        for (name, reg) in fp_registers() {
+            let value = reg.read(core)?;
+            values.push((name, value));
+        }
+    }
+
+    Ok(RegisterDump(values))
+}
+
+#[js_function(1)]
+pub fn read_registers(ctx: CallContext) -> napi::Result<JsObject> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let task = SessionTask::new(id, |session| read_all(&mut session.session.core(0)?));
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
+
+#[js_function(3)]
+pub fn write_register(ctx: CallContext) -> napi::Result<JsObject> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let name = ctx.get::<JsString>(1)?.into_utf8()?.as_str()?.to_string();
+    let value = ctx.get::<JsNumber>(2)?.get_uint32()?;
+
+    let task = SessionTask::new(id, move |session| {
+        let mut core = session.session.core(0)?;
+        check_halted(&mut core)?;
+        find_register(&mut core, &name)?.write(&mut core, value)
+    });
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
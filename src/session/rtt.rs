@@ -0,0 +1,522 @@
+use std::ops::Range;
+
+use napi::{
+    threadsafe_function::{ThreadSafeCallContext, ThreadsafeFunction, ThreadsafeFunctionCallMode},
+    CallContext, JsBuffer, JsFunction, JsNumber, JsObject, JsString, JsUnknown, ValueType,
+};
+use probe_rs::{config::MemoryRegion, Core, MemoryInterface, Session};
+use serde::{Deserialize, Serialize};
+
+use crate::{common::plunger_error::PlungerError, symbols::elf_resolver::ElfResolver};
+
+use super::debug_session::SessionTask;
+
+const RTT_ID: &[u8; 16] = b"SEGGER RTT\0\0\0\0\0\0";
+const RTT_SYMBOL: &str = "_SEGGER_RTT";
+// ID, then the number of up and down channels
+const RTT_HEADER_SIZE: u32 = 24;
+// Name, buffer, size, write offset, read offset, flags
+const RTT_CHANNEL_SIZE: u32 = 24;
+// Anything above this is garbage rather than a real control block
+const RTT_MAX_CHANNELS: u32 = 32;
+const RTT_NAME_MAX: usize = 32;
+const RTT_SCAN_CHUNK: u32 = 4096;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanRange {
+    start: u32,
+    end: u32,
+}
+
+/// Where to look for the control block, tried in this order
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RttOptions {
+    control_block_address: Option<u32>,
+    /// Falls back to the session's ELF file when not given
+    elf_path: Option<String>,
+    scan_ranges: Option<Vec<ScanRange>>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum RttMode {
+    NoBlockSkip,
+    NoBlockTrim,
+    BlockIfFull,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RttChannelInfo {
+    index: usize,
+    name: Option<String>,
+    size: u32,
+    mode: RttMode,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RttInfo {
+    control_block_address: u32,
+    up_channels: Vec<RttChannelInfo>,
+    down_channels: Vec<RttChannelInfo>,
+}
+
+pub struct RttData {
+    channel: usize,
+    data: Vec<u8>,
+}
+
+struct RttChannel {
+    info: RttChannelInfo,
+    descriptor: u32,
+    buffer: u32,
+}
+
+impl RttChannel {
+    fn read(core: &mut Core, index: usize, descriptor: u32) -> Result<RttChannel, PlungerError> {
+        let mut fields = [0u32; 6];
+        core.read_32(descriptor, &mut fields)?;
+
+        let name = match fields[0] {
+            0 => None,
+            address => {
+                let mut raw = vec![0u8; RTT_NAME_MAX];
+                core.read_8(address, &mut raw)?;
+                let len = raw.iter().position(|byte| *byte == 0).unwrap_or(raw.len());
+                Some(String::from_utf8_lossy(&raw[..len]).to_string())
+            }
+        };
+
+        Ok(RttChannel {
+            info: RttChannelInfo {
+                index,
+                name,
+                size: fields[2],
+                mode: match fields[5] & 0b11 {
+                    0 => RttMode::NoBlockSkip,
+                    1 => RttMode::NoBlockTrim,
+                    _ => RttMode::BlockIfFull,
+                },
+            },
+            descriptor,
+            buffer: fields[1],
+        })
+    }
+
+    // Returns the write and read offsets, checked against the buffer size
+    fn offsets(&self, core: &mut Core) -> Result<(u32, u32), PlungerError> {
+        let mut offsets = [0u32; 2];
+        core.read_32(self.descriptor + 12, &mut offsets)?;
+
+        checked_offsets(self.info.size, offsets[0], offsets[1]).ok_or_else(|| {
+            PlungerError::RttError(format!(
+                "channel {} has corrupt offsets, was the control block overwritten?",
+                self.info.index
+            ))
+        })
+    }
+
+    fn read_up(&self, core: &mut Core) -> Result<Vec<u8>, PlungerError> {
+        let (write, read) = self.offsets(core)?;
+        let (first, wrapped) = readable_spans(self.info.size, write, read);
+        if first.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut data = vec![0u8; first.len() + wrapped.len()];
+        let (head, tail) = data.split_at_mut(first.len());
+        core.read_8(self.buffer + first.start, head)?;
+        if !wrapped.is_empty() {
+            core.read_8(self.buffer, tail)?;
+        }
+
+        core.write_word_32(self.descriptor + 16, write)?;
+        Ok(data)
+    }
+
+    fn write_down(&self, core: &mut Core, data: &[u8]) -> Result<usize, PlungerError> {
+        let (write, read) = self.offsets(core)?;
+        let (first, wrapped) = writable_spans(self.info.size, write, read, data.len());
+
+        core.write_8(self.buffer + first.start, &data[..first.len()])?;
+        let count = first.len() + wrapped.len();
+        if !wrapped.is_empty() {
+            core.write_8(self.buffer, &data[first.len()..count])?;
+        }
+
+        let write = (write as usize + count) % self.info.size as usize;
+        core.write_word_32(self.descriptor + 12, write as u32)?;
+        Ok(count)
+    }
+}
+
+// Offsets past the end of the buffer mean the control block is not what we think it is
+fn checked_offsets(size: u32, write: u32, read: u32) -> Option<(u32, u32)> {
+    if write >= size || read >= size {
+        return None;
+    }
+
+    Some((write, read))
+}
+
+// Unread data, the second span is the part that wrapped around to the start of the buffer
+fn readable_spans(size: u32, write: u32, read: u32) -> (Range<u32>, Range<u32>) {
+    if write >= read {
+        (read..write, 0..0)
+    } else {
+        (read..size, 0..write)
+    }
+}
+
+// Where up to `len` bytes go, the second span is the part that wraps around. One byte always
+// stays free so a full buffer can be told apart from an empty one.
+fn writable_spans(size: u32, write: u32, read: u32, len: usize) -> (Range<u32>, Range<u32>) {
+    let free = if read > write {
+        read - write - 1
+    } else {
+        size - 1 - write + read
+    };
+    let count = len.min(free as usize) as u32;
+    let first = count.min(size - write);
+
+    (write..write + first, 0..count - first)
+}
+
+fn scan_for_control_block(
+    core: &mut Core,
+    ranges: &[ScanRange],
+) -> Result<Option<u32>, PlungerError> {
+    scan_for_id(ranges, |address, chunk| Ok(core.read_8(address, chunk)?))
+}
+
+fn scan_for_id<F>(ranges: &[ScanRange], mut read: F) -> Result<Option<u32>, PlungerError>
+where
+    F: FnMut(u32, &mut [u8]) -> Result<(), PlungerError>,
+{
+    for range in ranges {
+        let mut address = range.start;
+        while address < range.end {
+            // Overlap the chunks so an ID across the boundary is still found
+            let len = (RTT_SCAN_CHUNK + RTT_ID.len() as u32 - 1).min(range.end - address);
+            let mut chunk = vec![0u8; len as usize];
+            read(address, &mut chunk)?;
+
+            if let Some(offset) = chunk.windows(RTT_ID.len()).position(|w| w == RTT_ID) {
+                return Ok(Some(address + offset as u32));
+            }
+
+            address = address.saturating_add(RTT_SCAN_CHUNK);
+        }
+    }
+
+    Ok(None)
+}
+
+fn locate_control_block(
+    session: &mut Session,
+    options: &RttOptions,
+    symbols: Option<&ElfResolver>,
+) -> Result<u32, PlungerError> {
+    if let Some(address) = options.control_block_address {
+        return Ok(address);
+    }
+
+    let loaded = options
+        .elf_path
+        .as_deref()
+        .map(ElfResolver::load)
+        .transpose()?;
+    if let Some(symbols) = loaded.as_ref().or(symbols) {
+        return symbols
+            .find(RTT_SYMBOL)?
+            .map(|symbol| symbol.address)
+            .ok_or_else(|| {
+                PlungerError::RttError(format!(
+                    "{} is not defined in {}",
+                    RTT_SYMBOL,
+                    symbols.path()
+                ))
+            });
+    }
+
+    let ranges = match &options.scan_ranges {
+        Some(ranges) => ranges.clone(),
+        None => session
+            .target()
+            .memory_map
+            .iter()
+            .filter_map(|region| match region {
+                MemoryRegion::Ram(ram) => Some(ScanRange {
+                    start: ram.range.start,
+                    end: ram.range.end,
+                }),
+                _ => None,
+            })
+            .collect(),
+    };
+
+    let mut core = session.core(0)?;
+    scan_for_control_block(&mut core, &ranges)?
+        .ok_or_else(|| PlungerError::RttError("control block not found in RAM".to_string()))
+}
+
+/// An attached RTT control block, polled by the session thread
+pub struct RttLink {
+    control_block: u32,
+    up: Vec<RttChannel>,
+    down: Vec<RttChannel>,
+    callback: ThreadsafeFunction<RttData>,
+}
+
+impl RttLink {
+    pub fn attach(
+        session: &mut Session,
+        options: &RttOptions,
+        symbols: Option<&ElfResolver>,
+        callback: ThreadsafeFunction<RttData>,
+    ) -> Result<RttLink, PlungerError> {
+        let control_block = locate_control_block(session, options, symbols)?;
+        let mut core = session.core(0)?;
+
+        let mut header = [0u8; RTT_HEADER_SIZE as usize];
+        core.read_8(control_block, &mut header)?;
+        if &header[..RTT_ID.len()] != RTT_ID {
+            return Err(PlungerError::RttError(format!(
+                "no control block at 0x{:08x}, the firmware may not have initialised it yet",
+                control_block
+            )));
+        }
+
+        let max_up = u32::from_le_bytes([header[16], header[17], header[18], header[19]]);
+        let max_down = u32::from_le_bytes([header[20], header[21], header[22], header[23]]);
+        if max_up > RTT_MAX_CHANNELS || max_down > RTT_MAX_CHANNELS {
+            return Err(PlungerError::RttError(format!(
+                "control block at 0x{:08x} has {} up and {} down channels",
+                control_block, max_up, max_down
+            )));
+        }
+
+        let descriptor = |idx: u32| control_block + RTT_HEADER_SIZE + idx * RTT_CHANNEL_SIZE;
+        let up = (0..max_up)
+            .map(|idx| RttChannel::read(&mut core, idx as usize, descriptor(idx)))
+            .collect::<Result<Vec<RttChannel>, PlungerError>>()?;
+        let down = (0..max_down)
+            .map(|idx| RttChannel::read(&mut core, idx as usize, descriptor(max_up + idx)))
+            .collect::<Result<Vec<RttChannel>, PlungerError>>()?;
+
+        Ok(RttLink {
+            control_block,
+            up,
+            down,
+            callback,
+        })
+    }
+
+    pub fn info(&self) -> RttInfo {
+        RttInfo {
+            control_block_address: self.control_block,
+            up_channels: self.up.iter().map(|channel| channel.info.clone()).collect(),
+            down_channels: self
+                .down
+                .iter()
+                .map(|channel| channel.info.clone())
+                .collect(),
+        }
+    }
+
+    /// Drains every up channel and hands the data to the JS callback
+    pub fn poll(&mut self, session: &mut Session) -> Result<(), PlungerError> {
+        let mut core = session.core(0)?;
+
+        for channel in self.up.iter().filter(|channel| channel.info.size > 0) {
+            let data = channel.read_up(&mut core)?;
+            if data.is_empty() {
+                continue;
+            }
+
+            self.callback.call(
+                Ok(RttData {
+                    channel: channel.info.index,
+                    data,
+                }),
+                ThreadsafeFunctionCallMode::NonBlocking,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Returns how many bytes fitted, the rest is up to the caller to retry
+    pub fn write(
+        &mut self,
+        session: &mut Session,
+        channel: usize,
+        data: &[u8],
+    ) -> Result<usize, PlungerError> {
+        let channel = self
+            .down
+            .get(channel)
+            .filter(|channel| channel.info.size > 0)
+            .ok_or_else(|| {
+                PlungerError::InvalidOption(format!("RTT down channel {} does not exist", channel))
+            })?;
+
+        let mut core = session.core(0)?;
+        channel.write_down(&mut core, data)
+    }
+
+    pub fn report_error(&self, err: PlungerError) {
+        self.callback
+            .call(Err(err.into()), ThreadsafeFunctionCallMode::NonBlocking);
+    }
+}
+
+fn no_rtt() -> PlungerError {
+    PlungerError::StateError("RTT is not started on this session".to_string())
+}
+
+#[js_function(3)]
+pub fn start_rtt(ctx: CallContext) -> napi::Result<JsObject> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let options: RttOptions = match ctx.try_get::<JsObject>(1)? {
+        napi::Either::A(obj) => ctx.env.from_js_value(obj)?,
+        napi::Either::B(_) => RttOptions::default(),
+    };
+    let func = ctx.get::<JsFunction>(2)?;
+    let callback =
+        ctx.env
+            .create_threadsafe_function(&func, 0, |ctx: ThreadSafeCallContext<RttData>| {
+                let mut event = ctx.env.create_object()?;
+                event.set_named_property(
+                    "channel",
+                    ctx.env.create_uint32(ctx.value.channel as u32)?,
+                )?;
+                event.set_named_property(
+                    "data",
+                    ctx.env.create_buffer_with_data(ctx.value.data)?.into_raw(),
+                )?;
+                Ok(vec![event])
+            })?;
+
+    let task = SessionTask::new(id, move |session| {
+        let link = RttLink::attach(
+            &mut session.session,
+            &options,
+            session.symbols.as_ref(),
+            callback,
+        )?;
+        let info = link.info();
+        session.rtt = Some(link);
+        Ok(info)
+    });
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
+
+#[js_function(3)]
+pub fn rtt_write(ctx: CallContext) -> napi::Result<JsObject> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let channel = ctx.get::<JsNumber>(1)?.get_uint32()? as usize;
+    let data = match ctx.get::<JsUnknown>(2)?.get_type()? {
+        ValueType::String => ctx
+            .get::<JsString>(2)?
+            .into_utf8()?
+            .as_str()?
+            .as_bytes()
+            .to_vec(),
+        _ => ctx.get::<JsBuffer>(2)?.into_value()?.to_vec(),
+    };
+
+    let task = SessionTask::new(id, move |session| {
+        let rtt = session.rtt.as_mut().ok_or_else(no_rtt)?;
+        rtt.write(&mut session.session, channel, &data)
+    });
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
+
+#[js_function(1)]
+pub fn stop_rtt(ctx: CallContext) -> napi::Result<JsObject> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let task = SessionTask::new(id, |session| Ok(session.rtt.take().is_some()));
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_offsets_past_the_buffer() {
+        assert_eq!(checked_offsets(16, 15, 0), Some((15, 0)));
+        assert_eq!(checked_offsets(16, 16, 0), None);
+        assert_eq!(checked_offsets(16, 0, 0xffff_ffff), None);
+        assert_eq!(checked_offsets(0, 0, 0), None);
+    }
+
+    #[test]
+    fn reads_up_to_the_write_offset() {
+        assert_eq!(readable_spans(16, 4, 4), (4..4, 0..0));
+        assert_eq!(readable_spans(16, 10, 4), (4..10, 0..0));
+        // Wrapped, the write offset is behind the read offset
+        assert_eq!(readable_spans(16, 3, 12), (12..16, 0..3));
+        assert_eq!(readable_spans(16, 0, 12), (12..16, 0..0));
+    }
+
+    #[test]
+    fn keeps_one_byte_free_when_writing() {
+        // Empty buffer holds size - 1 bytes
+        assert_eq!(writable_spans(16, 0, 0, 100), (0..15, 0..0));
+        assert_eq!(writable_spans(16, 4, 4, 100), (4..16, 0..3));
+        // Full buffers
+        assert_eq!(writable_spans(16, 15, 0, 1), (15..15, 0..0));
+        assert_eq!(writable_spans(16, 3, 4, 1), (3..3, 0..0));
+        // Room up to just before the read offset
+        assert_eq!(writable_spans(16, 2, 10, 100), (2..9, 0..0));
+        assert_eq!(writable_spans(16, 12, 6, 100), (12..16, 0..5));
+        assert_eq!(writable_spans(16, 12, 6, 6), (12..16, 0..2));
+        assert_eq!(writable_spans(16, 12, 6, 3), (12..15, 0..0));
+    }
+
+    fn scan(memory: &[u8], ranges: &[ScanRange]) -> Option<u32> {
+        scan_for_id(ranges, |address, chunk| {
+            let start = address as usize;
+            chunk.copy_from_slice(&memory[start..start + chunk.len()]);
+            Ok(())
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn finds_an_id_across_chunk_boundaries() {
+        let size = RTT_SCAN_CHUNK as usize * 3;
+        let range = [ScanRange {
+            start: 0,
+            end: size as u32,
+        }];
+
+        for at in [0, 4090, RTT_SCAN_CHUNK as usize - 1, size - RTT_ID.len()] {
+            let mut memory = vec![0u8; size];
+            memory[at..at + RTT_ID.len()].copy_from_slice(RTT_ID);
+            assert_eq!(scan(&memory, &range), Some(at as u32));
+        }
+
+        assert_eq!(scan(&vec![0u8; size], &range), None);
+    }
+
+    #[test]
+    fn only_scans_inside_the_ranges() {
+        let mut memory = vec![0u8; 0x2000];
+        memory[0x1008..0x1018].copy_from_slice(RTT_ID);
+
+        let range = |start, end| ScanRange { start, end };
+        assert_eq!(scan(&memory, &[range(0, 0x1000)]), None);
+        // The ID has to fit in the range as a whole
+        assert_eq!(scan(&memory, &[range(0x1000, 0x1010)]), None);
+        assert_eq!(
+            scan(&memory, &[range(0, 0x100), range(0x1000, 0x1018)]),
+            Some(0x1008)
+        );
+    }
+}
@@ -0,0 +1,543 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Component, Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use napi::{
+    threadsafe_function::{ThreadSafeCallContext, ThreadsafeFunction, ThreadsafeFunctionCallMode},
+    CallContext, JsFunction, JsNumber, JsObject,
+};
+use probe_rs::{Core, CoreRegisterAddress, CoreStatus, MemoryInterface, Session};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::{
+    common::{cancel_token::CancelToken, plunger_error::PlungerError},
+    flasher::flash_report::to_millis,
+};
+
+use super::debug_session::with_session;
+
+// BKPT 0xAB in Thumb encoding
+const SEMIHOSTING_BKPT: u16 = 0xbeab;
+
+const SYS_OPEN: u32 = 0x01;
+const SYS_CLOSE: u32 = 0x02;
+const SYS_WRITEC: u32 = 0x03;
+const SYS_WRITE0: u32 = 0x04;
+const SYS_WRITE: u32 = 0x05;
+const SYS_READ: u32 = 0x06;
+const SYS_ISTTY: u32 = 0x09;
+const SYS_SEEK: u32 = 0x0a;
+const SYS_FLEN: u32 = 0x0c;
+const SYS_CLOCK: u32 = 0x10;
+const SYS_TIME: u32 = 0x11;
+const SYS_ERRNO: u32 = 0x13;
+const SYS_EXIT: u32 = 0x18;
+const SYS_EXIT_EXTENDED: u32 = 0x20;
+
+const ADP_STOPPED_APPLICATION_EXIT: u32 = 0x20026;
+
+// ":tt" opened for reading, writing and appending, files get handles after these
+const HANDLE_STDIN: u32 = 1;
+const HANDLE_STDOUT: u32 = 2;
+const HANDLE_STDERR: u32 = 3;
+const FIRST_FILE_HANDLE: u32 = 4;
+
+// newlib errno values reported through SYS_ERRNO
+const ENOENT: u32 = 2;
+const EBADF: u32 = 9;
+const EACCES: u32 = 13;
+
+const MAX_STRING_LEN: usize = 4096;
+// Longer SYS_READ and SYS_WRITE calls are cut short, the target sees a partial transfer
+const MAX_TRANSFER_LEN: u32 = 64 * 1024;
+const DEFAULT_SEMIHOSTING_TIMEOUT_MS: u64 = 60_000;
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SemihostingOptions {
+    /// Directory the target may open files from, file operations fail without one
+    sandbox_dir: Option<String>,
+    /// Reset the target before running, rather than resuming it where it is
+    reset: Option<bool>,
+    cancel_token: Option<u32>,
+    timeout_ms: Option<u64>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum SemihostingStop {
+    /// SYS_EXIT or SYS_EXIT_EXTENDED
+    Exit,
+    /// Halted by something other than a semihosting call, e.g. a breakpoint
+    Halted,
+    LockedUp,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SemihostingResult {
+    reason: SemihostingStop,
+    exit_code: Option<i32>,
+    output: String,
+    duration_ms: f64,
+}
+
+enum Serviced {
+    Return(u32),
+    Exit(i32),
+}
+
+fn reg(index: u16) -> CoreRegisterAddress {
+    CoreRegisterAddress(index)
+}
+
+/// A semihosting run in progress, serviced by the session thread between commands
+pub struct SemihostingRun {
+    sandbox: Option<PathBuf>,
+    files: HashMap<u32, File>,
+    next_handle: u32,
+    errno: u32,
+    output: Vec<u8>,
+    started_at: Instant,
+    cancel: CancelToken,
+    on_output: Option<ThreadsafeFunction<String>>,
+    done: mpsc::UnboundedSender<Result<SemihostingResult, PlungerError>>,
+}
+
+impl SemihostingRun {
+    fn start(
+        session: &mut Session,
+        options: &SemihostingOptions,
+        cancel: CancelToken,
+        on_output: Option<ThreadsafeFunction<String>>,
+        done: mpsc::UnboundedSender<Result<SemihostingResult, PlungerError>>,
+    ) -> Result<SemihostingRun, PlungerError> {
+        let sandbox = match &options.sandbox_dir {
+            Some(dir) => Some(Path::new(dir).canonicalize().map_err(|err| {
+                PlungerError::InvalidOption(format!("sandboxDir {}: {}", dir, err))
+            })?),
+            None => None,
+        };
+
+        let mut core = session.core(0)?;
+        if options.reset.unwrap_or(false) {
+            core.reset()?;
+        } else if core.core_halted()? {
+            core.run()?;
+        }
+
+        Ok(SemihostingRun {
+            sandbox,
+            files: HashMap::new(),
+            next_handle: FIRST_FILE_HANDLE,
+            errno: 0,
+            output: Vec::new(),
+            started_at: Instant::now(),
+            cancel,
+            on_output,
+            done,
+        })
+    }
+
+    /// Returns true once the run is over and its result has been handed back
+    pub fn poll(&mut self, session: &mut Session) -> bool {
+        let result = match self.step(session) {
+            Ok(None) => return false,
+            Ok(Some(result)) => Ok(result),
+            Err(err) => Err(err),
+        };
+
+        let _ = self.done.send(result);
+        true
+    }
+
+    fn finish(&self, reason: SemihostingStop, exit_code: Option<i32>) -> SemihostingResult {
+        SemihostingResult {
+            reason,
+            exit_code,
+            output: String::from_utf8_lossy(&self.output).to_string(),
+            duration_ms: to_millis(self.started_at.elapsed()),
+        }
+    }
+
+    fn step(&mut self, session: &mut Session) -> Result<Option<SemihostingResult>, PlungerError> {
+        self.cancel.check()?;
+
+        let mut core = session.core(0)?;
+        match core.status()? {
+            CoreStatus::Halted(_) => (),
+            CoreStatus::LockedUp => return Ok(Some(self.finish(SemihostingStop::LockedUp, None))),
+            _ => return Ok(None),
+        }
+
+        let pc = core.read_core_reg(core.registers().program_counter())?;
+        let mut instruction = [0u8; 2];
+        core.read_8(pc, &mut instruction)?;
+        if u16::from_le_bytes(instruction) != SEMIHOSTING_BKPT {
+            return Ok(Some(self.finish(SemihostingStop::Halted, None)));
+        }
+
+        let operation = core.read_core_reg(reg(0))?;
+        let parameter = core.read_core_reg(reg(1))?;
+        match self.service(&mut core, operation, parameter)? {
+            Serviced::Return(value) => {
+                core.write_core_reg(reg(0), value)?;
+                core.write_core_reg(core.registers().program_counter().into(), pc + 2)?;
+                core.run()?;
+                Ok(None)
+            }
+            Serviced::Exit(code) => Ok(Some(self.finish(SemihostingStop::Exit, Some(code)))),
+        }
+    }
+
+    fn service(
+        &mut self,
+        core: &mut Core,
+        operation: u32,
+        parameter: u32,
+    ) -> Result<Serviced, PlungerError> {
+        let value = match operation {
+            SYS_OPEN => {
+                let mut args = [0u32; 3];
+                core.read_32(parameter, &mut args)?;
+                let name = read_string(core, args[0], Some(args[2] as usize))?;
+                self.open(&name, args[1])
+            }
+            SYS_CLOSE => {
+                let handle = core.read_word_32(parameter)?;
+                match handle {
+                    HANDLE_STDIN | HANDLE_STDOUT | HANDLE_STDERR => 0,
+                    _ => match self.files.remove(&handle) {
+                        Some(_) => 0,
+                        None => self.fail(EBADF),
+                    },
+                }
+            }
+            SYS_WRITEC => {
+                let mut byte = [0u8; 1];
+                core.read_8(parameter, &mut byte)?;
+                self.emit(&byte);
+                0
+            }
+            SYS_WRITE0 => {
+                let text = read_string(core, parameter, None)?;
+                self.emit(text.as_bytes());
+                0
+            }
+            SYS_WRITE => {
+                let mut args = [0u32; 3];
+                core.read_32(parameter, &mut args)?;
+                match args[0] {
+                    HANDLE_STDOUT | HANDLE_STDERR => {
+                        let mut data = vec![0u8; args[2].min(MAX_TRANSFER_LEN) as usize];
+                        core.read_8(args[1], &mut data)?;
+                        self.emit(&data);
+                        args[2] - data.len() as u32
+                    }
+                    // Files are read-only, report nothing as written
+                    _ => {
+                        self.errno = EACCES;
+                        args[2]
+                    }
+                }
+            }
+            SYS_READ => {
+                let mut args = [0u32; 3];
+                core.read_32(parameter, &mut args)?;
+                match self.files.get_mut(&args[0]) {
+                    Some(file) => {
+                        let mut data = vec![0u8; args[2].min(MAX_TRANSFER_LEN) as usize];
+                        let count = file.read(&mut data).unwrap_or(0);
+                        core.write_8(args[1], &data[..count])?;
+                        args[2] - count as u32
+                    }
+                    // Nothing to read on stdin, which looks like EOF to the target
+                    None if args[0] == HANDLE_STDIN => args[2],
+                    None => self.fail(EBADF),
+                }
+            }
+            SYS_ISTTY => {
+                let handle = core.read_word_32(parameter)?;
+                (handle < FIRST_FILE_HANDLE) as u32
+            }
+            SYS_SEEK => {
+                let mut args = [0u32; 2];
+                core.read_32(parameter, &mut args)?;
+                let sought = self
+                    .files
+                    .get_mut(&args[0])
+                    .map(|file| file.seek(SeekFrom::Start(args[1] as u64)).is_ok());
+                match sought {
+                    Some(true) => 0,
+                    _ => self.fail(EBADF),
+                }
+            }
+            SYS_FLEN => {
+                let handle = core.read_word_32(parameter)?;
+                match self
+                    .files
+                    .get(&handle)
+                    .and_then(|file| file.metadata().ok())
+                {
+                    Some(metadata) => metadata.len() as u32,
+                    None => self.fail(EBADF),
+                }
+            }
+            SYS_CLOCK => (self.started_at.elapsed().as_millis() / 10) as u32,
+            SYS_TIME => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or(Duration::ZERO)
+                .as_secs() as u32,
+            SYS_ERRNO => self.errno,
+            SYS_EXIT => {
+                return Ok(Serviced::Exit(
+                    (parameter != ADP_STOPPED_APPLICATION_EXIT) as i32,
+                ))
+            }
+            SYS_EXIT_EXTENDED => {
+                let mut args = [0u32; 2];
+                core.read_32(parameter, &mut args)?;
+                return Ok(Serviced::Exit(args[1] as i32));
+            }
+            _ => u32::MAX,
+        };
+
+        Ok(Serviced::Return(value))
+    }
+
+    fn fail(&mut self, errno: u32) -> u32 {
+        self.errno = errno;
+        u32::MAX
+    }
+
+    fn emit(&mut self, data: &[u8]) {
+        self.output.extend_from_slice(data);
+        if let Some(callback) = &self.on_output {
+            callback.call(
+                Ok(String::from_utf8_lossy(data).to_string()),
+                ThreadsafeFunctionCallMode::NonBlocking,
+            );
+        }
+    }
+
+    // Modes 0-3 read, 4-7 write, 8-11 append, only reading is allowed for real files
+    fn open(&mut self, name: &str, mode: u32) -> u32 {
+        if name == ":tt" {
+            return match mode {
+                0..=3 => HANDLE_STDIN,
+                4..=7 => HANDLE_STDOUT,
+                _ => HANDLE_STDERR,
+            };
+        }
+
+        if mode > 3 {
+            return self.fail(EACCES);
+        }
+
+        let file = match self
+            .sandbox
+            .as_deref()
+            .and_then(|sandbox| sandbox_path(sandbox, name))
+        {
+            Some(path) => File::open(path),
+            None => return self.fail(EACCES),
+        };
+
+        match file {
+            Ok(file) => {
+                let handle = self.next_handle;
+                self.next_handle += 1;
+                self.files.insert(handle, file);
+                handle
+            }
+            Err(_) => self.fail(ENOENT),
+        }
+    }
+}
+
+// Only relative paths that stay inside the sandbox, symlinks included
+fn sandbox_path(sandbox: &Path, name: &str) -> Option<PathBuf> {
+    let path = Path::new(name);
+    if path
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return None;
+    }
+
+    let full = sandbox.join(path).canonicalize().ok()?;
+    full.starts_with(sandbox).then_some(full)
+}
+
+// Strings are either NUL terminated or come with a length
+fn read_string(core: &mut Core, address: u32, len: Option<usize>) -> Result<String, PlungerError> {
+    let mut data = vec![0u8; len.unwrap_or(MAX_STRING_LEN).min(MAX_STRING_LEN)];
+    if len.is_some() {
+        core.read_8(address, &mut data)?;
+        return Ok(String::from_utf8_lossy(&data).to_string());
+    }
+
+    // Read in small steps, the string may end right before unmapped memory
+    let mut end = 0;
+    for chunk in data.chunks_mut(64) {
+        core.read_8(address + end as u32, chunk)?;
+        if let Some(nul) = chunk.iter().position(|byte| *byte == 0) {
+            end += nul;
+            return Ok(String::from_utf8_lossy(&data[..end]).to_string());
+        }
+        end += chunk.len();
+    }
+
+    Ok(String::from_utf8_lossy(&data).to_string())
+}
+
+// The session thread hands the result back once the target exits, nothing waits on a worker
+// thread for the whole run
+async fn run_to_exit(
+    id: u32,
+    options: SemihostingOptions,
+    cancel: CancelToken,
+    on_output: Option<ThreadsafeFunction<String>>,
+) -> napi::Result<SemihostingResult> {
+    let (done, mut result) = mpsc::unbounded_channel();
+
+    let started = tokio::task::spawn_blocking(move || {
+        with_session(id, move |session| {
+            if session.semihosting.is_some() {
+                return Err(PlungerError::StateError(
+                    "Semihosting is already running on this session".to_string(),
+                ));
+            }
+
+            let run =
+                SemihostingRun::start(&mut session.session, &options, cancel, on_output, done)?;
+            session.semihosting = Some(run);
+            Ok(())
+        })
+    })
+    .await;
+
+    match started {
+        Ok(started) => started?,
+        Err(err) => {
+            return Err(napi::Error {
+                status: napi::Status::Unknown,
+                reason: format!("Unexpected failure to start semihosting: {}", err),
+            })
+        }
+    }
+
+    // The sender goes away without a result if the session is closed mid-run
+    Ok(result
+        .recv()
+        .await
+        .ok_or_else(|| PlungerError::StateError("Debug session has been closed".to_string()))??)
+}
+
+#[js_function(3)]
+pub fn run_semihosting(ctx: CallContext) -> napi::Result<JsObject> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let options: SemihostingOptions = match ctx.try_get::<JsObject>(1)? {
+        napi::Either::A(obj) => ctx.env.from_js_value(obj)?,
+        napi::Either::B(_) => SemihostingOptions::default(),
+    };
+    let on_output = match ctx.try_get::<JsFunction>(2)? {
+        napi::Either::A(func) => Some(ctx.env.create_threadsafe_function(
+            &func,
+            0,
+            |ctx: ThreadSafeCallContext<String>| Ok(vec![ctx.env.create_string(&ctx.value)?]),
+        )?),
+        napi::Either::B(_) => None,
+    };
+    let cancel = CancelToken::from_id(options.cancel_token)?;
+    let timeout =
+        Duration::from_millis(options.timeout_ms.unwrap_or(DEFAULT_SEMIHOSTING_TIMEOUT_MS));
+
+    ctx.env.execute_tokio_future(
+        run_to_exit(id, options, cancel.with_timeout(timeout), on_output),
+        |&mut env, data| env.to_js_value(&data),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    // A sandbox with a file inside and a sibling directory whose name starts with the sandbox's
+    fn sandbox(test: &str) -> (PathBuf, PathBuf) {
+        let root = std::env::temp_dir().join(format!(
+            "plunger-semihosting-{}-{}",
+            test,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("sandbox/data")).unwrap();
+        fs::create_dir_all(root.join("sandbox-evil")).unwrap();
+        fs::write(root.join("sandbox/data/input.bin"), b"in").unwrap();
+        fs::write(root.join("sandbox-evil/secret"), b"out").unwrap();
+
+        let root = root.canonicalize().unwrap();
+        (root.join("sandbox"), root)
+    }
+
+    #[test]
+    fn opens_relative_paths_inside_the_sandbox() {
+        let (sandbox, root) = sandbox("inside");
+
+        assert_eq!(
+            sandbox_path(&sandbox, "data/input.bin"),
+            Some(sandbox.join("data/input.bin"))
+        );
+        assert_eq!(
+            sandbox_path(&sandbox, "./data/./input.bin"),
+            Some(sandbox.join("data/input.bin"))
+        );
+        assert_eq!(sandbox_path(&sandbox, "data/missing.bin"), None);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn refuses_paths_leaving_the_sandbox() {
+        let (sandbox, root) = sandbox("outside");
+        let secret = root.join("sandbox-evil/secret");
+
+        assert_eq!(sandbox_path(&sandbox, "../sandbox-evil/secret"), None);
+        assert_eq!(
+            sandbox_path(&sandbox, "data/../../sandbox-evil/secret"),
+            None
+        );
+        assert_eq!(sandbox_path(&sandbox, "data/../input.bin"), None);
+        assert_eq!(sandbox_path(&sandbox, secret.to_str().unwrap()), None);
+        assert_eq!(sandbox_path(&sandbox, "/etc/passwd"), None);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn follows_symlinks_only_within_the_sandbox() {
+        use std::os::unix::fs::symlink;
+
+        let (sandbox, root) = sandbox("symlink");
+        symlink(root.join("sandbox-evil/secret"), sandbox.join("escape")).unwrap();
+        symlink(root.join("sandbox-evil"), sandbox.join("evil")).unwrap();
+        symlink(sandbox.join("data/input.bin"), sandbox.join("alias")).unwrap();
+
+        assert_eq!(sandbox_path(&sandbox, "escape"), None);
+        assert_eq!(sandbox_path(&sandbox, "evil/secret"), None);
+        assert_eq!(
+            sandbox_path(&sandbox, "alias"),
+            Some(sandbox.join("data/input.bin"))
+        );
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+use napi::{CallContext, JsNumber, JsObject, JsString, JsUnknown, Task};
+use serde::Deserialize;
+
+use crate::{
+    common::{
+        cancel_token::CancelToken,
+        connect_options::ConnectOptions,
+        probe_selector::{probe_selector_from_args, ProbeSelector},
+    },
+    svd::device::Device,
+    symbols::elf_resolver::ElfResolver,
+};
+
+use super::debug_session::{close_session, open_session, SessionInfo};
+
+const DEFAULT_OPEN_TIMEOUT_MS: u64 = 10_000;
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionOptions {
+    cancel_token: Option<u32>,
+    timeout_ms: Option<u64>,
+    /// Firmware running on the target, lets memory, breakpoint and RTT calls take symbol names
+    elf_path: Option<String>,
+    /// CMSIS-SVD file of the target, for readPeripheral and readRegisterField
+    svd_path: Option<String>,
+}
+
+pub struct OpenSessionTask {
+    selector: ProbeSelector,
+    target_name: String,
+    connect: ConnectOptions,
+    elf_path: Option<String>,
+    svd_path: Option<String>,
+    cancel: CancelToken,
+}
+
+impl Task for OpenSessionTask {
+    type Output = SessionInfo;
+    type JsValue = JsUnknown;
+
+    fn compute(&mut self) -> napi::Result<Self::Output> {
+        let symbols = self
+            .elf_path
+            .as_deref()
+            .map(ElfResolver::load)
+            .transpose()?;
+        let svd = self.svd_path.as_deref().map(Device::load).transpose()?;
+
+        Ok(open_session(
+            self.selector.resolve()?,
+            self.target_name.clone(),
+            self.connect.clone(),
+            symbols,
+            svd,
+            self.cancel.clone(),
+        )?)
+    }
+
+    fn resolve(self, env: napi::Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+        env.to_js_value(&output)
+    }
+
+    fn reject(self, _env: napi::Env, err: napi::Error) -> napi::Result<Self::JsValue> {
+        Err(err)
+    }
+}
+
+pub struct CloseSessionTask {
+    id: u32,
+}
+
+impl Task for CloseSessionTask {
+    type Output = bool;
+    type JsValue = JsUnknown;
+
+    fn compute(&mut self) -> napi::Result<Self::Output> {
+        Ok(close_session(self.id)?)
+    }
+
+    fn resolve(self, env: napi::Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+        env.to_js_value(&output)
+    }
+
+    fn reject(self, _env: napi::Env, err: napi::Error) -> napi::Result<Self::JsValue> {
+        Err(err)
+    }
+}
+
+#[js_function(5)]
+pub fn open_debug_session(ctx: CallContext) -> napi::Result<JsObject> {
+    let target_name = ctx.get::<JsString>(0)?.into_utf8()?.as_str()?.to_string();
+    let selector = probe_selector_from_args(&ctx, 1, 2, 3)?;
+    let options: SessionOptions = match ctx.try_get::<JsObject>(4)? {
+        napi::Either::A(obj) => ctx.env.from_js_value(obj)?,
+        napi::Either::B(_) => SessionOptions::default(),
+    };
+    let connect = ConnectOptions::from_arg(&ctx, 4)?;
+    let cancel = CancelToken::from_id(options.cancel_token)?.with_timeout(Duration::from_millis(
+        options.timeout_ms.unwrap_or(DEFAULT_OPEN_TIMEOUT_MS),
+    ));
+
+    let task = OpenSessionTask {
+        selector,
+        target_name,
+        connect,
+        elf_path: options.elf_path,
+        svd_path: options.svd_path,
+        cancel,
+    };
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
+
+#[js_function(1)]
+pub fn close_debug_session(ctx: CallContext) -> napi::Result<JsObject> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let task = CloseSessionTask { id };
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
@@ -0,0 +1,213 @@
+use napi::{
+    threadsafe_function::{ThreadSafeCallContext, ThreadsafeFunction, ThreadsafeFunctionCallMode},
+    CallContext, JsFunction, JsNumber, JsObject,
+};
+use probe_rs::{
+    architecture::arm::{SwoConfig, SwoMode},
+    MemoryInterface, Session,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::common::plunger_error::PlungerError;
+
+use super::{
+    debug_session::SessionTask,
+    itm::{ItmDecoder, ItmPacket},
+};
+
+// Trace enable register, one bit per stimulus port
+const ITM_TER: u32 = 0xe000_0e00;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SwoEncoding {
+    Uart,
+    Manchester,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SwoOptions {
+    baud: u32,
+    /// Clock feeding the TPIU, which is the core clock on most parts
+    cpu_hz: u32,
+    encoding: Option<SwoEncoding>,
+    /// Stimulus ports to enable, all of them when not given
+    ports: Option<Vec<u8>>,
+    /// Report timestamp and DWT packets as well as stimulus port data
+    raw_packets: Option<bool>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SwoInfo {
+    baud: u32,
+    cpu_hz: u32,
+    port_mask: u32,
+}
+
+/// Stimulus data gathered over one poll, or a packet passed through as is
+pub enum SwoEvent {
+    Stimulus { port: u8, data: Vec<u8> },
+    Packet(ItmPacket),
+}
+
+pub struct SwoLink {
+    decoder: ItmDecoder,
+    raw_packets: bool,
+    callback: ThreadsafeFunction<SwoEvent>,
+}
+
+fn port_mask(ports: &Option<Vec<u8>>) -> Result<u32, PlungerError> {
+    let ports = match ports {
+        Some(ports) => ports,
+        None => return Ok(u32::MAX),
+    };
+
+    ports.iter().try_fold(0u32, |mask, port| match port {
+        0..=31 => Ok(mask | (1 << port)),
+        _ => Err(PlungerError::InvalidOption(format!(
+            "ITM stimulus port {} does not exist, ports go from 0 to 31",
+            port
+        ))),
+    })
+}
+
+impl SwoLink {
+    pub fn start(
+        session: &mut Session,
+        options: &SwoOptions,
+        callback: ThreadsafeFunction<SwoEvent>,
+    ) -> Result<(SwoLink, SwoInfo), PlungerError> {
+        if options.baud == 0 || options.baud > options.cpu_hz {
+            return Err(PlungerError::InvalidOption(format!(
+                "SWO baud rate {} cannot be derived from a {} Hz clock",
+                options.baud, options.cpu_hz
+            )));
+        }
+        let port_mask = port_mask(&options.ports)?;
+
+        let mode = match options.encoding {
+            Some(SwoEncoding::Manchester) => SwoMode::Manchester,
+            _ => SwoMode::Uart,
+        };
+        let config = SwoConfig::new(options.cpu_hz)
+            .set_baud(options.baud)
+            .set_mode(mode);
+
+        // Sets up the probe's receiver as well as the TPIU, ITM and DWT on the target
+        session.setup_swv(&config)?;
+        if port_mask != u32::MAX {
+            session.core(0)?.write_word_32(ITM_TER, port_mask)?;
+        }
+
+        let link = SwoLink {
+            decoder: ItmDecoder::new(),
+            raw_packets: options.raw_packets.unwrap_or(false),
+            callback,
+        };
+        let info = SwoInfo {
+            baud: options.baud,
+            cpu_hz: options.cpu_hz,
+            port_mask,
+        };
+
+        Ok((link, info))
+    }
+
+    /// Decodes whatever the probe has buffered and hands it to the JS callback
+    pub fn poll(&mut self, session: &mut Session) -> Result<(), PlungerError> {
+        let data = session.read_swo()?;
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        // Stimulus writes are merged per port so a printf arrives as one chunk
+        let mut stimulus: Vec<(u8, Vec<u8>)> = Vec::new();
+        for packet in self.decoder.feed(&data) {
+            match packet {
+                ItmPacket::Instrumentation { port, value, size } => {
+                    let bytes = &value.to_le_bytes()[..size as usize];
+                    match stimulus.iter_mut().find(|(existing, _)| *existing == port) {
+                        Some((_, data)) => data.extend_from_slice(bytes),
+                        None => stimulus.push((port, bytes.to_vec())),
+                    }
+                }
+                ItmPacket::Overflow => self.emit(SwoEvent::Packet(ItmPacket::Overflow)),
+                packet if self.raw_packets => self.emit(SwoEvent::Packet(packet)),
+                _ => (),
+            }
+        }
+
+        for (port, data) in stimulus {
+            self.emit(SwoEvent::Stimulus { port, data });
+        }
+
+        Ok(())
+    }
+
+    fn emit(&self, event: SwoEvent) {
+        self.callback
+            .call(Ok(event), ThreadsafeFunctionCallMode::NonBlocking);
+    }
+
+    pub fn stop(self, session: &mut Session) -> Result<(), PlungerError> {
+        session.disable_swv()?;
+        Ok(())
+    }
+
+    pub fn report_error(&self, err: PlungerError) {
+        self.callback
+            .call(Err(err.into()), ThreadsafeFunctionCallMode::NonBlocking);
+    }
+}
+
+#[js_function(3)]
+pub fn start_swo(ctx: CallContext) -> napi::Result<JsObject> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let options: SwoOptions = ctx.env.from_js_value(ctx.get::<JsObject>(1)?)?;
+    let func = ctx.get::<JsFunction>(2)?;
+    let callback =
+        ctx.env
+            .create_threadsafe_function(&func, 0, |ctx: ThreadSafeCallContext<SwoEvent>| {
+                let event = match ctx.value {
+                    SwoEvent::Stimulus { port, data } => {
+                        let mut event = ctx.env.create_object()?;
+                        event.set_named_property("kind", ctx.env.create_string("stimulus")?)?;
+                        event.set_named_property("port", ctx.env.create_uint32(port as u32)?)?;
+                        event.set_named_property(
+                            "text",
+                            ctx.env.create_string(&String::from_utf8_lossy(&data))?,
+                        )?;
+                        event.set_named_property(
+                            "data",
+                            ctx.env.create_buffer_with_data(data)?.into_raw(),
+                        )?;
+                        event.into_unknown()
+                    }
+                    SwoEvent::Packet(packet) => ctx.env.to_js_value(&packet)?,
+                };
+                Ok(vec![event])
+            })?;
+
+    let task = SessionTask::new(id, move |session| {
+        if let Some(previous) = session.swo.take() {
+            previous.stop(&mut session.session)?;
+        }
+
+        let (link, info) = SwoLink::start(&mut session.session, &options, callback)?;
+        session.swo = Some(link);
+        Ok(info)
+    });
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
+
+#[js_function(1)]
+pub fn stop_swo(ctx: CallContext) -> napi::Result<JsObject> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let task = SessionTask::new(id, |session| match session.swo.take() {
+        Some(link) => link.stop(&mut session.session).map(|_| true),
+        None => Ok(false),
+    });
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
@@ -0,0 +1,863 @@
+use std::convert::TryFrom;
+
+use probe_rs::{Core, MemoryInterface};
+use serde::Serialize;
+
+use crate::common::plunger_error::PlungerError;
+
+use super::xml::{self, Element};
+
+// Far beyond any real array, it keeps a bogus dim from expanding into millions of registers
+const MAX_DIM: u32 = 1024;
+// Nested cluster arrays multiply, this bounds what one peripheral can expand into
+const MAX_REGISTERS: usize = 64 * 1024;
+
+/// Peripherals and registers of a CMSIS-SVD file, flattened: clusters become dotted
+/// register names and `dim` arrays are expanded
+#[derive(Debug, Clone)]
+pub struct Device {
+    pub name: String,
+    pub peripherals: Vec<Peripheral>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Peripheral {
+    pub name: String,
+    pub description: Option<String>,
+    pub base_address: u32,
+    pub registers: Vec<Register>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Register {
+    pub name: String,
+    pub description: Option<String>,
+    pub address_offset: u32,
+    /// In bits
+    pub size: u32,
+    pub readable: bool,
+    /// Reading clears or otherwise changes something, e.g. a status register with readAction
+    pub read_side_effects: bool,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub name: String,
+    pub description: Option<String>,
+    pub bit_offset: u32,
+    pub bit_width: u32,
+    pub values: Vec<EnumeratedValue>,
+}
+
+#[derive(Debug, Clone)]
+pub struct EnumeratedValue {
+    pub name: String,
+    pub description: Option<String>,
+    pub value: u32,
+    /// Bits that have to match, `#1x0` style values leave some out
+    pub mask: u32,
+    /// Matches whatever no other value of the field does
+    pub is_default: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EnumeratedInfo {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldValue {
+    pub name: String,
+    pub description: Option<String>,
+    pub bit_offset: u32,
+    pub bit_width: u32,
+    pub value: u32,
+    pub enumerated: Option<EnumeratedInfo>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterValue {
+    pub name: String,
+    pub description: Option<String>,
+    pub address: u32,
+    /// Missing for write-only registers and ones that change when read
+    pub value: Option<u32>,
+    pub fields: Vec<FieldValue>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PeripheralValue {
+    pub name: String,
+    pub description: Option<String>,
+    pub base_address: u32,
+    pub registers: Vec<RegisterValue>,
+}
+
+impl std::fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}=0x{:x}", self.name, self.value)?;
+        match &self.enumerated {
+            Some(enumerated) => write!(f, " ({})", enumerated.name),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::fmt::Display for RegisterValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = match self.value {
+            Some(value) => value,
+            None => return write!(f, "{} not read", self.name),
+        };
+
+        write!(f, "{} = 0x{:08x}", self.name, value)?;
+        for (idx, field) in self.fields.iter().enumerate() {
+            let separator = if idx == 0 { ": " } else { ", " };
+            write!(f, "{}{}", separator, field)?;
+        }
+        Ok(())
+    }
+}
+
+fn invalid(message: String) -> PlungerError {
+    PlungerError::InvalidSvd(message)
+}
+
+/// SVD numbers: decimal, 0x hex, 0b or # binary, where x in binary means "don't care".
+/// Returns the value and the mask of bits that are not "don't care"
+fn parse_masked(text: &str) -> Option<(u64, u64)> {
+    let text = text.trim();
+    let (digits, radix) =
+        if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+            (hex, 16)
+        } else if let Some(binary) = text
+            .strip_prefix('#')
+            .or_else(|| text.strip_prefix("0b"))
+            .or_else(|| text.strip_prefix("0B"))
+        {
+            (binary, 2)
+        } else {
+            (text, 10)
+        };
+
+    if radix != 2 {
+        return u64::from_str_radix(digits, radix)
+            .ok()
+            .map(|value| (value, u64::MAX));
+    }
+
+    let mut value = 0u64;
+    let mut mask = 0u64;
+    for digit in digits.chars() {
+        value <<= 1;
+        mask <<= 1;
+        match digit {
+            '0' => mask |= 1,
+            '1' => {
+                value |= 1;
+                mask |= 1
+            }
+            'x' | 'X' => (),
+            _ => return None,
+        }
+    }
+    match digits.is_empty() {
+        true => None,
+        false => Some((value, mask)),
+    }
+}
+
+fn parse_number(text: &str) -> Option<u64> {
+    parse_masked(text).map(|(value, _)| value)
+}
+
+fn number(element: &Element, name: &str) -> Result<Option<u64>, PlungerError> {
+    match element.child_text(name) {
+        Some(text) => parse_number(text).map(Some).ok_or_else(|| {
+            invalid(format!(
+                "<{}> of {} is not a number: {}",
+                name,
+                element.child_text("name").unwrap_or(&element.name),
+                text
+            ))
+        }),
+        None => Ok(None),
+    }
+}
+
+// Sizes, offsets and addresses are 32 bit, a larger number in the file is an error, not truncated
+fn number32(element: &Element, name: &str) -> Result<Option<u32>, PlungerError> {
+    number(element, name)?
+        .map(|value| {
+            u32::try_from(value).map_err(|_| {
+                invalid(format!(
+                    "<{}> of {} does not fit 32 bits: 0x{:x}",
+                    name,
+                    element.child_text("name").unwrap_or(&element.name),
+                    value
+                ))
+            })
+        })
+        .transpose()
+}
+
+fn add_offset(base: u32, offset: u32, name: &str) -> Result<u32, PlungerError> {
+    base.checked_add(offset).ok_or_else(|| {
+        invalid(format!(
+            "Address of {} overflows: 0x{:08x} + 0x{:x}",
+            name, base, offset
+        ))
+    })
+}
+
+fn required_name(element: &Element) -> Result<String, PlungerError> {
+    element
+        .child_text("name")
+        .map(|name| name.to_string())
+        .ok_or_else(|| invalid(format!("<{}> without a <name>", element.name)))
+}
+
+fn description(element: &Element) -> Option<String> {
+    // Descriptions are often wrapped over several lines in the file
+    element
+        .child_text("description")
+        .map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+// Size and access are inherited from the device, peripheral and cluster down to registers
+#[derive(Debug, Clone, Copy)]
+struct Defaults {
+    size: u32,
+    readable: bool,
+}
+
+impl Defaults {
+    fn apply(self, element: &Element) -> Result<Defaults, PlungerError> {
+        Ok(Defaults {
+            size: number32(element, "size")?.unwrap_or(self.size),
+            readable: match element.child_text("access") {
+                Some(access) => !matches!(access, "write-only" | "writeOnce"),
+                None => self.readable,
+            },
+        })
+    }
+}
+
+/// Names and address offsets an element stands for, more than one if it has `dim`
+fn expand(element: &Element, name: String) -> Result<Vec<(String, u32)>, PlungerError> {
+    let dim = match number(element, "dim")? {
+        Some(dim) if dim <= MAX_DIM as u64 => dim as u32,
+        Some(dim) => {
+            return Err(invalid(format!(
+                "dim {} of {} is over the limit of {}",
+                dim, name, MAX_DIM
+            )))
+        }
+        None => return Ok(vec![(name, 0)]),
+    };
+    let increment = number32(element, "dimIncrement")?.unwrap_or(0);
+
+    let indices: Vec<String> = match element.child_text("dimIndex") {
+        Some(list) if list.contains(',') => list.split(',').map(|s| s.trim().to_string()).collect(),
+        Some(range) if range.contains('-') => {
+            let mut bounds = range.split('-').map(|bound| bound.trim().parse::<u32>());
+            match (bounds.next(), bounds.next()) {
+                (Some(Ok(first)), Some(Ok(last))) => {
+                    (first..=last).map(|idx| idx.to_string()).collect()
+                }
+                _ => return Err(invalid(format!("Bad dimIndex {} of {}", range, name))),
+            }
+        }
+        Some(single) => vec![single.to_string()],
+        None => (0..dim).map(|idx| idx.to_string()).collect(),
+    };
+
+    indices
+        .iter()
+        .take(dim as usize)
+        .enumerate()
+        .map(|(idx, index)| {
+            let offset = (idx as u32).checked_mul(increment).ok_or_else(|| {
+                invalid(format!(
+                    "dimIncrement 0x{:x} of {} overflows",
+                    increment, name
+                ))
+            })?;
+            Ok((name.replace("%s", index), offset))
+        })
+        .collect()
+}
+
+fn parse_values(field: &Element, bit_width: u32) -> Result<Vec<EnumeratedValue>, PlungerError> {
+    let field_mask = match bit_width {
+        32 => u32::MAX,
+        width => (1 << width) - 1,
+    };
+
+    let mut values = Vec::new();
+    for list in field.children_named("enumeratedValues") {
+        // Values only meant for writing do not help decoding what was read
+        if list.child_text("usage") == Some("write") {
+            continue;
+        }
+
+        for value in list.children_named("enumeratedValue") {
+            let name = required_name(value)?;
+            let is_default = value.child_text("isDefault") == Some("true");
+            let (number, mask) = match value.child_text("value") {
+                Some(text) => parse_masked(text).ok_or_else(|| {
+                    invalid(format!("Value {} of {} is not a number", text, name))
+                })?,
+                None if is_default => (0, 0),
+                None => continue,
+            };
+
+            values.push(EnumeratedValue {
+                name,
+                description: description(value),
+                value: number as u32 & field_mask,
+                mask: mask as u32 & field_mask,
+                is_default,
+            });
+        }
+    }
+
+    Ok(values)
+}
+
+fn parse_field(field: &Element) -> Result<Field, PlungerError> {
+    let name = required_name(field)?;
+
+    // Kept in u64 until checked, a bogus position in the file must not wrap around
+    let (bit_offset, bit_width) = if let Some(offset) = number(field, "bitOffset")? {
+        (offset, number(field, "bitWidth")?.unwrap_or(1))
+    } else if let Some(lsb) = number(field, "lsb")? {
+        let msb = number(field, "msb")?.unwrap_or(lsb);
+        match msb.checked_sub(lsb) {
+            Some(span) => (lsb, span.saturating_add(1)),
+            None => return Err(invalid(format!("msb of {} is below its lsb", name))),
+        }
+    } else if let Some(range) = field.child_text("bitRange") {
+        // [msb:lsb]
+        let bounds: Vec<Option<u64>> = range
+            .trim_matches(|c| c == '[' || c == ']')
+            .split(':')
+            .map(parse_number)
+            .collect();
+        match bounds.as_slice() {
+            [Some(msb), Some(lsb)] if msb >= lsb => (*lsb, (msb - lsb).saturating_add(1)),
+            _ => return Err(invalid(format!("Bad bitRange {} of {}", range, name))),
+        }
+    } else {
+        return Err(invalid(format!("Field {} has no bit position", name)));
+    };
+
+    if bit_width == 0 || !matches!(bit_offset.checked_add(bit_width), Some(end) if end <= 32) {
+        return Err(invalid(format!(
+            "Field {} does not fit a 32 bit register",
+            name
+        )));
+    }
+    let (bit_offset, bit_width) = (bit_offset as u32, bit_width as u32);
+
+    Ok(Field {
+        values: parse_values(field, bit_width)?,
+        name,
+        description: description(field),
+        bit_offset,
+        bit_width,
+    })
+}
+
+fn parse_register(
+    element: &Element,
+    defaults: Defaults,
+    prefix: &str,
+    base_offset: u32,
+) -> Result<Vec<Register>, PlungerError> {
+    let name = format!("{}{}", prefix, required_name(element)?);
+    let defaults = defaults.apply(element)?;
+    let address_offset = add_offset(
+        base_offset,
+        number32(element, "addressOffset")?.unwrap_or(0),
+        &name,
+    )?;
+
+    let fields = match element.child("fields") {
+        Some(fields) => fields
+            .children_named("field")
+            .map(parse_field)
+            .collect::<Result<Vec<Field>, PlungerError>>()?,
+        None => Vec::new(),
+    };
+
+    expand(element, name)?
+        .into_iter()
+        .map(|(name, offset)| {
+            Ok(Register {
+                address_offset: add_offset(address_offset, offset, &name)?,
+                name,
+                description: description(element),
+                size: defaults.size,
+                readable: defaults.readable,
+                read_side_effects: element.child("readAction").is_some(),
+                fields: fields.clone(),
+            })
+        })
+        .collect()
+}
+
+// Registers of a <registers> or <cluster> element, cluster members get "CLUSTER." in front
+fn parse_registers(
+    element: &Element,
+    defaults: Defaults,
+    prefix: &str,
+    base_offset: u32,
+) -> Result<Vec<Register>, PlungerError> {
+    let mut registers = Vec::new();
+    for child in element.children.iter() {
+        match child.name.as_str() {
+            "register" => registers.extend(parse_register(child, defaults, prefix, base_offset)?),
+            "cluster" => {
+                let cluster_defaults = defaults.apply(child)?;
+                let name = required_name(child)?;
+                let offset = add_offset(
+                    base_offset,
+                    number32(child, "addressOffset")?.unwrap_or(0),
+                    &name,
+                )?;
+                for (name, step) in expand(child, name)? {
+                    registers.extend(parse_registers(
+                        child,
+                        cluster_defaults,
+                        &format!("{}{}.", prefix, name),
+                        add_offset(offset, step, &name)?,
+                    )?);
+                }
+            }
+            _ => (),
+        }
+
+        if registers.len() > MAX_REGISTERS {
+            return Err(invalid(format!(
+                "{} expands into more than {} registers",
+                element.child_text("name").unwrap_or(&element.name),
+                MAX_REGISTERS
+            )));
+        }
+    }
+
+    Ok(registers)
+}
+
+impl Device {
+    pub fn load(path: &str) -> Result<Device, PlungerError> {
+        let content = std::fs::read_to_string(path).map_err(|err| {
+            PlungerError::InvalidOption(format!("Cannot read SVD file {}: {}", path, err))
+        })?;
+
+        Device::parse(&content).map_err(|err| match err {
+            PlungerError::InvalidSvd(message) => invalid(format!("{}: {}", path, message)),
+            other => other,
+        })
+    }
+
+    pub fn parse(content: &str) -> Result<Device, PlungerError> {
+        let root = xml::parse(content)?;
+        if root.name != "device" {
+            return Err(invalid(format!(
+                "Root element is <{}>, not <device>",
+                root.name
+            )));
+        }
+
+        let defaults = Defaults {
+            size: 32,
+            readable: true,
+        }
+        .apply(&root)?;
+
+        let elements: Vec<&Element> = root
+            .child("peripherals")
+            .map(|peripherals| peripherals.children_named("peripheral").collect())
+            .unwrap_or_default();
+
+        let mut peripherals = Vec::new();
+        for element in elements.iter() {
+            // Derived peripherals, e.g. USART2 from USART1, only differ in address and name
+            let source = match element.attribute("derivedFrom") {
+                Some(base) if element.child("registers").is_none() => elements
+                    .iter()
+                    .find(|other| other.child_text("name") == Some(base))
+                    .ok_or_else(|| {
+                        invalid(format!("derivedFrom names unknown peripheral {}", base))
+                    })?,
+                _ => element,
+            };
+
+            let peripheral_defaults = defaults.apply(source)?.apply(element)?;
+            let registers = match source.child("registers") {
+                Some(registers) => parse_registers(registers, peripheral_defaults, "", 0)?,
+                None => Vec::new(),
+            };
+            let base_address = number32(element, "baseAddress")?.ok_or_else(|| {
+                invalid(format!(
+                    "Peripheral {} has no baseAddress",
+                    element.child_text("name").unwrap_or_default()
+                ))
+            })?;
+
+            for (name, offset) in expand(element, required_name(element)?)? {
+                peripherals.push(Peripheral {
+                    base_address: add_offset(base_address, offset, &name)?,
+                    name,
+                    description: description(element).or_else(|| description(source)),
+                    registers: registers.clone(),
+                });
+            }
+        }
+
+        Ok(Device {
+            name: root.child_text("name").unwrap_or_default().to_string(),
+            peripherals,
+        })
+    }
+
+    pub fn peripheral(&self, name: &str) -> Result<&Peripheral, PlungerError> {
+        self.peripherals
+            .iter()
+            .find(|peripheral| peripheral.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                PlungerError::InvalidOption(format!("No peripheral {} in {}", name, self.name))
+            })
+    }
+}
+
+impl Peripheral {
+    pub fn register(&self, name: &str) -> Result<&Register, PlungerError> {
+        self.registers
+            .iter()
+            .find(|register| register.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                PlungerError::InvalidOption(format!("No register {}.{}", self.name, name))
+            })
+    }
+
+    /// Reads every register that can be read without side effects
+    pub fn read(&self, core: &mut Core) -> Result<PeripheralValue, PlungerError> {
+        let registers = self
+            .registers
+            .iter()
+            .map(|register| {
+                let value = match register.readable && !register.read_side_effects {
+                    true => Some(register.read(core, self.base_address)?),
+                    false => None,
+                };
+                register.decode(self.base_address, value)
+            })
+            .collect::<Result<Vec<RegisterValue>, PlungerError>>()?;
+
+        Ok(PeripheralValue {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            base_address: self.base_address,
+            registers,
+        })
+    }
+}
+
+impl Register {
+    pub fn field(&self, name: &str) -> Result<&Field, PlungerError> {
+        self.fields
+            .iter()
+            .find(|field| field.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                PlungerError::InvalidOption(format!("No field {} in {}", name, self.name))
+            })
+    }
+
+    pub fn address(&self, base_address: u32) -> Result<u32, PlungerError> {
+        add_offset(base_address, self.address_offset, &self.name)
+    }
+
+    pub fn read(&self, core: &mut Core, base_address: u32) -> Result<u32, PlungerError> {
+        let address = self.address(base_address)?;
+        Ok(match self.size {
+            8 => core.read_word_8(address)? as u32,
+            // No halfword accesses in probe-rs, take the halfword out of its word
+            16 => (core.read_word_32(address & !3)? >> ((address & 2) * 8)) & 0xffff,
+            _ => core.read_word_32(address)?,
+        })
+    }
+
+    /// Splits `value` into its fields, named by their enumerated values where there are some
+    pub fn decode(
+        &self,
+        base_address: u32,
+        value: Option<u32>,
+    ) -> Result<RegisterValue, PlungerError> {
+        Ok(RegisterValue {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            address: self.address(base_address)?,
+            value,
+            fields: match value {
+                Some(value) => self
+                    .fields
+                    .iter()
+                    .map(|field| field.decode(value))
+                    .collect(),
+                None => Vec::new(),
+            },
+        })
+    }
+}
+
+impl Field {
+    pub fn decode(&self, register_value: u32) -> FieldValue {
+        let mask = match self.bit_width {
+            32 => u32::MAX,
+            width => (1 << width) - 1,
+        };
+        let value = (register_value >> self.bit_offset) & mask;
+
+        let enumerated = self
+            .values
+            .iter()
+            .find(|known| !known.is_default && value & known.mask == known.value & known.mask)
+            .or_else(|| self.values.iter().find(|known| known.is_default))
+            .map(|known| EnumeratedInfo {
+                name: known.name.clone(),
+                description: known.description.clone(),
+            });
+
+        FieldValue {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            bit_offset: self.bit_offset,
+            bit_width: self.bit_width,
+            value,
+            enumerated,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(peripherals: &str) -> Device {
+        Device::parse(&format!(
+            "<?xml version=\"1.0\"?>\n<device><name>TEST</name><peripherals>{}</peripherals></device>",
+            peripherals
+        ))
+        .unwrap()
+    }
+
+    fn field(body: &str) -> Result<Field, PlungerError> {
+        parse_field(&xml::parse(&format!(
+            "<field><name>F</name>{}</field>",
+            body
+        ))?)
+    }
+
+    #[test]
+    fn derived_peripherals_share_registers() {
+        let device = device(
+            "<peripheral><name>USART1</name><baseAddress>0x40013800</baseAddress>
+               <registers><register><name>CR1</name><addressOffset>0x0</addressOffset></register></registers>
+             </peripheral>
+             <peripheral derivedFrom=\"USART1\"><name>USART2</name><baseAddress>0x40004400</baseAddress></peripheral>",
+        );
+
+        let usart2 = device.peripheral("usart2").unwrap();
+        assert_eq!(usart2.base_address, 0x4000_4400);
+        assert_eq!(usart2.register("CR1").unwrap().address_offset, 0);
+        assert!(Device::parse(
+            "<device><peripherals><peripheral derivedFrom=\"NONE\"><name>P</name><baseAddress>0</baseAddress></peripheral></peripherals></device>"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn expands_dim_arrays_and_clusters() {
+        let device = device(
+            "<peripheral><name>DMA</name><baseAddress>0x40020000</baseAddress><registers>
+               <register><dim>3</dim><dimIncrement>4</dimIncrement><dimIndex>A,B,C</dimIndex>
+                 <name>ISR%s</name><addressOffset>0x0</addressOffset></register>
+               <register><dim>2</dim><dimIncrement>4</dimIncrement><dimIndex>1-2</dimIndex>
+                 <name>IFCR%s</name><addressOffset>0x10</addressOffset></register>
+               <cluster><dim>2</dim><dimIncrement>0x14</dimIncrement><name>CH%s</name>
+                 <addressOffset>0x20</addressOffset>
+                 <register><name>CCR</name><addressOffset>0x4</addressOffset><size>16</size></register>
+               </cluster>
+             </registers></peripheral>",
+        );
+
+        let dma = device.peripheral("DMA").unwrap();
+        let layout: Vec<(&str, u32, u32)> = dma
+            .registers
+            .iter()
+            .map(|register| {
+                (
+                    register.name.as_str(),
+                    register.address_offset,
+                    register.size,
+                )
+            })
+            .collect();
+        assert_eq!(
+            layout,
+            vec![
+                ("ISRA", 0x0, 32),
+                ("ISRB", 0x4, 32),
+                ("ISRC", 0x8, 32),
+                ("IFCR1", 0x10, 32),
+                ("IFCR2", 0x14, 32),
+                ("CH0.CCR", 0x24, 16),
+                ("CH1.CCR", 0x38, 16),
+            ]
+        );
+    }
+
+    #[test]
+    fn matches_enumerated_values_with_dont_care_bits() {
+        let field = field(
+            "<bitRange>[11:8]</bitRange><enumeratedValues>
+               <enumeratedValue><name>LOW</name><value>#0xxx</value></enumeratedValue>
+               <enumeratedValue><name>TOP</name><value>0xf</value></enumeratedValue>
+               <enumeratedValue><name>OTHER</name><isDefault>true</isDefault></enumeratedValue>
+             </enumeratedValues>",
+        )
+        .unwrap();
+        assert_eq!((field.bit_offset, field.bit_width), (8, 4));
+
+        let name = |value: u32| field.decode(value).enumerated.map(|known| known.name);
+        assert_eq!(name(0x500), Some("LOW".to_string()));
+        assert_eq!(name(0xf00), Some("TOP".to_string()));
+        assert_eq!(name(0x900), Some("OTHER".to_string()));
+    }
+
+    #[test]
+    fn rejects_fields_outside_the_register() {
+        assert!(field("<lsb>4</lsb><msb>2</msb>").is_err());
+        assert!(field("<bitOffset>30</bitOffset><bitWidth>4</bitWidth>").is_err());
+        assert!(field("<bitOffset>0xffffffff</bitOffset><bitWidth>2</bitWidth>").is_err());
+        assert!(field("<bitOffset>0xffffffffffffffff</bitOffset><bitWidth>2</bitWidth>").is_err());
+        assert!(field("<bitRange>[0xffffffffffffffff:0]</bitRange>").is_err());
+        assert!(field("<bitOffset>4</bitOffset><bitWidth>0</bitWidth>").is_err());
+
+        let field = field("<lsb>28</lsb><msb>31</msb>").unwrap();
+        assert_eq!((field.bit_offset, field.bit_width), (28, 4));
+    }
+
+    fn parse_peripherals(peripherals: &str) -> Result<Device, PlungerError> {
+        Device::parse(&format!(
+            "<device><name>TEST</name><peripherals>{}</peripherals></device>",
+            peripherals
+        ))
+    }
+
+    #[test]
+    fn rejects_numbers_wider_than_32_bits() {
+        let cases = [
+            "<peripheral><name>P</name><baseAddress>0x100000000</baseAddress></peripheral>",
+            "<peripheral><name>P</name><baseAddress>0</baseAddress><registers>
+               <register><name>R</name><addressOffset>0x100000000</addressOffset></register>
+             </registers></peripheral>",
+            "<peripheral><name>P</name><baseAddress>0</baseAddress><registers>
+               <register><name>R</name><size>0x100000020</size></register>
+             </registers></peripheral>",
+        ];
+        for case in cases.iter() {
+            assert!(
+                matches!(parse_peripherals(case), Err(PlungerError::InvalidSvd(_))),
+                "{}",
+                case
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_offsets_that_overflow() {
+        let cases = [
+            // Cluster offset plus register offset
+            "<peripheral><name>P</name><baseAddress>0</baseAddress><registers>
+               <cluster><name>C</name><addressOffset>0xfffffff0</addressOffset>
+                 <register><name>R</name><addressOffset>0x20</addressOffset></register>
+               </cluster>
+             </registers></peripheral>",
+            // dim steps past the end of the address space
+            "<peripheral><name>P</name><baseAddress>0</baseAddress><registers>
+               <register><dim>4</dim><dimIncrement>0x80000000</dimIncrement><name>R%s</name></register>
+             </registers></peripheral>",
+            "<peripheral><name>P</name><baseAddress>0</baseAddress><registers>
+               <register><dim>2</dim><dimIncrement>0x10</dimIncrement><name>R%s</name>
+                 <addressOffset>0xfffffff8</addressOffset></register>
+             </registers></peripheral>",
+            "<peripheral><dim>2</dim><dimIncrement>0x1000</dimIncrement><name>P%s</name>
+               <baseAddress>0xfffff800</baseAddress></peripheral>",
+        ];
+        for case in cases.iter() {
+            assert!(
+                matches!(parse_peripherals(case), Err(PlungerError::InvalidSvd(_))),
+                "{}",
+                case
+            );
+        }
+    }
+
+    #[test]
+    fn register_address_overflow_is_an_error() {
+        let device = device(
+            "<peripheral><name>P</name><baseAddress>0xfffffff0</baseAddress><registers>
+               <register><name>R</name><addressOffset>0x20</addressOffset></register>
+             </registers></peripheral>",
+        );
+        let peripheral = device.peripheral("P").unwrap();
+        let register = peripheral.register("R").unwrap();
+        assert!(register.address(peripheral.base_address).is_err());
+        assert!(register.decode(peripheral.base_address, Some(0)).is_err());
+    }
+
+    #[test]
+    fn caps_dim() {
+        let array = |dim: u32| {
+            format!(
+                "<peripheral><name>P</name><baseAddress>0</baseAddress><registers>
+                   <register><dim>{}</dim><dimIncrement>4</dimIncrement><name>R%s</name></register>
+                 </registers></peripheral>",
+                dim
+            )
+        };
+        let device = parse_peripherals(&array(MAX_DIM)).unwrap();
+        assert_eq!(device.peripherals[0].registers.len(), MAX_DIM as usize);
+        assert!(matches!(
+            parse_peripherals(&array(MAX_DIM + 1)),
+            Err(PlungerError::InvalidSvd(_))
+        ));
+        assert!(parse_peripherals(&array(u32::MAX)).is_err());
+
+        // Each level is within the cap, together they are not
+        let nested = format!(
+            "<peripheral><name>P</name><baseAddress>0</baseAddress><registers>
+               <cluster><dim>{0}</dim><dimIncrement>0</dimIncrement><name>A%s</name>
+                 <cluster><dim>{0}</dim><dimIncrement>0</dimIncrement><name>B%s</name>
+                   <register><dim>{0}</dim><dimIncrement>0</dimIncrement><name>R%s</name></register>
+                 </cluster>
+               </cluster>
+             </registers></peripheral>",
+            MAX_DIM
+        );
+        assert!(matches!(
+            parse_peripherals(&nested),
+            Err(PlungerError::InvalidSvd(_))
+        ));
+    }
+}
@@ -0,0 +1,3 @@
+pub mod device;
+pub mod svd_binding;
+pub mod xml;
@@ -0,0 +1,120 @@
+use napi::{CallContext, JsNumber, JsObject, JsString};
+use serde::{Deserialize, Serialize};
+
+use crate::{common::plunger_error::PlungerError, session::debug_session::SessionTask};
+
+use super::device::{Device, FieldValue};
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SvdOptions {
+    /// Overrides the SVD file given when opening the session
+    svd_path: Option<String>,
+    /// readRegisterField refuses registers that change when read unless this is set
+    #[serde(default)]
+    allow_read_side_effects: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterFieldValue {
+    register: String,
+    address: u32,
+    register_value: u32,
+    #[serde(flatten)]
+    field: FieldValue,
+}
+
+fn svd_options(ctx: &CallContext, index: usize) -> napi::Result<SvdOptions> {
+    match ctx.try_get::<JsObject>(index)? {
+        napi::Either::A(obj) => ctx.env.from_js_value(obj),
+        napi::Either::B(_) => Ok(SvdOptions::default()),
+    }
+}
+
+fn load(options: &SvdOptions) -> Result<Option<Device>, PlungerError> {
+    options.svd_path.as_deref().map(Device::load).transpose()
+}
+
+fn no_svd() -> PlungerError {
+    PlungerError::InvalidOption(
+        "No SVD file, pass svdPath here or when opening the session".to_string(),
+    )
+}
+
+#[js_function(3)]
+pub fn read_peripheral(ctx: CallContext) -> napi::Result<JsObject> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let name = ctx.get::<JsString>(1)?.into_utf8()?.as_str()?.to_string();
+    let options = svd_options(&ctx, 2)?;
+
+    let task = SessionTask::new(id, move |session| {
+        let loaded = load(&options)?;
+        let device = loaded
+            .as_ref()
+            .or(session.svd.as_ref())
+            .ok_or_else(no_svd)?;
+        let peripheral = device.peripheral(&name)?;
+        peripheral.read(&mut session.session.core(0)?)
+    });
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
+
+#[js_function(3)]
+pub fn read_register_field(ctx: CallContext) -> napi::Result<JsObject> {
+    let id = ctx.get::<JsNumber>(0)?.get_uint32()?;
+    let path = ctx.get::<JsString>(1)?.into_utf8()?.as_str()?.to_string();
+    let options = svd_options(&ctx, 2)?;
+
+    // Registers inside clusters have dots in their names, so the register is everything between
+    let (peripheral, register, field) = match (path.find('.'), path.rfind('.')) {
+        (Some(first), Some(last)) if first < last => {
+            (&path[..first], &path[first + 1..last], &path[last + 1..])
+        }
+        _ => {
+            return Err(PlungerError::InvalidOption(format!(
+                "Expected PERIPHERAL.REGISTER.FIELD, got {}",
+                path
+            ))
+            .into())
+        }
+    };
+    let (peripheral, register, field) = (
+        peripheral.to_string(),
+        register.to_string(),
+        field.to_string(),
+    );
+
+    let task = SessionTask::new(id, move |session| {
+        let loaded = load(&options)?;
+        let device = loaded
+            .as_ref()
+            .or(session.svd.as_ref())
+            .ok_or_else(no_svd)?;
+        let peripheral = device.peripheral(&peripheral)?;
+        let register = peripheral.register(&register)?;
+        let field = register.field(&field)?;
+        if !register.readable {
+            return Err(PlungerError::InvalidOption(format!(
+                "{}.{} is write-only",
+                peripheral.name, register.name
+            )));
+        }
+        if register.read_side_effects && !options.allow_read_side_effects {
+            return Err(PlungerError::InvalidOption(format!(
+                "Reading {}.{} has side effects, set allowReadSideEffects to read it anyway",
+                peripheral.name, register.name
+            )));
+        }
+
+        let address = register.address(peripheral.base_address)?;
+        let value = register.read(&mut session.session.core(0)?, peripheral.base_address)?;
+        Ok(RegisterFieldValue {
+            register: register.name.clone(),
+            address,
+            register_value: value,
+            field: field.decode(value),
+        })
+    });
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}
@@ -0,0 +1,294 @@
+use crate::common::plunger_error::PlungerError;
+
+/// Just enough XML for CMSIS-SVD files: elements, attributes, text and the predefined
+/// entities. Comments, processing instructions, DOCTYPE and CDATA are handled, namespaces are not.
+#[derive(Debug, Clone, Default)]
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Element>,
+    pub text: String,
+}
+
+impl Element {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    /// Trimmed text of a child element, None if it is missing or empty
+    pub fn child_text(&self, name: &str) -> Option<&str> {
+        self.child(name)
+            .map(|child| child.text.trim())
+            .filter(|text| !text.is_empty())
+    }
+}
+
+// SVD files nest a dozen levels deep, clusters in clusters add a few more. Elements recurse,
+// so anything deeper is refused before it can run the stack out.
+const MAX_DEPTH: usize = 64;
+
+fn invalid(message: &str, position: usize) -> PlungerError {
+    PlungerError::InvalidSvd(format!("{} at byte {}", message, position))
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn skip_past(&mut self, end: &str) -> Result<(), PlungerError> {
+        match self.rest().find(end) {
+            Some(offset) => {
+                self.position += offset + end.len();
+                Ok(())
+            }
+            None => Err(invalid(&format!("Missing {}", end), self.position)),
+        }
+    }
+
+    // Comments, processing instructions and DOCTYPE, anywhere markup may appear
+    fn skip_misc(&mut self) -> Result<bool, PlungerError> {
+        let rest = self.rest();
+        if rest.starts_with("<!--") {
+            self.skip_past("-->")?;
+        } else if rest.starts_with("<?") {
+            self.skip_past("?>")?;
+        } else if rest.starts_with("<!DOCTYPE") {
+            self.skip_past(">")?;
+        } else {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    fn name(&mut self) -> Result<String, PlungerError> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| c.is_whitespace() || c == '>' || c == '/' || c == '=')
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(invalid("Expected a name", self.position));
+        }
+
+        self.position += len;
+        Ok(rest[..len].to_string())
+    }
+
+    fn element(&mut self, depth: usize) -> Result<Element, PlungerError> {
+        if !self.rest().starts_with('<') {
+            return Err(invalid("Expected an element", self.position));
+        }
+        if depth >= MAX_DEPTH {
+            return Err(invalid(
+                &format!("Elements nested more than {} deep", MAX_DEPTH),
+                self.position,
+            ));
+        }
+        self.position += 1;
+
+        let mut element = Element {
+            name: self.name()?,
+            ..Element::default()
+        };
+
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.starts_with("/>") {
+                self.position += 2;
+                return Ok(element);
+            }
+            if rest.starts_with('>') {
+                self.position += 1;
+                break;
+            }
+
+            let key = self.name()?;
+            self.skip_whitespace();
+            if !self.rest().starts_with('=') {
+                return Err(invalid("Expected = after attribute name", self.position));
+            }
+            self.position += 1;
+            self.skip_whitespace();
+
+            let quote = match self.rest().chars().next() {
+                Some(quote @ ('"' | '\'')) => quote,
+                _ => return Err(invalid("Expected a quoted attribute value", self.position)),
+            };
+            self.position += 1;
+            let len = self
+                .rest()
+                .find(quote)
+                .ok_or_else(|| invalid("Unterminated attribute value", self.position))?;
+            let value = unescape(&self.rest()[..len]);
+            self.position += len + 1;
+
+            element.attributes.push((key, value));
+        }
+
+        loop {
+            let rest = self.rest();
+            if rest.is_empty() {
+                return Err(invalid(
+                    &format!("Unterminated element {}", element.name),
+                    self.position,
+                ));
+            }
+
+            if rest.starts_with("</") {
+                self.position += 2;
+                let name = self.name()?;
+                if name != element.name {
+                    return Err(invalid(
+                        &format!("Expected </{}>, found </{}>", element.name, name),
+                        self.position,
+                    ));
+                }
+                self.skip_past(">")?;
+                return Ok(element);
+            }
+
+            if rest.starts_with("<![CDATA[") {
+                self.position += "<![CDATA[".len();
+                let len = self
+                    .rest()
+                    .find("]]>")
+                    .ok_or_else(|| invalid("Unterminated CDATA", self.position))?;
+                element.text.push_str(&self.rest()[..len]);
+                self.position += len + 3;
+            } else if self.skip_misc()? {
+                continue;
+            } else if rest.starts_with('<') {
+                element.children.push(self.element(depth + 1)?);
+            } else {
+                let len = rest.find('<').unwrap_or(rest.len());
+                element.text.push_str(&unescape(&rest[..len]));
+                self.position += len;
+            }
+        }
+    }
+}
+
+fn unescape(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = match rest.find(';') {
+            Some(end) => end,
+            None => break,
+        };
+        let decoded = match &rest[1..end] {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            entity => match entity.strip_prefix('#') {
+                Some(hex) if hex.starts_with('x') => u32::from_str_radix(&hex[1..], 16).ok(),
+                Some(decimal) => decimal.parse().ok(),
+                None => None,
+            }
+            .and_then(char::from_u32),
+        };
+
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            // Not an entity we know, keep it as it is
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
+
+/// Parses a document and returns its root element
+pub fn parse(input: &str) -> Result<Element, PlungerError> {
+    let mut parser = Parser {
+        input: input.trim_start_matches('\u{feff}'),
+        position: 0,
+    };
+
+    loop {
+        parser.skip_whitespace();
+        if !parser.skip_misc()? {
+            break;
+        }
+    }
+
+    parser.element(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_attributes_text_and_entities() {
+        let root = parse(
+            "\u{feff}<?xml version=\"1.0\"?>\n<!-- header -->\n<!DOCTYPE device>\n<device schemaVersion='1.1'>\n  <name>A &amp; B &#x41;&#65; &bogus;</name>\n  <!-- <name>ignored</name> -->\n  <description><![CDATA[x < y]]></description>\n  <empty/>\n</device>",
+        )
+        .unwrap();
+
+        assert_eq!(root.name, "device");
+        assert_eq!(root.attribute("schemaVersion"), Some("1.1"));
+        assert_eq!(root.child_text("name"), Some("A & B AA &bogus;"));
+        assert_eq!(root.children_named("name").count(), 1);
+        assert_eq!(root.child_text("description"), Some("x < y"));
+        assert_eq!(root.child_text("empty"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_documents() {
+        assert!(parse("<device><name>A</device>").is_err());
+        assert!(parse("<device>").is_err());
+        assert!(parse("<device name=unquoted/>").is_err());
+        assert!(parse("<device><![CDATA[open</device>").is_err());
+    }
+
+    #[test]
+    fn limits_nesting_depth() {
+        let nested = |depth: usize| format!("{}{}", "<a>".repeat(depth), "</a>".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(matches!(
+            parse(&nested(MAX_DEPTH + 1)),
+            Err(PlungerError::InvalidSvd(_))
+        ));
+        // Deep enough to overflow the stack without the limit
+        assert!(parse(&"<a>".repeat(1_000_000)).is_err());
+    }
+}
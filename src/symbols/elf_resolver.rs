@@ -0,0 +1,308 @@
+use gimli::{EndianSlice, RunTimeEndian, SectionId};
+use object::{Object, ObjectSection, ObjectSymbol, SymbolKind};
+use serde::{Deserialize, Serialize};
+
+use crate::common::plunger_error::PlungerError;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SymbolType {
+    Function,
+    Object,
+    /// Linker symbols and labels without a type
+    Other,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: u32,
+    pub column: Option<u32>,
+}
+
+impl std::fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Symbol {
+    pub name: String,
+    /// Thumb bit cleared for functions
+    pub address: u32,
+    pub size: u32,
+    pub kind: SymbolType,
+    pub section: Option<String>,
+}
+
+impl Symbol {
+    pub fn contains(&self, address: u32) -> bool {
+        address >= self.address && (address - self.address) < self.size.max(1)
+    }
+}
+
+/// What `lookupSymbol` found for a name or an address
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SymbolInfo {
+    #[serde(flatten)]
+    pub symbol: Symbol,
+    /// How far into the symbol a looked up address is, zero for name lookups
+    pub offset: u32,
+    pub location: Option<SourceLocation>,
+}
+
+/// An address, or a symbol to look up in the firmware's ELF file
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Location {
+    Address(u32),
+    Symbol(String),
+}
+
+/// Symbols and DWARF line info of one ELF file, read once and parsed on demand
+#[derive(Debug)]
+pub struct ElfResolver {
+    path: String,
+    content: Vec<u8>,
+}
+
+impl ElfResolver {
+    pub fn load(path: &str) -> Result<ElfResolver, PlungerError> {
+        let content = std::fs::read(path).map_err(|err| {
+            PlungerError::InvalidOption(format!("Cannot read ELF file {}: {}", path, err))
+        })?;
+
+        let resolver = ElfResolver {
+            path: path.to_string(),
+            content,
+        };
+        resolver.file()?;
+        Ok(resolver)
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    fn file(&self) -> Result<object::File<'_>, PlungerError> {
+        object::File::parse(&*self.content).map_err(|err| self.invalid(err))
+    }
+
+    fn invalid<T: std::fmt::Display>(&self, err: T) -> PlungerError {
+        PlungerError::InvalidFirmware(format!("{}: {}", self.path, err))
+    }
+
+    pub fn symbols(&self) -> Result<Vec<Symbol>, PlungerError> {
+        let file = self.file()?;
+
+        let mut symbols = Vec::new();
+        for symbol in file.symbols() {
+            let kind = match symbol.kind() {
+                SymbolKind::Text => SymbolType::Function,
+                SymbolKind::Data | SymbolKind::Tls => SymbolType::Object,
+                SymbolKind::Unknown | SymbolKind::Label => SymbolType::Other,
+                _ => continue,
+            };
+
+            // ARM mapping symbols ($t, $d, ...) mark code and data, they are not names
+            let name = match symbol.name() {
+                Ok(name) if !name.is_empty() && !name.starts_with('$') => name,
+                _ => continue,
+            };
+
+            let section = symbol
+                .section_index()
+                .and_then(|index| file.section_by_index(index).ok())
+                .and_then(|section| section.name().ok().map(|name| name.to_string()));
+
+            let address = symbol.address() as u32;
+            symbols.push(Symbol {
+                name: name.to_string(),
+                address: match kind {
+                    SymbolType::Function => address & !1,
+                    _ => address,
+                },
+                size: symbol.size() as u32,
+                kind,
+                section,
+            });
+        }
+
+        Ok(symbols)
+    }
+
+    pub fn find(&self, name: &str) -> Result<Option<Symbol>, PlungerError> {
+        Ok(self
+            .symbols()?
+            .into_iter()
+            .find(|symbol| symbol.name == name))
+    }
+
+    /// The function or object `address` falls in, if any
+    pub fn find_at(&self, address: u32) -> Result<Option<Symbol>, PlungerError> {
+        // Sized functions win over linker symbols that happen to share the address
+        Ok(self
+            .symbols()?
+            .into_iter()
+            .filter(|symbol| symbol.contains(address))
+            .max_by_key(|symbol| (symbol.size > 0, symbol.kind == SymbolType::Function)))
+    }
+
+    pub fn address_of(&self, name: &str) -> Result<u32, PlungerError> {
+        self.find(name)?
+            .map(|symbol| symbol.address)
+            .ok_or_else(|| {
+                PlungerError::InvalidOption(format!("Symbol {} not found in {}", name, self.path))
+            })
+    }
+
+    /// Source file and line `address` was compiled from, None without debug info
+    pub fn source_location(&self, address: u32) -> Result<Option<SourceLocation>, PlungerError> {
+        let file = self.file()?;
+        let endian = if file.is_little_endian() {
+            RunTimeEndian::Little
+        } else {
+            RunTimeEndian::Big
+        };
+
+        let load = |id: SectionId| -> Result<EndianSlice<RunTimeEndian>, gimli::Error> {
+            let data = file
+                .section_by_name(id.name())
+                .and_then(|section| section.data().ok())
+                .unwrap_or(&[]);
+            Ok(EndianSlice::new(data, endian))
+        };
+        let dwarf = gimli::Dwarf::load(load).map_err(|err| self.invalid(err))?;
+
+        let address = address as u64;
+        let mut units = dwarf.units();
+        while let Some(header) = units.next().map_err(|err| self.invalid(err))? {
+            let unit = dwarf.unit(header).map_err(|err| self.invalid(err))?;
+            let program = match unit.line_program.clone() {
+                Some(program) => program,
+                None => continue,
+            };
+
+            // The row covering an address is the last one at or below it in its sequence
+            let mut rows = program.rows();
+            let mut previous = None;
+            while let Some((header, row)) = rows.next_row().map_err(|err| self.invalid(err))? {
+                if let Some((start, file_index, line, column)) = previous {
+                    if start <= address && address < row.address() {
+                        let file = header
+                            .file(file_index)
+                            .map(|entry| self.file_name(&dwarf, &unit, header, entry))
+                            .transpose()?
+                            .unwrap_or_default();
+
+                        return Ok(Some(SourceLocation { file, line, column }));
+                    }
+                }
+
+                previous = match (row.end_sequence(), row.line()) {
+                    (false, Some(line)) => Some((
+                        row.address(),
+                        row.file_index(),
+                        line.get() as u32,
+                        match row.column() {
+                            gimli::ColumnType::Column(column) => Some(column.get() as u32),
+                            gimli::ColumnType::LeftEdge => None,
+                        },
+                    )),
+                    _ => None,
+                };
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn file_name(
+        &self,
+        dwarf: &gimli::Dwarf<EndianSlice<RunTimeEndian>>,
+        unit: &gimli::Unit<EndianSlice<RunTimeEndian>>,
+        header: &gimli::LineProgramHeader<EndianSlice<RunTimeEndian>>,
+        entry: &gimli::FileEntry<EndianSlice<RunTimeEndian>>,
+    ) -> Result<String, PlungerError> {
+        let attr_string = |attr| -> Result<String, PlungerError> {
+            let value = dwarf
+                .attr_string(unit, attr)
+                .map_err(|err| self.invalid(err))?;
+            Ok(value.to_string_lossy().to_string())
+        };
+
+        let name = attr_string(entry.path_name())?;
+        if name.starts_with('/') {
+            return Ok(name);
+        }
+
+        let directory = match entry.directory(header) {
+            Some(directory) => attr_string(directory)?,
+            None => String::new(),
+        };
+        let directory = match (directory.starts_with('/'), &unit.comp_dir) {
+            (false, Some(comp_dir)) => {
+                let comp_dir = comp_dir.to_string_lossy().to_string();
+                match directory.is_empty() {
+                    true => comp_dir,
+                    false => format!("{}/{}", comp_dir, directory),
+                }
+            }
+            _ => directory,
+        };
+
+        Ok(match directory.is_empty() {
+            true => name,
+            false => format!("{}/{}", directory, name),
+        })
+    }
+
+    pub fn lookup(&self, location: &Location) -> Result<Option<SymbolInfo>, PlungerError> {
+        let (symbol, address) = match location {
+            Location::Symbol(name) => match self.find(name)? {
+                Some(symbol) => {
+                    let address = symbol.address;
+                    (symbol, address)
+                }
+                None => return Ok(None),
+            },
+            Location::Address(address) => match self.find_at(*address & !1)? {
+                Some(symbol) => (symbol, *address & !1),
+                None => return Ok(None),
+            },
+        };
+
+        Ok(Some(SymbolInfo {
+            offset: address - symbol.address,
+            location: self.source_location(address)?,
+            symbol,
+        }))
+    }
+}
+
+impl Location {
+    /// Symbols come from `elf_path` when given, otherwise from the session's ELF file
+    pub fn resolve(
+        &self,
+        elf_path: Option<&str>,
+        session_symbols: Option<&ElfResolver>,
+    ) -> Result<u32, PlungerError> {
+        let name = match self {
+            Location::Address(address) => return Ok(*address),
+            Location::Symbol(name) => name,
+        };
+
+        match (elf_path, session_symbols) {
+            (Some(path), _) => ElfResolver::load(path)?.address_of(name),
+            (None, Some(symbols)) => symbols.address_of(name),
+            (None, None) => Err(PlungerError::InvalidOption(format!(
+                "Resolving {} needs an elfPath, here or when opening the session",
+                name
+            ))),
+        }
+    }
+}
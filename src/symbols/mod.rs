@@ -0,0 +1,2 @@
+pub mod elf_resolver;
+pub mod symbol_binding;
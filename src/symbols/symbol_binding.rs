@@ -0,0 +1,35 @@
+use napi::{CallContext, JsObject, JsString, JsUnknown, Task};
+
+use super::elf_resolver::{ElfResolver, Location, SymbolInfo};
+
+pub struct LookupSymbolTask {
+    elf_path: String,
+    location: Location,
+}
+
+impl Task for LookupSymbolTask {
+    type Output = Option<SymbolInfo>;
+    type JsValue = JsUnknown;
+
+    fn compute(&mut self) -> napi::Result<Self::Output> {
+        let resolver = ElfResolver::load(&self.elf_path)?;
+        Ok(resolver.lookup(&self.location)?)
+    }
+
+    fn resolve(self, env: napi::Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+        env.to_js_value(&output)
+    }
+
+    fn reject(self, _env: napi::Env, err: napi::Error) -> napi::Result<Self::JsValue> {
+        Err(err)
+    }
+}
+
+#[js_function(2)]
+pub fn lookup_symbol(ctx: CallContext) -> napi::Result<JsObject> {
+    let elf_path = ctx.get::<JsString>(0)?.into_utf8()?.as_str()?.to_string();
+    let location: Location = ctx.env.from_js_value(ctx.get::<JsUnknown>(1)?)?;
+
+    let task = LookupSymbolTask { elf_path, location };
+    ctx.env.spawn(task).map(|t| t.promise_object())
+}